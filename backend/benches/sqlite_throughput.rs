@@ -0,0 +1,155 @@
+//! Rendimiento concurrente de SQLite antes y después de ajustar la conexión.
+//!
+//! `antes`: lo que usaba el servidor (journal `delete`, `synchronous = full`).
+//! `despues`: `DbOptions::default()` (WAL + `synchronous = normal`).
+//!
+//! ```bash
+//! cargo bench --bench sqlite_throughput
+//! ```
+//!
+//! Referencia (1 CPU, mediana): `login` 34 → 42 sesiones/s (domina Argon2);
+//! `list` con escrituras concurrentes 424 → 2204 peticiones/s.
+use backend::{
+    core::{
+        clock::SystemClock,
+        models::user::UserSearch,
+        repository::DynUserRepository,
+        services::{
+            auth::{hash_password, AuthService, JwtKeys},
+            user::UserService,
+        },
+    },
+    data::db::{DbOptions, DbPool},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+
+const USERS: usize = 50;
+const PASSWORD: &str = "benchmark1";
+/// Sesiones simultáneas por iteración (login + logout)
+const LOGINS: usize = 16;
+/// Peticiones simultáneas por iteración; una de cada cuatro escribe
+const LISTS: usize = 64;
+
+fn configs() -> [(&'static str, DbOptions); 2] {
+    let before = DbOptions {
+        sqlite_journal_mode: "delete".into(),
+        sqlite_synchronous: "full".into(),
+        ..DbOptions::default()
+    };
+    [("antes", before), ("despues", DbOptions::default())]
+}
+
+/// Base en archivo (WAL no aplica en memoria) con `USERS` usuarios.
+async fn setup(dir: &tempfile::TempDir, options: &DbOptions) -> DynUserRepository {
+    let url = format!("sqlite://{}", dir.path().join("bench.db").display());
+    let db = DbPool::connect_with(&url, options).await.unwrap();
+    db.migrate().await.unwrap();
+    let users = db.repository();
+    let hash = hash_password(PASSWORD).unwrap();
+    for i in 0..USERS {
+        users
+            .create_user(&format!("user{}", i), &hash)
+            .await
+            .unwrap();
+    }
+    users
+}
+
+fn login(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("login");
+    group.throughput(Throughput::Elements(LOGINS as u64));
+    for (name, options) in configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let users = rt.block_on(setup(&dir, &options));
+        let auth = Arc::new(AuthService::new(
+            users,
+            JwtKeys::from_secret(b"bench"),
+            Arc::new(SystemClock),
+        ));
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.to_async(&rt).iter(|| {
+                let auth = auth.clone();
+                async move {
+                    let tasks: Vec<_> = (0..LOGINS)
+                        .map(|i| {
+                            let auth = auth.clone();
+                            tokio::spawn(async move {
+                                let username = format!("user{}", i % USERS);
+                                let session = auth.login(&username, PASSWORD).await.unwrap();
+                                auth.logout(&session.token).await.unwrap();
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn list(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("list");
+    group.throughput(Throughput::Elements(LISTS as u64));
+    for (name, options) in configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let users = rt.block_on(setup(&dir, &options));
+        let next_jti = Arc::new(AtomicU64::new(0));
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.to_async(&rt).iter(|| {
+                let users = users.clone();
+                let next_jti = next_jti.clone();
+                async move {
+                    let tasks: Vec<_> = (0..LISTS)
+                        .map(|i| {
+                            let users = users.clone();
+                            let next_jti = next_jti.clone();
+                            tokio::spawn(async move {
+                                if i % 4 == 0 {
+                                    // Escritura concurrente (como un logout)
+                                    let jti = next_jti.fetch_add(1, Ordering::Relaxed).to_string();
+                                    users.revoke_token(&jti, i64::MAX).await.unwrap();
+                                } else {
+                                    UserService::new(users)
+                                        .search(UserSearch {
+                                            q: Some("user1".into()),
+                                            page: 1,
+                                            limit: 20,
+                                            ..Default::default()
+                                        })
+                                        .await
+                                        .unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(10));
+    targets = login, list
+}
+criterion_main!(benches);
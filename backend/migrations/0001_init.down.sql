@@ -0,0 +1,2 @@
+-- Revierte 0001_init: elimina la tabla de usuarios
+DROP TABLE IF EXISTS users;
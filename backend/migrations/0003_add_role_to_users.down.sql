@@ -0,0 +1,2 @@
+-- Revierte 0003_add_role_to_users
+ALTER TABLE users DROP COLUMN role;
@@ -0,0 +1,2 @@
+-- Revierte 0004_create_audit_logs (se pierde la auditoría no archivada)
+DROP TABLE IF EXISTS audit_logs;
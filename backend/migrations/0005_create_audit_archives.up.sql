@@ -0,0 +1,11 @@
+-- Registro de archivos generados por la política de retención de auditoría
+CREATE TABLE audit_archives (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_name TEXT NOT NULL UNIQUE, -- Nombre del .ndjson.gz dentro del directorio de archivo
+    entries INTEGER NOT NULL,       -- Cantidad de registros archivados
+    first_log_id INTEGER NOT NULL,  -- Primer id de audit_logs incluido
+    last_log_id INTEGER NOT NULL,   -- Último id de audit_logs incluido
+    last_hash TEXT NOT NULL,        -- Hash final de la cadena (enlaza con el siguiente archivo)
+    sha256 TEXT NOT NULL,           -- Checksum del archivo comprimido
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
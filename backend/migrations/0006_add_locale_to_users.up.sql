@@ -0,0 +1,2 @@
+-- Idioma preferido del usuario ('es', 'en'). NULL = negociar con Accept-Language
+ALTER TABLE users ADD COLUMN locale TEXT;
@@ -0,0 +1,2 @@
+-- Revierte 0007_create_revoked_tokens (las sesiones cerradas vuelven a ser válidas hasta expirar)
+DROP TABLE IF EXISTS revoked_tokens;
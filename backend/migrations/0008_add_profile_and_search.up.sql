@@ -0,0 +1,34 @@
+-- Datos de perfil opcionales (PUT /api/v1/me/profile)
+ALTER TABLE users ADD COLUMN display_name TEXT;
+ALTER TABLE users ADD COLUMN email TEXT;
+
+-- Índice de texto completo sobre `users` (contenido externo: solo guarda el índice)
+CREATE VIRTUAL TABLE users_fts USING fts5(
+    username,
+    display_name,
+    email,
+    content = 'users',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- Los triggers mantienen el índice al día con cada cambio en `users`
+CREATE TRIGGER users_fts_insert AFTER INSERT ON users BEGIN
+    INSERT INTO users_fts (rowid, username, display_name, email)
+    VALUES (new.id, new.username, new.display_name, new.email);
+END;
+
+CREATE TRIGGER users_fts_delete AFTER DELETE ON users BEGIN
+    INSERT INTO users_fts (users_fts, rowid, username, display_name, email)
+    VALUES ('delete', old.id, old.username, old.display_name, old.email);
+END;
+
+CREATE TRIGGER users_fts_update AFTER UPDATE OF username, display_name, email ON users BEGIN
+    INSERT INTO users_fts (users_fts, rowid, username, display_name, email)
+    VALUES ('delete', old.id, old.username, old.display_name, old.email);
+    INSERT INTO users_fts (rowid, username, display_name, email)
+    VALUES (new.id, new.username, new.display_name, new.email);
+END;
+
+-- Indexar los usuarios existentes
+INSERT INTO users_fts (users_fts) VALUES ('rebuild');
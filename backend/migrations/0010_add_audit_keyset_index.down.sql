@@ -0,0 +1,2 @@
+-- Revierte 0010_add_audit_keyset_index
+DROP INDEX IF EXISTS idx_audit_logs_timestamp_id;
@@ -0,0 +1,2 @@
+-- Paginación por cursor de la bitácora: (timestamp, id) descendente sin ordenar en memoria
+CREATE INDEX idx_audit_logs_timestamp_id ON audit_logs (timestamp, id);
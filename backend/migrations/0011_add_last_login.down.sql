@@ -0,0 +1,2 @@
+-- Revierte 0011_add_last_login
+ALTER TABLE users DROP COLUMN last_login_at;
@@ -0,0 +1,2 @@
+-- Revierte 0012_create_erasure_requests
+DROP TABLE IF EXISTS erasure_requests;
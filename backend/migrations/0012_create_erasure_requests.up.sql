@@ -0,0 +1,13 @@
+-- Solicitudes de borrado (derecho de supresión): se ejecutan pasado el periodo de gracia
+CREATE TABLE erasure_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,          -- Sin FK: la fila sobrevive al usuario borrado
+    pseudonym TEXT NOT NULL UNIQUE,    -- Sustituye al username en la auditoría
+    status TEXT NOT NULL DEFAULT 'pending', -- pending | cancelled | completed
+    requested_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    scheduled_for DATETIME NOT NULL,
+    resolved_at DATETIME               -- Cancelación o ejecución
+);
+-- Como mucho una pendiente por usuario
+CREATE UNIQUE INDEX idx_erasure_requests_pending ON erasure_requests (user_id) WHERE status = 'pending';
+CREATE INDEX idx_erasure_requests_due ON erasure_requests (status, scheduled_for);
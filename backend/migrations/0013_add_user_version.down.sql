@@ -0,0 +1,2 @@
+-- Revierte 0013_add_user_version
+ALTER TABLE users DROP COLUMN version;
@@ -0,0 +1,2 @@
+-- Concurrencia optimista: cada escritura en la fila incrementa la versión (ETag / If-Match)
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+-- Respuestas guardadas de los POST con `Idempotency-Key`, para repetirlas en los reintentos
+CREATE TABLE idempotency_keys (
+    key TEXT NOT NULL,
+    scope TEXT NOT NULL,               -- Método, ruta y usuario de la sesión (`POST /api/v1/users:batch root`)
+    fingerprint TEXT NOT NULL,         -- SHA-256 de la petición original
+    status INTEGER,                    -- NULL mientras la primera petición está en curso
+    headers TEXT,                      -- Cabeceras de la respuesta, una por línea (`nombre: valor`)
+    body BLOB,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL,
+    PRIMARY KEY (key, scope)
+);
+CREATE INDEX idx_idempotency_keys_expires ON idempotency_keys (expires_at);
@@ -0,0 +1,12 @@
+-- Accesos rechazados por los guards (401/403), con el motivo de `AuthFailure`
+CREATE TABLE security_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    reason TEXT NOT NULL,   -- Código del rechazo (ej: "token_expired")
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,     -- Sin la query string
+    timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_security_events_timestamp_id ON security_events (timestamp, id);
+
+-- La retención también archiva estos eventos, en su propia cadena de hashes
+ALTER TABLE audit_archives ADD COLUMN kind TEXT NOT NULL DEFAULT 'audit_logs'; -- audit_logs | security_events
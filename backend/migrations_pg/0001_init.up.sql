@@ -0,0 +1,8 @@
+-- Equivalente PostgreSQL de migrations/0001_init.sql
+-- Las fechas se guardan como TEXT con el formato de CURRENT_TIMESTAMP de SQLite
+-- para que ambos backends compartan modelos y comparaciones por cadena
+CREATE TABLE IF NOT EXISTS users (
+    id BIGSERIAL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    created_at TEXT DEFAULT to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
@@ -0,0 +1,2 @@
+-- Revierte 0002_add_password_hash
+ALTER TABLE users DROP COLUMN password_hash;
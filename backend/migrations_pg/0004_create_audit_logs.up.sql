@@ -0,0 +1,8 @@
+-- Tabla de Auditoría para registrar acciones administrativas
+CREATE TABLE audit_logs (
+    id BIGSERIAL PRIMARY KEY,
+    admin_username TEXT NOT NULL, -- Quién ejecutó la acción
+    action TEXT NOT NULL,         -- Qué hizo (ej: "DELETE_USER")
+    target TEXT NOT NULL,         -- A quién afectó (ej: "usuario_borrado")
+    timestamp TEXT DEFAULT to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
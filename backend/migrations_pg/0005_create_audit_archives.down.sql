@@ -0,0 +1,2 @@
+-- Revierte 0005_create_audit_archives (los .ndjson.gz del disco no se tocan)
+DROP TABLE IF EXISTS audit_archives;
@@ -0,0 +1,11 @@
+-- Registro de archivos generados por la política de retención de auditoría
+CREATE TABLE audit_archives (
+    id BIGSERIAL PRIMARY KEY,
+    file_name TEXT NOT NULL UNIQUE, -- Nombre del .ndjson.gz dentro del directorio de archivo
+    entries BIGINT NOT NULL,        -- Cantidad de registros archivados
+    first_log_id BIGINT NOT NULL,   -- Primer id de audit_logs incluido
+    last_log_id BIGINT NOT NULL,    -- Último id de audit_logs incluido
+    last_hash TEXT NOT NULL,        -- Hash final de la cadena (enlaza con el siguiente archivo)
+    sha256 TEXT NOT NULL,           -- Checksum del archivo comprimido
+    created_at TEXT DEFAULT to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
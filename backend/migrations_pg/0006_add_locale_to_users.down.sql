@@ -0,0 +1,2 @@
+-- Revierte 0006_add_locale_to_users
+ALTER TABLE users DROP COLUMN locale;
@@ -0,0 +1,5 @@
+-- Sesiones cerradas: el guard rechaza los tokens cuyo jti aparece aquí
+CREATE TABLE revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    expires_at BIGINT NOT NULL -- exp del token (epoch); pasado ese momento se puede purgar
+);
@@ -0,0 +1,19 @@
+-- Las fechas pasan de TEXT a TIMESTAMPTZ: los modelos usan DateTime<Utc>
+-- y el corte de retención se compara como fecha, no como texto
+ALTER TABLE users
+    ALTER COLUMN created_at DROP DEFAULT,
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::TIMESTAMP AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at SET DEFAULT now(),
+    ALTER COLUMN created_at SET NOT NULL;
+
+ALTER TABLE audit_logs
+    ALTER COLUMN timestamp DROP DEFAULT,
+    ALTER COLUMN timestamp TYPE TIMESTAMPTZ USING timestamp::TIMESTAMP AT TIME ZONE 'UTC',
+    ALTER COLUMN timestamp SET DEFAULT now(),
+    ALTER COLUMN timestamp SET NOT NULL;
+
+ALTER TABLE audit_archives
+    ALTER COLUMN created_at DROP DEFAULT,
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::TIMESTAMP AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at SET DEFAULT now(),
+    ALTER COLUMN created_at SET NOT NULL;
@@ -0,0 +1,4 @@
+-- Revierte 0009_add_profile
+ALTER TABLE users
+    DROP COLUMN email,
+    DROP COLUMN display_name;
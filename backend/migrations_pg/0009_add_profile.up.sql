@@ -0,0 +1,4 @@
+-- Datos de perfil opcionales (PUT /api/v1/me/profile). Sin FTS5: la búsqueda usa ILIKE
+ALTER TABLE users
+    ADD COLUMN display_name TEXT,
+    ADD COLUMN email TEXT;
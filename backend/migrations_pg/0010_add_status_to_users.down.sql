@@ -0,0 +1,3 @@
+-- Revierte 0010_add_status_to_users
+DROP INDEX IF EXISTS idx_users_created_at;
+ALTER TABLE users DROP COLUMN status;
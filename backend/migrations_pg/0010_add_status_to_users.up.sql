@@ -0,0 +1,3 @@
+-- Estado de la cuenta ('active', 'suspended') y un índice para filtrar y ordenar por alta
+ALTER TABLE users ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+CREATE INDEX idx_users_created_at ON users (created_at);
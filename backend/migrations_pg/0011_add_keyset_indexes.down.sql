@@ -0,0 +1,4 @@
+-- Revierte 0011_add_keyset_indexes
+DROP INDEX IF EXISTS idx_audit_logs_timestamp_id;
+DROP INDEX IF EXISTS idx_users_created_at_id;
+CREATE INDEX idx_users_created_at ON users (created_at);
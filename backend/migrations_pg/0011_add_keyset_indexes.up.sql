@@ -0,0 +1,4 @@
+-- Paginación por cursor: (created_at, id) en usuarios y (timestamp, id) en la bitácora
+DROP INDEX IF EXISTS idx_users_created_at;
+CREATE INDEX idx_users_created_at_id ON users (created_at, id);
+CREATE INDEX idx_audit_logs_timestamp_id ON audit_logs (timestamp, id);
@@ -0,0 +1,2 @@
+-- Último inicio de sesión correcto (expansión `include=last_login` del listado)
+ALTER TABLE users ADD COLUMN last_login_at TIMESTAMPTZ;
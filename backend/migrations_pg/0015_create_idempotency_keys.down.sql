@@ -0,0 +1,2 @@
+-- Revierte 0015_create_idempotency_keys
+DROP TABLE IF EXISTS idempotency_keys;
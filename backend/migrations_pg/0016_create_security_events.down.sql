@@ -0,0 +1,3 @@
+-- Revierte 0016_create_security_events (los .ndjson.gz del disco no se tocan)
+ALTER TABLE audit_archives DROP COLUMN IF EXISTS kind;
+DROP TABLE IF EXISTS security_events;
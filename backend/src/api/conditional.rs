@@ -0,0 +1,125 @@
+//! Peticiones condicionales (RFC 9110) sobre un recurso versionado. El `ETag`
+//! es `"<versión>-<huella>"`: la versión es lo que comprueba `If-Match` en las
+//! escrituras, y la huella (del cuerpo enviado) distingue las representaciones
+//! de una misma versión para `If-None-Match`: otros `fields`/`include`, la
+//! vista de admin o campos que no cambian la versión (idioma, último login).
+use crate::core::models::user::IfMatch;
+use crate::error::AppError;
+use axum::http::{header, HeaderMap, HeaderValue};
+use sha2::{Digest, Sha256};
+
+/// `ETag` fuerte de una representación (`body`) de la versión `version`.
+pub fn etag(version: i64, body: &[u8]) -> HeaderValue {
+    let digest = hex::encode(Sha256::digest(body));
+    HeaderValue::from_str(&format!("\"{}-{}\"", version, &digest[..16]))
+        .expect("número y hex son un valor válido")
+}
+
+/// Etiquetas de una lista (`"1-ab", W/"2-cd"`) sin comillas. Las débiles solo
+/// se aceptan si `weak`.
+fn tags(raw: &str, weak: bool) -> impl Iterator<Item = &str> {
+    raw.split(',')
+        .map(str::trim)
+        .filter_map(move |tag| match tag.strip_prefix("W/") {
+            Some(tag) if weak => Some(tag),
+            Some(_) => None,
+            None => Some(tag),
+        })
+        .filter_map(|tag| tag.strip_prefix('"')?.strip_suffix('"'))
+}
+
+/// Versiones de una lista de etiquetas (la parte antes del `-`; también se
+/// admite la versión sola). Lo que no es una versión nuestra se ignora
+/// (nunca coincide).
+fn versions(raw: &str) -> Vec<i64> {
+    tags(raw, false)
+        .filter_map(|tag| {
+            tag.split_once('-')
+                .map_or(tag, |(version, _)| version)
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Lee `If-Match` (comparación fuerte). Sin la cabecera, `None`, o
+/// `PreconditionRequired` si `required`.
+pub fn if_match(headers: &HeaderMap, required: bool) -> Result<Option<IfMatch>, AppError> {
+    let Some(raw) = headers.get(header::IF_MATCH) else {
+        if required {
+            return Err(AppError::PreconditionRequired(
+                "precondition.if_match_required".to_string(),
+            ));
+        }
+        return Ok(None);
+    };
+    let raw = raw.to_str().unwrap_or_default().trim();
+    if raw == "*" {
+        return Ok(Some(IfMatch::Any));
+    }
+    Ok(Some(IfMatch::Versions(versions(raw))))
+}
+
+/// `true` si `If-None-Match` incluye el `ETag` actual (comparación débil):
+/// el cliente ya tiene la representación y basta un 304.
+pub fn not_modified(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let current = etag.to_str().unwrap_or_default().trim_matches('"');
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|raw| raw.to_str().ok())
+        .is_some_and(|raw| raw.trim() == "*" || tags(raw, true).any(|tag| tag == current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_if_match_lists_and_wildcard() {
+        let raw = "\"2-00ff\", W/\"3-00ff\", \"4\", \"x\"";
+        let parsed = if_match(&headers(header::IF_MATCH, raw), false);
+        assert_eq!(parsed.unwrap(), Some(IfMatch::Versions(vec![2, 4])));
+        let parsed = if_match(&headers(header::IF_MATCH, "*"), true);
+        assert_eq!(parsed.unwrap(), Some(IfMatch::Any));
+        assert_eq!(if_match(&HeaderMap::new(), false).unwrap(), None);
+        assert!(matches!(
+            if_match(&HeaderMap::new(), true),
+            Err(AppError::PreconditionRequired(_))
+        ));
+    }
+
+    #[test]
+    fn etag_depends_on_version_and_representation() {
+        let tag = etag(4, b"{\"id\":1}");
+        assert!(tag.to_str().unwrap().starts_with("\"4-"));
+        assert_ne!(tag, etag(5, b"{\"id\":1}"));
+        assert_ne!(tag, etag(4, b"{\"id\":1,\"email\":null}"));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let tag = etag(4, b"{}");
+        let raw = tag.to_str().unwrap();
+        assert!(not_modified(
+            &headers(header::IF_NONE_MATCH, &format!("W/{}", raw)),
+            &tag
+        ));
+        assert!(not_modified(
+            &headers(header::IF_NONE_MATCH, &format!("\"1-00\", {}", raw)),
+            &tag
+        ));
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "*"), &tag));
+        // Misma versión, otra representación
+        assert!(!not_modified(
+            &headers(header::IF_NONE_MATCH, "\"4\""),
+            &tag
+        ));
+        assert!(!not_modified(&HeaderMap::new(), &tag));
+    }
+}
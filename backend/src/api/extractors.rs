@@ -0,0 +1,54 @@
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::request::Parts,
+    Json,
+};
+use serde::de::DeserializeOwned;
+use validator::Validate;
+
+/// `Json<T>` que además ejecuta `Validate`. Tanto los rechazos del cuerpo como
+/// las reglas incumplidas se devuelven como `AppError`.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        value.validate()?;
+        Ok(Self(value))
+    }
+}
+
+/// `Query<T>` que además ejecuta `Validate`. Un parámetro ilegible (p. ej. un
+/// `sort` desconocido) es un `AppError::Validation` traducible; el motivo de
+/// serde (en inglés) solo va al log.
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) =
+            Query::<T>::from_request_parts(parts, state)
+                .await
+                .map_err(|rejection| {
+                    tracing::debug!(reason = %rejection.body_text(), "Query string ilegible");
+                    AppError::Validation("validation.query".to_string())
+                })?;
+        value.validate()?;
+        Ok(Self(value))
+    }
+}
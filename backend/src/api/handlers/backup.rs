@@ -0,0 +1,51 @@
+use crate::core::models::user::Claims;
+use crate::data::backup::{BackupInfo, BackupManager};
+use crate::error::AppError;
+use crate::state::AppState;
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use std::sync::Arc;
+
+/// Acción registrada en la auditoría al crear una copia bajo demanda.
+pub const CREATE_BACKUP_ACTION: &str = "CREATE_BACKUP";
+
+fn manager(state: &AppState) -> Result<Arc<BackupManager>, AppError> {
+    state
+        .backups
+        .clone()
+        .ok_or(AppError::Conflict("backup.unavailable".to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/backups",
+    responses(
+        (status = 201, description = "Copia de seguridad creada", body = BackupInfo),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "La base no es SQLite", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn create_backup(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<(StatusCode, Json<BackupInfo>), AppError> {
+    let info = manager(&state)?.create().await?;
+    state
+        .users
+        .record_audit(&claims.sub, CREATE_BACKUP_ACTION, &info.file_name)
+        .await?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/backups",
+    responses(
+        (status = 200, description = "Copias disponibles (la más reciente primero)", body = Vec<BackupInfo>),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_backups(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BackupInfo>>, AppError> {
+    Ok(Json(manager(&state)?.list()?))
+}
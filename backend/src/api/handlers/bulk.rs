@@ -0,0 +1,332 @@
+use crate::api::extractors::{ValidatedJson, ValidatedQuery};
+use crate::core::models::bulk::{
+    BatchItemResult, BatchReport, BatchRequest, ExportFormat, ExportOptions, ImportOptions,
+    ImportReport, ImportRow, ImportRowError,
+};
+use crate::core::models::user::{Claims, Keyset, User};
+use crate::core::services::bulk::BulkService;
+use crate::error::{self, AppError};
+use crate::i18n::Locale;
+use crate::state::AppState;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::Serialize;
+
+/// Lee el cuerpo según su `Content-Type`: `text/csv` (con cabecera) o NDJSON
+/// (`application/x-ndjson`, un objeto por línea). Cada fila es la fila leída o
+/// el motivo por el que no se pudo leer.
+fn parse_rows(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Vec<Result<ImportRow, String>>, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    match mime {
+        "text/csv" => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::Headers)
+                .from_reader(body);
+            Ok(reader
+                .deserialize::<ImportRow>()
+                .map(|row| row.map_err(|e| e.to_string()))
+                .collect())
+        }
+        "application/x-ndjson" | "application/ndjson" | "application/jsonl" => {
+            let text = std::str::from_utf8(body)
+                .map_err(|_| AppError::Validation("import.invalid_encoding".to_string()))?;
+            Ok(text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| serde_json::from_str::<ImportRow>(line).map_err(|e| e.to_string()))
+                .collect())
+        }
+        _ => Err(AppError::Validation(
+            "import.unsupported_format".to_string(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users/import",
+    params(ImportOptions),
+    request_body(
+        content = String,
+        description = "CSV con cabecera (`username,password,role,display_name,email`) o NDJSON con esas claves",
+        content_type = "text/csv"
+    ),
+    responses(
+        (status = 200, description = "`dry_run`: informe (con los errores por fila) sin escribir nada", body = ImportReport),
+        (status = 201, description = "Filas escritas en una transacción y auditadas como un lote", body = ImportReport),
+        (status = 400, description = "Formato no soportado, vacío, más de 5000 filas o `invite` sin servidor de correo", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Alguna fila es inválida: no se escribió nada; errores por fila", body = ImportReport)
+    )
+)]
+pub async fn import_users(
+    State(state): State<AppState>,
+    locale: Locale,
+    Extension(claims): Extension<Claims>,
+    ValidatedQuery(options): ValidatedQuery<ImportOptions>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let rows = parse_rows(&headers, &body)?;
+    let outcome = state
+        .bulk_service()
+        .import(rows, &options, &claims.sub)
+        .await?;
+
+    let status = if outcome.dry_run {
+        StatusCode::OK
+    } else if !outcome.issues.is_empty() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::CREATED
+    };
+    let report = ImportReport {
+        batch_id: outcome.batch_id,
+        dry_run: outcome.dry_run,
+        rows: outcome.rows,
+        created: outcome.created,
+        updated: outcome.updated,
+        invited: outcome.invited,
+        errors: outcome
+            .issues
+            .into_iter()
+            .map(|issue| ImportRowError {
+                row: issue.row,
+                username: issue.username,
+                errors: error::field_errors(&issue.errors, locale),
+            })
+            .collect(),
+    };
+    Ok((status, Json(report)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users:batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Resultado por operación; sin `atomic`, las que fallan no impiden las demás", body = BatchReport),
+        (status = 400, description = "Lote vacío, de más de 100 operaciones u operación desconocida", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Lote `atomic` con un fallo: no se aplicó nada; el informe llega hasta la operación que falló y marca las anteriores con 424", body = BatchReport)
+    )
+)]
+pub async fn batch_users(
+    State(state): State<AppState>,
+    locale: Locale,
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(request): ValidatedJson<BatchRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let outcome = state
+        .bulk_service()
+        .apply_batch(&request, &claims.sub)
+        .await?;
+
+    let status = if outcome.committed {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    let results: Vec<BatchItemResult> = outcome
+        .items
+        .into_iter()
+        .map(|item| match item.result {
+            // Aplicada pero deshecha con el resto del lote atómico
+            Ok(()) if !outcome.committed => BatchItemResult {
+                index: item.index,
+                id: item.id,
+                status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+                error: None,
+            },
+            Ok(()) => BatchItemResult {
+                index: item.index,
+                id: item.id,
+                status: StatusCode::OK.as_u16(),
+                error: None,
+            },
+            Err(e) => BatchItemResult {
+                index: item.index,
+                id: item.id,
+                status: e.status().as_u16(),
+                error: Some(e.to_problem(None, locale)),
+            },
+        })
+        .collect();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    let rolled_back = if outcome.committed {
+        0
+    } else {
+        results.len() - failed
+    };
+    let report = BatchReport {
+        batch_id: outcome.batch_id,
+        atomic: outcome.atomic,
+        committed: outcome.committed,
+        succeeded: results.len() - failed - rolled_back,
+        failed,
+        rolled_back,
+        results,
+    };
+    Ok((status, Json(report)))
+}
+
+/// Cabecera del CSV: los campos de `ExportRow`, en orden.
+const EXPORT_COLUMNS: [&str; 8] = [
+    "id",
+    "username",
+    "role",
+    "status",
+    "display_name",
+    "email",
+    "created_at",
+    "last_login_at",
+];
+
+/// Columnas de la exportación; con `mode=upsert` se pueden volver a importar.
+#[derive(Serialize)]
+struct ExportRow<'a> {
+    id: i64,
+    username: &'a str,
+    role: &'static str,
+    status: &'static str,
+    display_name: Option<&'a str>,
+    email: Option<&'a str>,
+    created_at: DateTime<Utc>,
+    last_login_at: Option<DateTime<Utc>>,
+}
+
+impl<'a> From<&'a User> for ExportRow<'a> {
+    fn from(user: &'a User) -> Self {
+        Self {
+            id: user.id,
+            username: &user.username,
+            role: user.role.as_str(),
+            status: user.status.as_str(),
+            display_name: user.display_name.as_deref(),
+            email: user.email.as_deref(),
+            created_at: user.created_at,
+            last_login_at: user.last_login_at,
+        }
+    }
+}
+
+fn encode(format: ExportFormat, users: &[User], with_header: bool) -> Result<Bytes, AppError> {
+    let failed = |e: String| AppError::Internal(format!("Exportación: {}", e));
+    match format {
+        ExportFormat::Csv => {
+            // `csv` solo escribe la cabecera con la primera fila: una exportación
+            // vacía se quedaría sin ella y no se podría reimportar
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            if with_header {
+                writer
+                    .write_record(EXPORT_COLUMNS)
+                    .map_err(|e| failed(e.to_string()))?;
+            }
+            for user in users {
+                writer
+                    .serialize(ExportRow::from(user))
+                    .map_err(|e| failed(e.to_string()))?;
+            }
+            let bytes = writer.into_inner().map_err(|e| failed(e.to_string()))?;
+            Ok(Bytes::from(bytes))
+        }
+        ExportFormat::Ndjson => {
+            let mut bytes = Vec::new();
+            for user in users {
+                serde_json::to_writer(&mut bytes, &ExportRow::from(user))
+                    .map_err(|e| failed(e.to_string()))?;
+                bytes.push(b'\n');
+            }
+            Ok(Bytes::from(bytes))
+        }
+    }
+}
+
+/// Estado del stream de exportación: el bloque siguiente empieza tras `after`.
+struct ExportCursor {
+    service: BulkService,
+    options: ExportOptions,
+    after: Option<Keyset>,
+    first: bool,
+    done: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users/export",
+    params(ExportOptions),
+    responses(
+        (status = 200, description = "Usuarios por orden de alta, en CSV (con cabecera) o NDJSON, enviados por bloques", content_type = "text/csv"),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn export_users(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    ValidatedQuery(options): ValidatedQuery<ExportOptions>,
+) -> Result<Response, AppError> {
+    let service = state.bulk_service();
+    let batch_id = service.start_export(&options, &claims.sub).await?;
+    let format = options.format;
+
+    // Un bloque por consulta: la memoria no crece con el número de usuarios
+    let cursor = ExportCursor {
+        service,
+        options,
+        after: None,
+        first: true,
+        done: false,
+    };
+    let chunks = stream::try_unfold(cursor, |mut cursor| async move {
+        if cursor.done {
+            return Ok(None);
+        }
+        let page = cursor
+            .service
+            .export_chunk(&cursor.options, cursor.after)
+            .await?;
+        let bytes = encode(cursor.options.format, &page.items, cursor.first)?;
+        cursor.first = false;
+        cursor.done = page.next.is_none();
+        cursor.after = page.next;
+        Ok::<_, AppError>(Some((bytes, cursor)))
+    });
+    let body = Body::from_stream(futures_util::TryStreamExt::map_err(chunks, |e| {
+        tracing::error!("❌ Exportación interrumpida: {}", e);
+        std::io::Error::other(e.to_string())
+    }));
+
+    let content_type = match format {
+        ExportFormat::Csv => "text/csv; charset=utf-8",
+        ExportFormat::Ndjson => "application/x-ndjson",
+    };
+    let disposition = format!(
+        "attachment; filename=\"users-{}.{}\"",
+        batch_id,
+        format.as_str()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
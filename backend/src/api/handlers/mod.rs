@@ -1 +1,4 @@
+pub mod backup;
+pub mod bulk;
+pub mod privacy;
 pub mod user;
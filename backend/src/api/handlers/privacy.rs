@@ -0,0 +1,79 @@
+use crate::core::models::privacy::ErasureRequest;
+use crate::core::models::user::Claims;
+use crate::error::AppError;
+use crate::i18n::{self, Locale};
+use crate::state::AppState;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/export",
+    responses(
+        (status = 200, description = "Archivo JSON con todo lo que guardamos sobre el usuario: perfil, sesiones, auditoría que le afecta y solicitud de borrado", body = DataExport),
+        (status = 401, description = "No autorizado", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn export_me(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AppError> {
+    let export = state.privacy_service().export(&claims.sub).await?;
+    // El id (no el username) en el nombre: no hace falta escaparlo
+    let disposition = format!("attachment; filename=\"datos-{}.json\"", export.profile.id);
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(export)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me",
+    responses(
+        (status = 202, description = "Borrado programado para el fin del periodo de gracia (`erasure_grace_days`)", body = ErasureRequest),
+        (status = 401, description = "No autorizado", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Ya hay una solicitud pendiente", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn request_erasure(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AppError> {
+    let request = state.privacy_service().request_erasure(&claims.sub).await?;
+    Ok((StatusCode::ACCEPTED, Json(request)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/erasure",
+    responses(
+        (status = 200, description = "Solicitud de borrado pendiente", body = ErasureRequest),
+        (status = 404, description = "No hay ninguna pendiente", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_erasure(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<ErasureRequest>, AppError> {
+    let request = state.privacy_service().pending_erasure(&claims.sub).await?;
+    Ok(Json(request))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/erasure",
+    responses(
+        (status = 200, description = "Solicitud cancelada; la cuenta se conserva"),
+        (status = 404, description = "No hay ninguna pendiente", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn cancel_erasure(
+    State(state): State<AppState>,
+    locale: Locale,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AppError> {
+    state.privacy_service().cancel_erasure(&claims.sub).await?;
+    Ok((StatusCode::OK, i18n::t(locale, "erasure.cancelled")))
+}
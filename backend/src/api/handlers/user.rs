@@ -1,26 +1,26 @@
+use crate::api::extractors::{ValidatedJson, ValidatedQuery};
+use crate::api::pagination::{self, CursorPage};
+use crate::api::shaping::{self, Shape, ShapeQuery};
+use crate::api::{conditional, middleware};
+use crate::core::cursor;
 use crate::core::models::user::{
-    AuditLog, Claims, CreateUserRequest, LoginRequest, User, UserSearch,
-};
-use crate::core::repository::UserRepository;
-use crate::data::user_repository::SqliteRepository;
-use crate::error::AppError;
-use argon2::{
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    AuditArchive, AuditLogQuery, Claims, CreateUserRequest, KeysetPage, LoginRequest, Role,
+    SecurityEvent, SecurityEventQuery, SetPasswordRequest, UpdateLocaleRequest,
+    UpdateProfileRequest, UpdateUserRequest, User, UserHit, UserSearch,
 };
+use crate::error::{AppError, AuthFailure};
+use crate::i18n::{self, Locale};
+use crate::state::AppState;
 use axum::{
     debug_handler,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
+    extract::{OriginalUri, Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use serde_json::json;
-use sqlx::SqlitePool;
+use serde::Serialize;
+use serde_json::{json, Value};
 use tower_cookies::{Cookie, Cookies};
-use validator::Validate;
 
 #[utoipa::path(
     post,
@@ -28,62 +28,232 @@ use validator::Validate;
     request_body = CreateUserRequest,
     responses(
         (status = 201, description = "Usuario creado exitosamente", body = User),
-        (status = 409, description = "El usuario ya existe"),
-        (status = 400, description = "Datos inválidos")
+        (status = 409, description = "El usuario ya existe", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 400, description = "Datos inválidos", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn create_user(
-    State(pool): State<SqlitePool>,
-    Json(payload): Json<CreateUserRequest>,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<CreateUserRequest>,
 ) -> Result<(StatusCode, Json<User>), AppError> {
-    // 0. Validar inputs antes de procesar
-    if let Err(e) = payload.validate() {
-        return Err(AppError::Validation(format!("Datos inválidos: {}", e)));
-    }
-
-    // 1. Generar Salt y Hash seguro
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(payload.password.as_bytes(), &salt)
-        .map_err(|e| AppError::AuthError(format!("Error de seguridad: {}", e)))?
-        .to_string();
-
-    let repo = SqliteRepository::new(pool);
-    let user = repo.create_user(&payload.username, &password_hash).await?;
+    let user = state
+        .user_service()
+        .register(&payload.username, &payload.password)
+        .await?;
     Ok((StatusCode::CREATED, Json(user)))
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/audit-logs",
+    params(AuditLogQuery),
     responses(
-        (status = 200, description = "Bitácora de auditoría del sistema", body = Vec<AuditLog>)
+        (status = 200, description = "Bitácora de auditoría del sistema, de la más reciente a la más antigua. Con `after`, una página `AuditLogCursorPage`", body = Vec<AuditLog>),
+        (status = 400, description = "Cursor inválido o `limit` fuera de 1..=100", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_audit_logs(
-    State(pool): State<SqlitePool>,
-) -> Result<Json<Vec<AuditLog>>, AppError> {
-    let repo = SqliteRepository::new(pool);
-    let logs = repo.get_audit_logs().await?;
-    Ok(Json(logs))
+    State(state): State<AppState>,
+    ValidatedQuery(params): ValidatedQuery<AuditLogQuery>,
+) -> Result<Response, AppError> {
+    let Some(after) = params.after else {
+        let logs = state.users.get_audit_logs().await?;
+        return Ok(Json(logs).into_response());
+    };
+    let after = pagination::decode_after(&state.cursors, cursor::AUDIT_LOGS, &after)?;
+    let page = state
+        .user_service()
+        .audit_logs_after(after, params.limit)
+        .await?;
+    Ok(Json(CursorPage::new(&state.cursors, cursor::AUDIT_LOGS, page)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit-logs/archives",
+    responses(
+        (status = 200, description = "Archivos generados por la política de retención", body = Vec<AuditArchive>)
+    )
+)]
+pub async fn get_audit_archives(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AuditArchive>>, AppError> {
+    let archives = state.users.get_audit_archives().await?;
+    Ok(Json(archives))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/security-events",
+    params(SecurityEventQuery),
+    responses(
+        (status = 200, description = "Accesos rechazados por los guards, del más reciente al más antiguo", body = SecurityEventCursorPage),
+        (status = 400, description = "Cursor inválido o `limit` fuera de 1..=100", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_security_events(
+    State(state): State<AppState>,
+    ValidatedQuery(params): ValidatedQuery<SecurityEventQuery>,
+) -> Result<Json<CursorPage<SecurityEvent>>, AppError> {
+    let after = params.after.unwrap_or_default();
+    let after = pagination::decode_after(&state.cursors, cursor::SECURITY_EVENTS, &after)?;
+    let page = state
+        .user_service()
+        .security_events_after(after, params.limit)
+        .await?;
+    Ok(Json(CursorPage::new(
+        &state.cursors,
+        cursor::SECURITY_EVENTS,
+        page,
+    )))
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/users",
-    params(UserSearch),
+    params(UserSearch, ShapeQuery),
     responses(
-        (status = 200, description = "Lista de usuarios registrados", body = Vec<User>)
+        (status = 200, description = "Una página de usuarios; con `q`, por relevancia y con la coincidencia resaltada. El total va en `X-Total-Count` y la navegación en `Link`. Con `after`, una página `UserCursorPage` por orden de alta y sin esas cabeceras. El email solo se devuelve y se busca con una sesión de admin", body = Vec<UserHit>,
+            headers(
+                ("X-Total-Count" = i64, description = "Usuarios que cumplen los filtros"),
+                ("Link" = String, description = "Páginas `first`, `prev`, `next` y `last` (RFC 8288)")
+            )
+        ),
+        (status = 400, description = "`page < 1`, `limit` fuera de 1..=100, cursor inválido, campo o expansión desconocidos (con los permitidos) o parámetro inválido", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "`fields` o `include` piden el email o `last_login` sin sesión de admin", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_users(
-    State(pool): State<SqlitePool>,
-    Query(params): Query<UserSearch>,
-) -> Result<Json<Vec<User>>, AppError> {
-    let repo = SqliteRepository::new(pool);
-    let users = repo.get_all(params.q, params.page, params.limit).await?;
-    Ok(Json(users))
+    State(state): State<AppState>,
+    claims: Option<Extension<Claims>>,
+    OriginalUri(uri): OriginalUri,
+    ValidatedQuery(mut params): ValidatedQuery<UserSearch>,
+    ValidatedQuery(shape): ValidatedQuery<ShapeQuery>,
+) -> Result<Response, AppError> {
+    // El email y el último login solo los ven los admins (y solo ellos buscan por email)
+    let admin = claims.is_some_and(|Extension(claims)| claims.role == Role::Admin);
+    let shape = Shape::parse(&shape, shaping::USER_FIELDS, shaping::USER_INCLUDES)?
+        .restrict(shaping::USER_PRIVILEGED, admin)?;
+    params.search_email = admin;
+    if let Some(after) = params.after.take() {
+        let after = pagination::decode_after(&state.cursors, cursor::USERS, &after)?;
+        let page = state.user_service().search_after(params, after).await?;
+        let page = KeysetPage {
+            items: page.items.iter().map(|hit| shape.user(hit)).collect(),
+            next: page.next,
+        };
+        return Ok(Json(CursorPage::new(&state.cursors, cursor::USERS, page)).into_response());
+    }
+    let (page, limit) = (params.page, params.limit);
+    let result = state.user_service().search(params).await?;
+    let headers = pagination::headers(&uri, page, limit, result.total);
+    let items: Vec<Value> = result.items.iter().map(|hit| shape.user(hit)).collect();
+    Ok((headers, Json(items)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{id}",
+    params(("id" = i64, Path, description = "ID del usuario"), ShapeQuery),
+    responses(
+        (status = 200, description = "El usuario, con su versión en `ETag`", body = UserHit,
+            headers(("ETag" = String, description = "Versión del usuario y huella de esta representación; se envía en `If-Match` al editarlo o borrarlo"))
+        ),
+        (status = 304, description = "`If-None-Match` coincide con el `ETag` actual"),
+        (status = 400, description = "Campo o expansión desconocidos", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Sin sesión", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Ni admin ni el propio usuario, o pide el email o `last_login` sin ser admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No existe", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    ValidatedQuery(shape): ValidatedQuery<ShapeQuery>,
+) -> Result<Response, AppError> {
+    let admin = claims.role == Role::Admin;
+    let shape = Shape::parse(&shape, shaping::USER_FIELDS, shaping::USER_INCLUDES)?
+        .restrict(shaping::USER_PRIVILEGED, admin)?;
+    // Un usuario normal solo se consulta a sí mismo (sin saber si otro id existe)
+    let user = match state.user_service().get(id).await {
+        Ok(user) if admin || user.username == claims.sub => user,
+        Ok(_) | Err(AppError::NotFound(_)) if !admin => {
+            return Err(
+                middleware::deny(&state, AuthFailure::InsufficientRole, &method, &uri).await,
+            )
+        }
+        result => result?,
+    };
+    let (etag, body) = versioned_json(user.version, &shape.user(&UserHit::from(user)))?;
+    // La representación depende de quién pregunta (admin o el propio usuario)
+    let headers_out = [
+        (header::ETAG, etag.clone()),
+        (
+            header::VARY,
+            HeaderValue::from_static("cookie, authorization"),
+        ),
+    ];
+    if conditional::not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers_out).into_response());
+    }
+    Ok((
+        headers_out,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
+
+/// Cuerpo JSON de un usuario en la versión `version`, con su `ETag`.
+fn versioned_json(
+    version: i64,
+    value: &impl Serialize,
+) -> Result<(HeaderValue, Vec<u8>), AppError> {
+    let body = serde_json::to_vec(value)
+        .map_err(|e| AppError::Internal(format!("Usuario no serializable: {}", e)))?;
+    Ok((conditional::etag(version, &body), body))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "ID del usuario a editar"),
+        ("If-Match" = Option<String>, Header, description = "`ETag` leído; obligatorio con `require_if_match`")
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "Usuario editado y auditado, con la nueva versión en `ETag`", body = User),
+        (status = 400, description = "Datos inválidos o ningún campo", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No existe", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "El usuario cambió desde que se leyó", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "Falta `If-Match`", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn update_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    ValidatedJson(payload): ValidatedJson<UpdateUserRequest>,
+) -> Result<Response, AppError> {
+    let if_match = conditional::if_match(&headers, state.settings.require_if_match)?;
+    let user = state
+        .user_service()
+        .update(id, &payload, if_match.as_ref(), &claims.sub)
+        .await?;
+    let (etag, body) = versioned_json(user.version, &user)?;
+    Ok((
+        [(header::ETAG, etag)],
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
 }
 
 #[debug_handler]
@@ -93,52 +263,25 @@ pub async fn get_users(
     request_body = LoginRequest,
     responses(
         (status = 200, description = "Login exitoso (Cookie establecida)"),
-        (status = 401, description = "Credenciales inválidas")
+        (status = 401, description = "Credenciales inválidas", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Cuenta suspendida (`account_suspended`)", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn login(
-    State(pool): State<SqlitePool>,
+    State(state): State<AppState>,
+    locale: Locale,
     cookies: Cookies,
-    Json(payload): Json<LoginRequest>,
+    ValidatedJson(payload): ValidatedJson<LoginRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    // 1. Buscar usuario en DB
-    let repo = SqliteRepository::new(pool);
-    let user = repo
-        .get_by_username(&payload.username)
-        .await?
-        .ok_or(AppError::AuthError("Credenciales inválidas".to_string()))?;
-
-    // 2. Verificar password
-    let parsed_hash = PasswordHash::new(&user.password_hash)
-        .map_err(|_| AppError::AuthError("Error verificando credenciales".to_string()))?;
-
-    if Argon2::default()
-        .verify_password(payload.password.as_bytes(), &parsed_hash)
-        .is_ok()
-    {
-        // GENERAR JWT
-        let expiration = Utc::now()
-            .checked_add_signed(Duration::hours(24))
-            .expect("Tiempo inválido")
-            .timestamp();
-        let claims = Claims {
-            sub: user.username,
-            role: user.role,
-            exp: expiration as usize,
-        };
-        // NOTA: En producción, "secret" debe venir de variables de entorno (.env)
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret("secret".as_ref()),
-        )
-        .map_err(|_| AppError::AuthError("Error generando token".to_string()))?;
+    let session = state
+        .auth_service()
+        .login(&payload.username, &payload.password)
+        .await?;
 
-        cookies.add(Cookie::new("auth_token", token));
-        Ok((StatusCode::OK, "Login exitoso"))
-    } else {
-        Err(AppError::AuthError("Credenciales inválidas".to_string()))
-    }
+    cookies.add(Cookie::new("auth_token", session.token));
+    // La preferencia guardada manda sobre el idioma negociado
+    let locale = session.user.locale.unwrap_or(locale);
+    Ok((StatusCode::OK, i18n::t(locale, "login.success")))
 }
 
 #[utoipa::path(
@@ -148,78 +291,156 @@ pub async fn login(
         (status = 200, description = "Sesión cerrada correctamente")
     )
 )]
-pub async fn logout(cookies: Cookies) -> impl IntoResponse {
+pub async fn logout(
+    State(state): State<AppState>,
+    locale: Locale,
+    cookies: Cookies,
+) -> Result<impl IntoResponse, AppError> {
+    // Revocar el token aunque alguien conserve una copia de la cookie
+    if let Some(cookie) = cookies.get("auth_token") {
+        state.auth_service().logout(cookie.value()).await?;
+    }
+
     cookies.remove(Cookie::new("auth_token", ""));
-    (StatusCode::OK, "Sesión cerrada correctamente").into_response()
+    Ok((StatusCode::OK, i18n::t(locale, "logout.success")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/password",
+    request_body = SetPasswordRequest,
+    responses(
+        (status = 200, description = "Contraseña guardada; el código ya no sirve"),
+        (status = 400, description = "Contraseña demasiado corta", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Código caducado, ya usado o inválido", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn set_password(
+    State(state): State<AppState>,
+    locale: Locale,
+    ValidatedJson(payload): ValidatedJson<SetPasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .auth_service()
+        .set_password(&payload.token, &payload.password)
+        .await?;
+    Ok((StatusCode::OK, i18n::t(locale, "password.updated")))
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/dashboard",
     responses(
-        (status = 200, description = "Información del usuario actual")
+        (status = 200, description = "Información del usuario actual"),
+        (status = 401, description = "Sesión ausente, expirada o revocada", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn dashboard(cookies: Cookies) -> Result<impl IntoResponse, AppError> {
-    let cookie = cookies
-        .get("auth_token")
-        .map(|c| c.value().to_string())
-        .unwrap_or_default();
-
-    // Decodificar el token para saber quién es
-    let token_data = decode::<Claims>(
-        &cookie,
-        &DecodingKey::from_secret("secret".as_ref()),
-        &Validation::default(),
+pub async fn dashboard(locale: Locale, Extension(claims): Extension<Claims>) -> impl IntoResponse {
+    // `auth_guard` ya validó el token y dejó los claims
+    let message = i18n::t_with(
+        locale,
+        "dashboard.welcome",
+        &[
+            ("user", claims.sub.clone()),
+            ("role", format!("{:?}", claims.role)),
+        ],
     );
-
-    match token_data {
-        Ok(c) => Ok((
-            StatusCode::OK,
-            Json(json!({
-                "username": c.claims.sub,
-                "role": c.claims.role,
-                "message": format!("🔐 Panel de Control | Agente: {} | Rango: {:?}", c.claims.sub, c.claims.role)
-            })),
-        )),
-        Err(_) => Err(AppError::AuthError(
-            "Sesión inválida o expirada".to_string(),
-        )),
-    }
+    (
+        StatusCode::OK,
+        Json(json!({
+            "username": claims.sub,
+            "role": claims.role,
+            "message": message
+        })),
+    )
 }
 
 #[utoipa::path(
     delete,
     path = "/api/v1/users/{id}",
-    params(("id" = i64, Path, description = "ID del usuario a eliminar")),
+    params(
+        ("id" = i64, Path, description = "ID del usuario a eliminar"),
+        ("If-Match" = Option<String>, Header, description = "`ETag` leído; obligatorio con `require_if_match`")
+    ),
     responses(
         (status = 200, description = "Usuario eliminado y auditado"),
-        (status = 401, description = "No autorizado")
+        (status = 401, description = "No autorizado", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Requiere rol Admin", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "El usuario cambió desde que se leyó (o ya no existe)", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "Falta `If-Match`", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn delete_user(
-    State(pool): State<SqlitePool>,
-    cookies: Cookies,
+    State(state): State<AppState>,
+    locale: Locale,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse, AppError> {
-    // 1. Identificar al Admin (Auditoría)
-    // Aunque el middleware ya validó, necesitamos el username para el log.
-    let admin_username = if let Some(cookie) = cookies.get("auth_token") {
-        if let Ok(token_data) = decode::<Claims>(
-            cookie.value(),
-            &DecodingKey::from_secret("secret".as_ref()),
-            &Validation::default(),
-        ) {
-            token_data.claims.sub
-        } else {
-            "Desconocido".to_string()
-        }
-    } else {
-        "Desconocido".to_string()
-    };
+    let if_match = conditional::if_match(&headers, state.settings.require_if_match)?;
+    // El admin (para la auditoría) viene de los claims que dejó `admin_guard`
+    state
+        .user_service()
+        .delete(id, if_match.as_ref(), &claims.sub)
+        .await?;
 
-    let repo = SqliteRepository::new(pool);
-    repo.delete_user(id, &admin_username).await?;
+    Ok((StatusCode::OK, i18n::t(locale, "user.deleted")))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/me/locale",
+    request_body = UpdateLocaleRequest,
+    responses(
+        (status = 200, description = "Idioma preferido guardado (la cookie se renueva)"),
+        (status = 401, description = "No autorizado", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn update_locale(
+    State(state): State<AppState>,
+    locale: Locale,
+    cookies: Cookies,
+    Extension(mut claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<UpdateLocaleRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .user_service()
+        .set_locale(&claims.sub, payload.locale)
+        .await?;
+
+    // Re-emitir el token para que la preferencia aplique desde ya
+    claims.locale = payload.locale;
+    let token = state.auth_service().issue(&claims)?;
+    cookies.add(Cookie::new("auth_token", token));
+
+    let locale = payload.locale.unwrap_or(locale);
+    Ok((StatusCode::OK, i18n::t(locale, "locale.updated")))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/me/profile",
+    request_body = UpdateProfileRequest,
+    responses(
+        (status = 200, description = "Perfil actualizado (nombre visible y email)"),
+        (status = 401, description = "No autorizado", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 400, description = "Datos inválidos", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn update_profile(
+    State(state): State<AppState>,
+    locale: Locale,
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(payload): ValidatedJson<UpdateProfileRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .user_service()
+        .set_profile(
+            &claims.sub,
+            payload.display_name.as_deref(),
+            payload.email.as_deref(),
+        )
+        .await?;
 
-    Ok((StatusCode::OK, "Usuario eliminado y auditado"))
+    Ok((StatusCode::OK, i18n::t(locale, "profile.updated")))
 }
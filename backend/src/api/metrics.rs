@@ -0,0 +1,40 @@
+//! Contadores en memoria expuestos en `/metrics` (formato texto de Prometheus),
+//! solo para administradores.
+use crate::error::AuthFailure;
+use axum::{http::header, response::IntoResponse};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static AUTH_FAILURES: [AtomicU64; AuthFailure::ALL.len()] =
+    [const { AtomicU64::new(0) }; AuthFailure::ALL.len()];
+
+pub fn record_auth_failure(failure: AuthFailure) {
+    AUTH_FAILURES[failure as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn auth_failures(failure: AuthFailure) -> u64 {
+    AUTH_FAILURES[failure as usize].load(Ordering::Relaxed)
+}
+
+pub fn render() -> String {
+    let mut out = String::from(
+        "# HELP auth_guard_failures_total Peticiones rechazadas por los guards, por motivo\n\
+         # TYPE auth_guard_failures_total counter\n",
+    );
+    for failure in AuthFailure::ALL {
+        let _ = writeln!(
+            out,
+            "auth_guard_failures_total{{reason=\"{}\"}} {}",
+            failure.code(),
+            auth_failures(failure)
+        );
+    }
+    out
+}
+
+pub async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(),
+    )
+}
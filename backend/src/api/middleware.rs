@@ -1,50 +1,354 @@
-use crate::core::models::user::{Claims, Role};
-use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use crate::api::metrics;
+use crate::core::{
+    models::idempotency::IdempotencyRecord,
+    models::user::{Claims, Role},
+    services::auth::{decode_token, Principal},
+};
+use crate::error::{scope_error_context, AppError, AuthFailure, ErrorContext};
+use crate::i18n::Locale;
+use crate::state::{AppState, JwtKeys};
+use axum::{
+    body::{self, Body},
+    extract::{OriginalUri, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Duration;
+use sha2::{Digest, Sha256};
+use std::future::Future;
 use tower_cookies::Cookies;
 
+/// Cabecera con la que el cliente marca un POST que puede reintentar.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// `true` en las respuestas repetidas desde el almacén (no se ejecutó nada).
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+/// Lo que dura la reserva de una clave mientras se atiende la primera
+/// petición: si el proceso muere a mitad, la clave no queda bloqueada todo el TTL.
+const IDEMPOTENCY_LOCK_SECS: i64 = 300;
+/// Mismo límite que el `DefaultBodyLimit` de axum.
+const IDEMPOTENT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Token de la cookie `auth_token` o, en su defecto, de `Authorization: Bearer`.
+fn request_token(cookies: &Cookies, headers: &HeaderMap) -> Option<String> {
+    cookies
+        .get("auth_token")
+        .map(|c| c.value().to_string())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .map(str::to_string)
+        })
+}
+
+async fn authenticate(
+    state: &AppState,
+    cookies: &Cookies,
+    headers: &HeaderMap,
+) -> Result<Principal, AuthFailure> {
+    let token = request_token(cookies, headers).ok_or(AuthFailure::MissingToken)?;
+    state.auth_service().authenticate(&token).await
+}
+
+/// Registra (log, métrica y `security_events`) el motivo del rechazo y lo
+/// convierte en `AppError`.
+fn reject<'a>(
+    state: &'a AppState,
+    failure: AuthFailure,
+    req: &Request,
+) -> impl Future<Output = AppError> + Send + 'a {
+    // Sin retener la petición (no es `Sync`) durante el `await`. La URI
+    // completa: dentro de un `nest` la de la petición pierde el prefijo
+    let method = req.method().clone();
+    let uri = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| req.uri().clone(), |original| original.0.clone());
+    async move { deny(state, failure, &method, &uri).await }
+}
+
+/// Como `reject`, para los handlers que niegan el acceso después del guard
+/// (p. ej. según el recurso pedido): mismo log, métrica y evento.
+pub async fn deny(state: &AppState, failure: AuthFailure, method: &Method, uri: &Uri) -> AppError {
+    tracing::warn!(
+        reason = failure.code(),
+        %method,
+        %uri,
+        "🚫 Acceso rechazado por el guard"
+    );
+    metrics::record_auth_failure(failure);
+    // Sin la query string: puede llevar datos que no hace falta conservar
+    if let Err(e) = state
+        .users
+        .record_security_event(failure.code(), method.as_str(), uri.path())
+        .await
+    {
+        // El rechazo no depende de poder guardarlo
+        tracing::error!(error = %e, "❌ No se pudo guardar el evento de seguridad");
+    }
+    AppError::Guard(failure)
+}
+
+/// Exige una sesión válida. Deja los `Claims` en las extensiones de la petición.
 pub async fn auth_guard(
+    State(state): State<AppState>,
     cookies: Cookies,
-    req: Request,
+    mut req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    // Verificamos si existe la cookie de sesión
-    if let Some(cookie) = cookies.get("auth_token") {
-        // Validar que el token sea real y no haya expirado
-        let validation = decode::<Claims>(
-            cookie.value(),
-            &DecodingKey::from_secret("secret".as_ref()),
-            &Validation::default(),
-        );
-
-        if validation.is_ok() {
-            return Ok(next.run(req).await);
-        }
-    }
+) -> Result<Response, AppError> {
+    let principal = match authenticate(&state, &cookies, req.headers()).await {
+        Ok(principal) => principal,
+        Err(failure) => return Err(reject(&state, failure, &req).await),
+    };
 
-    Err(StatusCode::UNAUTHORIZED)
+    req.extensions_mut().insert(principal.claims);
+    Ok(next.run(req).await)
 }
 
+/// Como `auth_guard`, pero además exige `Role::Admin` (403 si no lo tiene).
+/// Se mira el rol guardado: un admin degradado pierde el acceso sin esperar
+/// a que caduque su token.
 pub async fn admin_guard(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let principal = match authenticate(&state, &cookies, req.headers()).await {
+        Ok(principal) => principal,
+        Err(failure) => return Err(reject(&state, failure, &req).await),
+    };
+
+    if principal.user.role != Role::Admin {
+        return Err(reject(&state, AuthFailure::InsufficientRole, &req).await);
+    }
+
+    req.extensions_mut().insert(principal.claims);
+    Ok(next.run(req).await)
+}
+
+/// Para rutas públicas que muestran más con sesión: con un token válido deja
+/// los `Claims` en las extensiones, como `auth_guard`; sin él (o si no vale) la
+/// petición sigue como anónima.
+pub async fn optional_auth(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if let Ok(principal) = authenticate(&state, &cookies, req.headers()).await {
+        req.extensions_mut().insert(principal.claims);
+    }
+    next.run(req).await
+}
+
+/// Idioma de la petición: preferencia del usuario (claim del token), luego
+/// Accept-Language y por último el idioma por defecto.
+fn resolve_locale(keys: &JwtKeys, cookies: &Cookies, headers: &HeaderMap) -> Locale {
+    let preferred = request_token(cookies, headers)
+        .and_then(|token| decode_token(keys, &token).ok())
+        .and_then(|claims| claims.locale);
+
+    preferred
+        .or_else(|| {
+            headers
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(Locale::negotiate)
+        })
+        .unwrap_or_default()
+}
+
+/// Expone el id de la petición, el formato configurado y el idioma al render
+/// de `AppError`; el idioma también queda como extensión para los handlers.
+pub async fn error_context(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let locale = resolve_locale(&state.keys, &cookies, req.headers());
+    req.extensions_mut().insert(locale);
+
+    let ctx = ErrorContext {
+        request_id,
+        format: state.settings.error_format,
+        locale,
+    };
+    let mut response = scope_error_context(ctx, next.run(req)).await;
+
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(locale.as_str()),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+    response
+}
+
+/// `Idempotency-Key` en los POST: la primera petición con una clave se ejecuta
+/// y su respuesta se guarda `idempotency_ttl_secs`; las repeticiones la reciben
+/// tal cual sin volver a ejecutarse. Las claves son de cada usuario (el `sub`
+/// de la sesión; las anónimas comparten espacio) y la huella cubre la URI y el
+/// cuerpo, así que la misma clave con otra petición es 422. Se repiten el
+/// estado, las cabeceras y el cuerpo. Los 5xx, los 401/403/404 y las
+/// respuestas que tocan cookies (login, logout) no se guardan: se reintentan.
+///
+/// Se monta en cada ruta POST, por dentro de su guard: una petición rechazada
+/// por el guard no llega a reservar la clave.
+pub async fn idempotency(
+    State(state): State<AppState>,
     cookies: Cookies,
     req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let token = cookies.get("auth_token").map(|c| c.value().to_string());
-
-    match token {
-        Some(t) => {
-            let token_data = decode::<Claims>(
-                &t,
-                &DecodingKey::from_secret("secret".as_ref()),
-                &Validation::default(),
-            );
-
-            match token_data {
-                Ok(c) if c.claims.role == Role::Admin => Ok(next.run(req).await),
-                _ => Err(StatusCode::FORBIDDEN), // 403: Prohibido (tiene token, pero no rango)
-            }
+) -> Result<Response, AppError> {
+    if req.method() != Method::POST {
+        return Ok(next.run(req).await);
+    }
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(next.run(req).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| (1..=255).contains(&key.trim().len()))
+        .ok_or(AppError::Validation("idempotency.invalid_key".to_string()))?
+        .trim()
+        .to_string();
+    // Dentro de `nest` la URI llega sin el prefijo `/api/v1`
+    let uri = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|original| original.0.clone())
+        .unwrap_or_else(|| req.uri().clone());
+    let mut scope = format!("{} {}", req.method(), uri.path());
+    if let Some(claims) = req.extensions().get::<Claims>() {
+        scope = format!("{} {}", scope, claims.sub);
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = body::to_bytes(body, IDEMPOTENT_BODY_LIMIT)
+        .await
+        .map_err(|_| AppError::Validation("idempotency.body_too_large".to_string()))?;
+    let mut hasher = Sha256::new();
+    for part in [uri.to_string().as_bytes(), &body] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    let fingerprint = hex::encode(hasher.finalize());
+
+    let now = state.clock.now();
+    let reserved_until = now + Duration::seconds(IDEMPOTENCY_LOCK_SECS);
+    if let Some(existing) = state
+        .users
+        .reserve_idempotency_key(&key, &scope, &fingerprint, now, reserved_until)
+        .await?
+    {
+        if existing.fingerprint != fingerprint {
+            return Err(AppError::Unprocessable(
+                "idempotency.key_reused".to_string(),
+            ));
         }
-        None => Err(StatusCode::UNAUTHORIZED), // 401: No hay token
+        return replay(existing);
     }
+
+    let cookies_before = cookie_snapshot(&cookies);
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !storable(response.status()) || cookie_snapshot(&cookies) != cookies_before {
+        state.users.release_idempotency_key(&key, &scope).await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            state.users.release_idempotency_key(&key, &scope).await?;
+            return Err(AppError::Internal(format!("Respuesta ilegible: {}", e)));
+        }
+    };
+    let expires_at = now + Duration::seconds(state.settings.idempotency_ttl_secs as i64);
+    // La petición ya se ejecutó: si no se puede guardar, se entrega igual y se
+    // libera la clave para que un reintento no quede bloqueado con 409
+    if let Err(e) = state
+        .users
+        .complete_idempotency_key(
+            &key,
+            &scope,
+            i32::from(parts.status.as_u16()),
+            &stored_headers(&parts.headers),
+            &body,
+            expires_at,
+        )
+        .await
+    {
+        tracing::warn!(error = %e, %scope, "⚠️ No se pudo guardar la respuesta idempotente");
+        if let Err(e) = state.users.release_idempotency_key(&key, &scope).await {
+            tracing::error!(error = %e, %scope, "❌ No se pudo liberar la Idempotency-Key");
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Si la respuesta se repite en los reintentos. Los 401/403/404 dependen de la
+/// sesión o de que el recurso exista todavía: el reintento debe volver a mirarlo.
+fn storable(status: StatusCode) -> bool {
+    !status.is_server_error()
+        && !matches!(
+            status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        )
+}
+
+/// Cabeceras de la respuesta tal como se guardan, una por línea (`nombre: valor`);
+/// `Content-Length` se recalcula al repetirla.
+fn stored_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .filter(|(name, _)| *name != header::CONTENT_LENGTH)
+        .filter_map(|(name, value)| Some(format!("{}: {}", name, value.to_str().ok()?)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Respuesta guardada de una clave; `Conflict` si la primera petición sigue en curso.
+fn replay(record: IdempotencyRecord) -> Result<Response, AppError> {
+    let Some(status) = record.status else {
+        return Err(AppError::Conflict("idempotency.in_progress".to_string()));
+    };
+    let mut response = Response::new(Body::from(record.body.unwrap_or_default()));
+    *response.status_mut() = u16::try_from(status)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or(AppError::Internal(format!(
+            "Estado guardado inválido: {}",
+            status
+        )))?;
+    let headers = response.headers_mut();
+    for line in record.headers.as_deref().unwrap_or_default().lines() {
+        let Some((name, value)) = line.split_once(": ") else {
+            continue;
+        };
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    Ok(response)
+}
+
+fn cookie_snapshot(cookies: &Cookies) -> Vec<String> {
+    let mut snapshot: Vec<String> = cookies.list().iter().map(|c| c.to_string()).collect();
+    snapshot.sort();
+    snapshot
 }
@@ -1,2 +1,7 @@
+pub mod conditional;
+pub mod extractors;
 pub mod handlers;
+pub mod metrics;
 pub mod middleware;
+pub mod pagination;
+pub mod shaping;
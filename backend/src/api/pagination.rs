@@ -0,0 +1,104 @@
+//! Paginación de los listados: por offset, con `X-Total-Count` y `Link`
+//! (RFC 8288); por cursor, con un sobre `{ items, next_cursor }`.
+use crate::core::cursor::CursorKey;
+use crate::core::models::user::{AuditLog, Keyset, KeysetPage, SecurityEvent, UserHit};
+use crate::error::AppError;
+use axum::http::{HeaderMap, HeaderValue, Uri};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Respuesta en modo cursor. `next_cursor` va en `?after=` para pedir la
+/// página siguiente; `null` en la última.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    UserCursorPage = CursorPage<UserHit>,
+    AuditLogCursorPage = CursorPage<AuditLog>,
+    SecurityEventCursorPage = CursorPage<SecurityEvent>
+)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    pub fn new(key: &CursorKey, scope: &str, page: KeysetPage<T>) -> Self {
+        Self {
+            items: page.items,
+            next_cursor: page.next.map(|next| key.encode(scope, &next)),
+        }
+    }
+}
+
+/// `?after=` vacío es la primera página.
+pub fn decode_after(key: &CursorKey, scope: &str, after: &str) -> Result<Option<Keyset>, AppError> {
+    match after {
+        "" => Ok(None),
+        cursor => key.decode(scope, cursor).map(Some),
+    }
+}
+
+/// `X-Total-Count` con el total y `Link` con `first`/`prev`/`next`/`last`,
+/// conservando el resto de parámetros de la petición.
+pub fn headers(uri: &Uri, page: i64, limit: i64, total: i64) -> HeaderMap {
+    let last = ((total + limit - 1) / limit).max(1);
+    let mut links = vec![(1, "first")];
+    if page > 1 {
+        links.push(((page - 1).min(last), "prev"));
+    }
+    if page < last {
+        links.push((page + 1, "next"));
+    }
+    links.push((last, "last"));
+
+    // Los valores ya vienen codificados: solo se sustituye `page`
+    let params: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty() && !p.starts_with("page="))
+        .collect();
+    let link = links
+        .iter()
+        .map(|(page, rel)| {
+            let mut query = params.clone();
+            let page = format!("page={}", page);
+            query.push(&page);
+            format!("<{}?{}>; rel=\"{}\"", uri.path(), query.join("&"), rel)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-total-count", HeaderValue::from(total));
+    if let Ok(link) = HeaderValue::from_str(&link) {
+        headers.insert("link", link);
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_keep_filters_and_bound_pages() {
+        let uri: Uri = "/api/v1/users?q=an&page=2&limit=10".parse().unwrap();
+        let links = headers(&uri, 2, 10, 35);
+        assert_eq!(links["x-total-count"], "35");
+        assert_eq!(
+            links["link"],
+            "</api/v1/users?q=an&limit=10&page=1>; rel=\"first\", \
+             </api/v1/users?q=an&limit=10&page=1>; rel=\"prev\", \
+             </api/v1/users?q=an&limit=10&page=3>; rel=\"next\", \
+             </api/v1/users?q=an&limit=10&page=4>; rel=\"last\""
+        );
+
+        // Sin resultados hay una única página
+        let uri: Uri = "/api/v1/users".parse().unwrap();
+        let links = headers(&uri, 1, 10, 0);
+        assert_eq!(
+            links["link"],
+            "</api/v1/users?page=1>; rel=\"first\", </api/v1/users?page=1>; rel=\"last\""
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Respuestas a medida: `?fields=` elige los campos de cada elemento e
+//! `?include=` añade expansiones que no van por defecto.
+use crate::core::models::user::UserHit;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use utoipa::IntoParams;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// Campos de un usuario en las respuestas.
+pub const USER_FIELDS: &[&str] = &[
+    "id",
+    "username",
+    "role",
+    "status",
+    "created_at",
+    "locale",
+    "display_name",
+    "email",
+    "version",
+    "highlight",
+];
+/// Expansiones de un usuario: `last_login`, el último inicio de sesión correcto.
+pub const USER_INCLUDES: &[&str] = &["last_login"];
+/// Campos y expansiones de un usuario reservados a los admins.
+pub const USER_PRIVILEGED: &[&str] = &["email", "last_login"];
+
+#[derive(Debug, Default, Deserialize, IntoParams, Validate)]
+pub struct ShapeQuery {
+    /// Campos a devolver separados por comas (p. ej. `id,username,role`); sin él, todos
+    pub fields: Option<String>,
+    /// Expansiones separadas por comas (`last_login`)
+    pub include: Option<String>,
+}
+
+/// `ShapeQuery` validado contra los campos y expansiones de un recurso.
+#[derive(Debug, Default)]
+pub struct Shape {
+    fields: Option<Vec<String>>,
+    include: Vec<String>,
+    hidden: &'static [&'static str],
+}
+
+impl Shape {
+    /// Un nombre desconocido es un error de validación en `fields` o `include`
+    /// (`validation.unknown_field`) con los permitidos en `params.allowed`.
+    pub fn parse(query: &ShapeQuery, fields: &[&str], includes: &[&str]) -> Result<Self, AppError> {
+        let mut errors = ValidationErrors::new();
+        let selected = query
+            .fields
+            .as_deref()
+            .map(|raw| names(raw, fields, "fields", &mut errors))
+            .filter(|selected| !selected.is_empty());
+        let include = query
+            .include
+            .as_deref()
+            .map(|raw| names(raw, includes, "include", &mut errors))
+            .unwrap_or_default();
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+        Ok(Self {
+            fields: selected,
+            include,
+            hidden: &[],
+        })
+    }
+
+    /// Sin `admin`, los campos de `privileged` no se devuelven, y pedirlos en
+    /// `fields` o `include` es 403 (`shaping.admin_only`).
+    pub fn restrict(
+        self,
+        privileged: &'static [&'static str],
+        admin: bool,
+    ) -> Result<Self, AppError> {
+        if admin {
+            return Ok(self);
+        }
+        let requested = self.fields.iter().flatten().chain(&self.include);
+        if requested
+            .into_iter()
+            .any(|name| privileged.contains(&name.as_str()))
+        {
+            return Err(AppError::Forbidden("shaping.admin_only".to_string()));
+        }
+        Ok(Self {
+            hidden: privileged,
+            ..self
+        })
+    }
+
+    pub fn includes(&self, expansion: &str) -> bool {
+        self.include.iter().any(|name| name == expansion)
+    }
+
+    /// Serializa `item` dejando solo los campos pedidos.
+    pub fn select<T: Serialize>(&self, item: &T) -> Map<String, Value> {
+        let mut object = match serde_json::to_value(item) {
+            Ok(Value::Object(object)) => object,
+            _ => Map::new(),
+        };
+        if let Some(fields) = &self.fields {
+            object.retain(|key, _| fields.iter().any(|field| field == key));
+        }
+        object.retain(|key, _| !self.hidden.contains(&key.as_str()));
+        object
+    }
+
+    /// Un usuario con los campos de `fields` y las expansiones de `include`.
+    pub fn user(&self, hit: &UserHit) -> Value {
+        let mut object = self.select(hit);
+        if self.includes("last_login") {
+            object.insert(
+                "last_login".to_string(),
+                serde_json::to_value(hit.user.last_login_at).unwrap_or(Value::Null),
+            );
+        }
+        Value::Object(object)
+    }
+}
+
+/// Nombres de una lista separada por comas, sin repetidos; los que no están en
+/// `allowed` se anotan en `errors`.
+fn names(
+    raw: &str,
+    allowed: &[&str],
+    param: &'static str,
+    errors: &mut ValidationErrors,
+) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if !allowed.contains(&name) {
+            let mut error = ValidationError::new("unknown_field")
+                .with_message(Cow::Borrowed("validation.unknown_field"));
+            error.add_param(Cow::Borrowed("field"), &name);
+            error.add_param(Cow::Borrowed("allowed"), &allowed.join(", "));
+            errors.add(param, error);
+        } else if !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::user::{Role, User, UserStatus};
+
+    fn query(fields: Option<&str>, include: Option<&str>) -> ShapeQuery {
+        ShapeQuery {
+            fields: fields.map(Into::into),
+            include: include.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn selects_fields_and_expansions() {
+        let hit = UserHit::from(User {
+            id: 7,
+            username: "ana".into(),
+            password_hash: "secreto".into(),
+            role: Role::Admin,
+            status: UserStatus::Active,
+            created_at: chrono::Utc::now(),
+            locale: None,
+            display_name: None,
+            email: None,
+            last_login_at: None,
+            version: 1,
+        });
+
+        let shape = Shape::parse(
+            &query(Some("id, username,id"), Some("last_login")),
+            USER_FIELDS,
+            USER_INCLUDES,
+        )
+        .unwrap();
+        assert_eq!(
+            shape.user(&hit),
+            serde_json::json!({ "id": 7, "username": "ana", "last_login": null })
+        );
+
+        // Sin parámetros, la forma de siempre (y nunca el hash)
+        let full = Shape::default().user(&hit);
+        assert!(full.get("role").is_some() && full.get("last_login").is_none());
+        assert!(full.get("password_hash").is_none());
+
+        let err = Shape::parse(
+            &query(Some("id,password_hash"), Some("memberships")),
+            USER_FIELDS,
+            USER_INCLUDES,
+        )
+        .unwrap_err();
+        let AppError::ValidationFields(errors) = err else {
+            panic!("se esperaba ValidationFields");
+        };
+        let errors = errors.field_errors();
+        assert_eq!(errors["fields"][0].params["field"], "password_hash");
+        assert_eq!(errors["include"][0].params["allowed"], "last_login");
+    }
+
+    #[test]
+    fn privileged_names_need_admin() {
+        let hit = UserHit::from(User {
+            id: 7,
+            username: "ana".into(),
+            password_hash: "secreto".into(),
+            role: Role::User,
+            status: UserStatus::Active,
+            created_at: chrono::Utc::now(),
+            locale: None,
+            display_name: None,
+            email: Some("ana@example.com".into()),
+            last_login_at: None,
+            version: 1,
+        });
+        let shape = |fields, include, admin| {
+            Shape::parse(&query(fields, include), USER_FIELDS, USER_INCLUDES)
+                .unwrap()
+                .restrict(USER_PRIVILEGED, admin)
+        };
+
+        // Sin pedirlos, simplemente no aparecen
+        let public = shape(None, None, false).unwrap().user(&hit);
+        assert!(public.get("email").is_none() && public.get("username").is_some());
+        for (fields, include) in [(Some("id,email"), None), (None, Some("last_login"))] {
+            assert!(matches!(
+                shape(fields, include, false),
+                Err(AppError::Forbidden(_))
+            ));
+        }
+
+        let admin = shape(Some("email"), Some("last_login"), true).unwrap();
+        assert_eq!(
+            admin.user(&hit),
+            serde_json::json!({ "email": "ana@example.com", "last_login": null })
+        );
+    }
+}
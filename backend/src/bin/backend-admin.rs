@@ -0,0 +1,523 @@
+//! CLI de administración: tareas operativas sin pasar por la API ni editar la
+//! base de datos a mano. Usa la misma configuración (`config/`, `APP_*`) y la
+//! misma capa de repositorio que el servidor.
+use backend::{
+    api::handlers::backup::CREATE_BACKUP_ACTION,
+    core::{
+        clock::SystemClock,
+        models::user::{AuditLog, CreateUserRequest, Role, User, UserSearch},
+        repository::DynUserRepository,
+        services::user::UserService,
+    },
+    data::{
+        audit_archive::verify_archives,
+        backup::{self, BackupInfo, BackupManager},
+        db::{DbPool, MigrationState, MigrationStatus},
+    },
+    error::AppError,
+    settings::Settings,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
+use validator::Validate;
+
+#[derive(Parser)]
+#[command(name = "backend-admin", about = "Tareas operativas de Sintonía 3026")]
+struct Cli {
+    /// Formato de salida
+    #[arg(long, value_enum, default_value_t = Output::Table, global = true)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Db(DbCommand),
+    /// Restaura una copia sobre la base (con el servidor detenido)
+    Restore {
+        /// Nombre del archivo tal como aparece en `backup list`
+        file_name: String,
+        /// Directorio de las copias (por defecto `backup_dir`)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+/// Los subcomandos que trabajan con la base abierta (todos menos `restore`).
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Crea un usuario (la contraseña se lee de stdin si no se indica)
+    CreateUser {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+        /// Crearlo directamente como administrador
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Da el rol admin a un usuario
+    Promote { username: String },
+    /// Devuelve un admin al rol user
+    Demote { username: String },
+    /// Sustituye la contraseña de un usuario
+    ResetPassword {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Lista usuarios con el mismo filtro y paginación que la API
+    ListUsers {
+        #[arg(long)]
+        q: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        page: i64,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Consulta y verificación de la auditoría
+    #[command(subcommand)]
+    Audit(AuditCommand),
+    /// Migraciones del esquema
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Copias de seguridad de SQLite
+    #[command(subcommand)]
+    Backup(BackupCommand),
+}
+
+#[derive(Subcommand)]
+enum BackupCommand {
+    /// Crea una copia ahora (en caliente: el servidor puede seguir activo)
+    Create,
+    /// Copias disponibles, la más reciente primero
+    List,
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Últimos registros de auditoría (más recientes primero)
+    Tail {
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: usize,
+    },
+    /// Comprueba la integridad de los archivos de auditoría
+    Verify {
+        /// Directorio de los archivos (por defecto `audit_archive_dir`)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Aplica las migraciones pendientes
+    Up {
+        /// Solo muestra qué se aplicaría
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Revierte las migraciones posteriores a `--target` con sus `.down.sql`
+    Down {
+        /// Última versión que se conserva (0 = revertir todas)
+        #[arg(long)]
+        target: i64,
+        /// Solo muestra qué se revertiría
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Estado de cada migración (falla si hay checksums distintos o la base es más nueva)
+    Status,
+}
+
+/// Resultado de un comando: filas para la tabla y valor para `--output json`.
+struct Report {
+    headers: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+    json: serde_json::Value,
+    /// `false` hace que el proceso termine con código 1 (p. ej. verify)
+    ok: bool,
+}
+
+impl Report {
+    fn new(headers: &'static [&'static str], rows: Vec<Vec<String>>, json: impl Serialize) -> Self {
+        Self {
+            headers,
+            rows,
+            json: serde_json::to_value(json).unwrap_or_default(),
+            ok: true,
+        }
+    }
+
+    fn print(&self, output: Output) {
+        match output {
+            Output::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&self.json).unwrap_or_default()
+            ),
+            Output::Table => print_table(self.headers, &self.rows),
+        }
+    }
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[&str]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    line(headers);
+    line(&separator.iter().map(String::as_str).collect::<Vec<_>>());
+    for row in rows {
+        line(&row.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+}
+
+const USER_HEADERS: &[&str] = &["id", "username", "email", "role", "locale", "created_at"];
+
+fn user_row(user: &User) -> Vec<String> {
+    vec![
+        user.id.to_string(),
+        user.username.clone(),
+        user.email.clone().unwrap_or_default(),
+        user.role.as_str().to_string(),
+        user.locale
+            .map(|l| l.as_str().to_string())
+            .unwrap_or_default(),
+        user.created_at.to_rfc3339(),
+    ]
+}
+
+fn users_report(users: Vec<User>) -> Report {
+    Report::new(USER_HEADERS, users.iter().map(user_row).collect(), users)
+}
+
+fn audit_row(log: &AuditLog) -> Vec<String> {
+    vec![
+        log.id.to_string(),
+        log.timestamp.to_rfc3339(),
+        log.admin_username.clone(),
+        log.action.clone(),
+        log.target.clone(),
+    ]
+}
+
+const MIGRATION_HEADERS: &[&str] = &["version", "description", "state", "reversible"];
+
+fn migration_row(migration: &MigrationStatus) -> Vec<String> {
+    let state = match migration.state {
+        MigrationState::Applied => "aplicada",
+        MigrationState::Pending => "pendiente",
+        MigrationState::ChecksumMismatch => "checksum distinto",
+        MigrationState::Unknown => "desconocida",
+    };
+    vec![
+        migration.version.to_string(),
+        migration.description.clone(),
+        state.to_string(),
+        if migration.reversible { "sí" } else { "no" }.to_string(),
+    ]
+}
+
+fn migrations_report(migrations: Vec<MigrationStatus>) -> Report {
+    Report::new(
+        MIGRATION_HEADERS,
+        migrations.iter().map(migration_row).collect(),
+        migrations,
+    )
+}
+
+const BACKUP_HEADERS: &[&str] = &["file_name", "size_bytes", "schema", "created_at", "sha256"];
+
+fn backups_report(backups: Vec<BackupInfo>) -> Report {
+    let rows = backups
+        .iter()
+        .map(|b| {
+            vec![
+                b.file_name.clone(),
+                b.size_bytes.to_string(),
+                b.schema_version.map(|v| v.to_string()).unwrap_or_default(),
+                b.created_at.to_rfc3339(),
+                b.sha256.clone(),
+            ]
+        })
+        .collect();
+    Report::new(BACKUP_HEADERS, rows, backups)
+}
+
+/// Quién figura en la auditoría para las acciones hechas desde la CLI.
+fn actor() -> String {
+    let user = std::env::var("USER").unwrap_or_else(|_| "desconocido".to_string());
+    format!("cli:{}", user)
+}
+
+/// Contraseña del argumento o, si falta, la primera línea de stdin. Se valida
+/// con las mismas reglas que el alta por la API.
+fn read_password(username: &str, password: Option<String>) -> Result<String, AppError> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            if io::stdin().is_terminal() {
+                eprint!("Contraseña para {}: ", username);
+                io::stderr().flush().ok();
+            }
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| AppError::Internal(format!("No se pudo leer stdin: {}", e)))?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    CreateUserRequest {
+        username: username.to_string(),
+        password: password.clone(),
+    }
+    .validate()?;
+    Ok(password)
+}
+
+async fn find_user(users: &DynUserRepository, username: &str) -> Result<User, AppError> {
+    users
+        .get_by_username(username)
+        .await?
+        .ok_or(AppError::NotFound("user.not_found".to_string()))
+}
+
+async fn set_role(
+    users: DynUserRepository,
+    username: &str,
+    role: Role,
+) -> Result<Report, AppError> {
+    let user = find_user(&users, username).await?;
+    let user = UserService::new(users)
+        .change_role(user.id, role, &actor())
+        .await?;
+    Ok(users_report(vec![user]))
+}
+
+/// No abre la base: sustituye su archivo.
+async fn restore(
+    settings: &Settings,
+    file_name: &str,
+    dir: Option<PathBuf>,
+) -> Result<Report, AppError> {
+    let dir = dir.unwrap_or_else(|| PathBuf::from(&settings.backup_dir));
+    let report = backup::restore(&settings.database_url, &dir, file_name, &SystemClock).await?;
+    let row = vec![
+        report.file_name.clone(),
+        report.previous.clone().unwrap_or_default(),
+        report.pending_migrations.to_string(),
+    ];
+    Ok(Report::new(
+        &["restored", "previous", "pending_migrations"],
+        vec![row],
+        report,
+    ))
+}
+
+async fn run(command: DbCommand, settings: &Settings, db: &DbPool) -> Result<Report, AppError> {
+    let users = db.repository();
+    match command {
+        DbCommand::CreateUser {
+            username,
+            password,
+            admin,
+        } => {
+            let password = read_password(&username, password)?;
+            let service = UserService::new(users);
+            let mut user = service.register(&username, &password).await?;
+            if admin {
+                user = service.change_role(user.id, Role::Admin, &actor()).await?;
+            }
+            Ok(users_report(vec![user]))
+        }
+        DbCommand::Promote { username } => set_role(users, &username, Role::Admin).await,
+        DbCommand::Demote { username } => set_role(users, &username, Role::User).await,
+        DbCommand::ResetPassword { username, password } => {
+            let user = find_user(&users, &username).await?;
+            let password = read_password(&username, password)?;
+            UserService::new(users)
+                .reset_password(user.id, &password, &actor())
+                .await?;
+            Ok(users_report(vec![user]))
+        }
+        DbCommand::ListUsers { q, page, limit } => {
+            let hits = UserService::new(users)
+                .search(UserSearch {
+                    q,
+                    page,
+                    limit,
+                    search_email: true,
+                    ..Default::default()
+                })
+                .await?;
+            Ok(users_report(
+                hits.items.into_iter().map(|hit| hit.user).collect(),
+            ))
+        }
+        DbCommand::Audit(AuditCommand::Tail { lines }) => {
+            let logs = users.get_audit_logs_after(None, lines as i64).await?;
+            Ok(Report::new(
+                &["id", "timestamp", "admin", "action", "target"],
+                logs.iter().map(audit_row).collect(),
+                logs,
+            ))
+        }
+        DbCommand::Audit(AuditCommand::Verify { dir }) => {
+            let dir = dir.unwrap_or_else(|| PathBuf::from(&settings.audit_archive_dir));
+            let archives = users.get_audit_archives().await?;
+            let checks = verify_archives(&dir, &archives);
+            let rows = checks
+                .iter()
+                .map(|c| {
+                    vec![
+                        c.kind.as_str().to_string(),
+                        c.file_name.clone(),
+                        c.entries.to_string(),
+                        if c.ok { "✅" } else { "❌" }.to_string(),
+                        c.error.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            let ok = checks.iter().all(|c| c.ok);
+            let mut report = Report::new(
+                &["kind", "file_name", "entries", "ok", "error"],
+                rows,
+                checks,
+            );
+            report.ok = ok;
+            Ok(report)
+        }
+        DbCommand::Migrate(MigrateCommand::Up { dry_run: true }) => {
+            db.ensure_not_ahead().await?;
+            let pending = db
+                .migration_status()
+                .await?
+                .into_iter()
+                .filter(|m| m.state == MigrationState::Pending)
+                .collect();
+            Ok(migrations_report(pending))
+        }
+        DbCommand::Migrate(MigrateCommand::Up { dry_run: false }) => {
+            db.ensure_not_ahead().await?;
+            db.migrate().await?;
+            Ok(migrations_report(db.migration_status().await?))
+        }
+        DbCommand::Migrate(MigrateCommand::Down { target, dry_run }) => {
+            let reverted = if dry_run {
+                db.revert_plan(target).await?
+            } else {
+                db.revert(target).await?
+            };
+            Ok(migrations_report(reverted))
+        }
+        DbCommand::Backup(command) => {
+            let manager = BackupManager::for_db(
+                db,
+                Arc::new(SystemClock),
+                &settings.backup_dir,
+                settings.backup_retention,
+                settings.backup_compress,
+            )
+            .ok_or(AppError::Conflict("backup.unavailable".to_string()))?;
+            match command {
+                BackupCommand::Create => {
+                    let info = manager.create().await?;
+                    users
+                        .record_audit(&actor(), CREATE_BACKUP_ACTION, &info.file_name)
+                        .await?;
+                    Ok(backups_report(vec![info]))
+                }
+                BackupCommand::List => Ok(backups_report(manager.list()?)),
+            }
+        }
+        DbCommand::Migrate(MigrateCommand::Status) => {
+            let status = db.migration_status().await?;
+            let ok = status
+                .iter()
+                .all(|m| matches!(m.state, MigrationState::Applied | MigrationState::Pending));
+            let mut report = migrations_report(status);
+            report.ok = ok;
+            Ok(report)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    // Logs a stderr para no mezclarlos con la salida (que puede ser JSON)
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
+        )
+        .with_writer(io::stderr)
+        .init();
+
+    let settings = match Settings::new() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("❌ Fallo al cargar configuración: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match cli.command {
+        Command::Restore { file_name, dir } => restore(&settings, &file_name, dir).await,
+        Command::Db(command) => {
+            match DbPool::connect_with(&settings.database_url, &settings.db_options()).await {
+                Ok(db) => {
+                    let result = run(command, &settings, &db).await;
+                    // Cerrar espera a que SQLite finalice las sentencias (y confirme
+                    // p. ej. un INSERT ... RETURNING) antes de que termine el proceso
+                    db.close().await;
+                    result
+                }
+                Err(e) => Err(e),
+            }
+        }
+    };
+
+    match result {
+        Ok(report) => {
+            report.print(cli.output);
+            if report.ok {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Cursores opacos para la paginación por keyset.
+//!
+//! Un cursor es `base64url(payload).base64url(hmac)`: el cliente no puede
+//! fabricarlo ni alterarlo, y el `scope` firmado impide reutilizar el de un
+//! listado en otro.
+use crate::core::models::user::Keyset;
+use crate::error::AppError;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Listados paginables por cursor.
+pub const USERS: &str = "users";
+pub const AUDIT_LOGS: &str = "audit_logs";
+pub const SECURITY_EVENTS: &str = "security_events";
+
+#[derive(Clone)]
+pub struct CursorKey {
+    secret: Arc<[u8]>,
+}
+
+impl CursorKey {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self, scope: &str, payload: &[u8]) -> HmacSha256 {
+        // El prefijo separa estas firmas de las de los JWT con el mismo secreto
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC acepta cualquier clave");
+        mac.update(b"cursor:");
+        mac.update(scope.as_bytes());
+        mac.update(b":");
+        mac.update(payload);
+        mac
+    }
+
+    pub fn encode(&self, scope: &str, keyset: &Keyset) -> String {
+        // Nanosegundos: el repositorio en memoria no redondea los instantes
+        let payload = format!(
+            "{}|{}",
+            keyset.at.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            keyset.id
+        );
+        let tag = self.mac(scope, payload.as_bytes()).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(tag)
+        )
+    }
+
+    /// Cursor mal formado, con otra firma o de otro listado: `validation.cursor`.
+    pub fn decode(&self, scope: &str, cursor: &str) -> Result<Keyset, AppError> {
+        let invalid = || AppError::Validation("validation.cursor".to_string());
+        let (payload, tag) = cursor.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| invalid())?;
+        self.mac(scope, &payload)
+            .verify_slice(&tag)
+            .map_err(|_| invalid())?;
+
+        let payload = String::from_utf8(payload).map_err(|_| invalid())?;
+        let (at, id) = payload.split_once('|').ok_or_else(invalid)?;
+        Ok(Keyset {
+            at: DateTime::parse_from_rfc3339(at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_and_tampering() {
+        let key = CursorKey::from_secret(b"secret");
+        let keyset = Keyset {
+            at: Utc::now(),
+            id: 42,
+        };
+        let cursor = key.encode(USERS, &keyset);
+        assert_eq!(key.decode(USERS, &cursor).unwrap(), keyset);
+
+        // Otro listado, otra clave o el payload alterado
+        assert!(key.decode(AUDIT_LOGS, &cursor).is_err());
+        assert!(CursorKey::from_secret(b"otro")
+            .decode(USERS, &cursor)
+            .is_err());
+        let (_, tag) = cursor.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode("2000-01-01T00:00:00Z|1"),
+            tag
+        );
+        assert!(key.decode(USERS, &forged).is_err());
+        assert!(key.decode(USERS, "basura").is_err());
+    }
+}
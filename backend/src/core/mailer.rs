@@ -0,0 +1,35 @@
+use crate::error::AppError;
+use async_trait::async_trait;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), AppError>;
+
+    /// Si el correo llega de verdad al destinatario. Sin eso no se invita a
+    /// nadie: la cuenta quedaría sin forma de entrar.
+    fn delivers(&self) -> bool {
+        true
+    }
+}
+
+/// No envía nada: deja el correo en los logs (desarrollo y tests).
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), AppError> {
+        tracing::info!(to = %email.to, subject = %email.subject, "📧 Correo (no enviado)");
+        Ok(())
+    }
+
+    fn delivers(&self) -> bool {
+        false
+    }
+}
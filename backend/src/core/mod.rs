@@ -1,2 +1,7 @@
+pub mod clock;
+pub mod cursor;
+pub mod mailer;
 pub mod models;
 pub mod repository;
+pub mod search;
+pub mod services;
@@ -0,0 +1,223 @@
+//! Importación y exportación masiva de usuarios.
+use super::user::{Role, UserStatus};
+use crate::error::{AppError, FieldError, ProblemDetails};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationErrors};
+
+/// Máximo de filas por importación.
+pub const MAX_IMPORT_ROWS: usize = 5000;
+/// Máximo de operaciones por lote de `POST /api/v1/users:batch`.
+pub const MAX_BATCH_OPERATIONS: u64 = 100;
+
+/// Una fila de la importación: columna del CSV o clave del objeto NDJSON.
+/// Solo `username` es obligatoria; vacío equivale a ausente.
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct ImportRow {
+    #[validate(
+        length(min = 3, message = "validation.username_length"),
+        custom(function = "super::user::username_chars")
+    )]
+    pub username: String,
+    /// Sin contraseña hace falta `invite=true` y un `email` (o, en `upsert`,
+    /// que el usuario ya exista: conserva la suya)
+    #[validate(length(min = 8, message = "validation.password_length"))]
+    pub password: Option<String>,
+    pub role: Option<Role>,
+    #[validate(length(max = 100, message = "validation.display_name_length"))]
+    pub display_name: Option<String>,
+    #[validate(email(message = "validation.email"))]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Un usuario existente es un error de la fila
+    #[default]
+    Create,
+    /// Un usuario existente se actualiza con los campos presentes
+    Upsert,
+}
+
+/// Parámetros de `POST /api/v1/admin/users/import`.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams, Validate)]
+pub struct ImportOptions {
+    /// Valida y devuelve el informe sin escribir nada
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub mode: ImportMode,
+    /// A las filas sin contraseña se les envía por email un código para
+    /// elegirla; requiere un `Mailer` que entregue de verdad
+    #[serde(default)]
+    pub invite: bool,
+}
+
+/// Fila rechazada (numeradas desde 1, sin contar la cabecera del CSV).
+#[derive(Debug)]
+pub struct RowIssue {
+    pub row: usize,
+    pub username: Option<String>,
+    pub errors: ValidationErrors,
+}
+
+/// Resultado de una importación. Con errores no se escribe ninguna fila.
+#[derive(Debug, Default)]
+pub struct ImportOutcome {
+    /// Identificador del lote en la auditoría; `None` si no se escribió nada
+    pub batch_id: Option<String>,
+    pub dry_run: bool,
+    pub rows: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub invited: usize,
+    pub issues: Vec<RowIssue>,
+}
+
+/// Errores de una fila, con el mismo formato que `ProblemDetails.errors`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub username: Option<String>,
+    pub errors: BTreeMap<String, Vec<FieldError>>,
+}
+
+/// Informe de `POST /api/v1/admin/users/import`. En `dry_run`, `created` y
+/// `updated` son lo que se haría.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportReport {
+    pub batch_id: Option<String>,
+    pub dry_run: bool,
+    pub rows: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub invited: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Parámetros de `GET /api/v1/admin/users/export`.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams, Validate)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub format: ExportFormat,
+    pub role: Option<Role>,
+    pub status: Option<UserStatus>,
+}
+
+/// Una operación de `POST /api/v1/users:batch` sobre un usuario. `version` es
+/// el `If-Match` de la operación: la versión del `ETag` leído (obligatoria
+/// con `require_if_match`).
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Delete {
+        id: i64,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+    Suspend {
+        id: i64,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+    ChangeRole {
+        id: i64,
+        role: Role,
+        #[serde(default)]
+        version: Option<i64>,
+    },
+}
+
+impl BatchOperation {
+    pub fn id(&self) -> i64 {
+        match self {
+            BatchOperation::Delete { id, .. }
+            | BatchOperation::Suspend { id, .. }
+            | BatchOperation::ChangeRole { id, .. } => *id,
+        }
+    }
+
+    pub fn version(&self) -> Option<i64> {
+        match self {
+            BatchOperation::Delete { version, .. }
+            | BatchOperation::Suspend { version, .. }
+            | BatchOperation::ChangeRole { version, .. } => *version,
+        }
+    }
+}
+
+/// Cuerpo de `POST /api/v1/users:batch`.
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct BatchRequest {
+    #[validate(length(min = 1, max = MAX_BATCH_OPERATIONS, message = "validation.batch_size"))]
+    pub operations: Vec<BatchOperation>,
+    /// Todo o nada: una sola transacción que se deshace con el primer fallo.
+    /// Sin él, cada operación se confirma por separado
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+/// Resultado de una operación del lote (`index` desde 0, en el orden recibido).
+#[derive(Debug)]
+pub struct BatchItemOutcome {
+    pub index: usize,
+    pub id: i64,
+    pub result: Result<(), AppError>,
+}
+
+/// Resultado de un lote. Con `atomic` se detiene en el primer fallo.
+#[derive(Debug)]
+pub struct BatchOutcome {
+    pub batch_id: String,
+    pub atomic: bool,
+    /// `false` si un lote atómico se deshizo
+    pub committed: bool,
+    pub items: Vec<BatchItemOutcome>,
+}
+
+/// Resultado de una operación en el informe.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub id: i64,
+    /// Código HTTP equivalente de la operación por separado; 424 si se
+    /// aplicó pero se deshizo por el fallo de otra del lote `atomic`
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProblemDetails>,
+}
+
+/// Informe de `POST /api/v1/users:batch`. Las entradas de auditoría de cada
+/// operación llevan `batch:<batch_id>` en el objetivo.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchReport {
+    pub batch_id: String,
+    pub atomic: bool,
+    /// `false` si un lote atómico falló y no se aplicó nada
+    pub committed: bool,
+    /// Operaciones aplicadas y confirmadas
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Operaciones que se aplicaron pero se deshicieron con el lote
+    pub rolled_back: usize,
+    pub results: Vec<BatchItemResult>,
+}
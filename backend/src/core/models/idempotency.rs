@@ -0,0 +1,26 @@
+//! Claves de idempotencia (`Idempotency-Key`) de las peticiones POST.
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+/// Una clave ya usada y, si la primera petición terminó, su respuesta.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    /// Método y ruta a los que pertenece la clave (`POST /api/v1/users`)
+    pub scope: String,
+    /// SHA-256 de la petición original: otra distinta con la misma clave se rechaza
+    pub fingerprint: String,
+    /// `None` mientras la primera petición está en curso
+    pub status: Option<i32>,
+    /// Cabeceras de la respuesta, una por línea (`nombre: valor`)
+    pub headers: Option<String>,
+    pub body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl IdempotencyRecord {
+    pub fn is_complete(&self) -> bool {
+        self.status.is_some()
+    }
+}
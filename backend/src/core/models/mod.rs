@@ -1 +1,4 @@
+pub mod bulk;
+pub mod idempotency;
+pub mod privacy;
 pub mod user;
@@ -0,0 +1,81 @@
+//! Exportación de datos personales y derecho de supresión.
+use super::user::{AuditLog, Role, User, UserStatus};
+use crate::i18n::Locale;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Type};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureStatus {
+    /// En periodo de gracia: el usuario aún puede cancelarla
+    Pending,
+    Cancelled,
+    Completed,
+}
+
+/// Solicitud de borrado de una cuenta (`DELETE /api/v1/me`).
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ErasureRequest {
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: i64,
+    /// Identificador que sustituye al username en la auditoría al ejecutarse
+    #[serde(skip)]
+    pub pseudonym: String,
+    pub status: ErasureStatus,
+    pub requested_at: DateTime<Utc>,
+    /// A partir de este momento se ejecuta (fin del periodo de gracia)
+    pub scheduled_for: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Perfil tal como se guarda, sin el hash de la contraseña.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProfileExport {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    pub status: UserStatus,
+    pub created_at: DateTime<Utc>,
+    pub locale: Option<Locale>,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl From<&User> for ProfileExport {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username.clone(),
+            role: user.role.clone(),
+            status: user.status,
+            created_at: user.created_at,
+            locale: user.locale,
+            display_name: user.display_name.clone(),
+            email: user.email.clone(),
+        }
+    }
+}
+
+/// Sesiones: los tokens no se guardan en el servidor (solo los revocados, sin
+/// relación con el usuario), así que lo único que conservamos es el último login.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionsExport {
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// Archivo de `GET /api/v1/me/export`: todo lo que guardamos sobre el usuario.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DataExport {
+    pub generated_at: DateTime<Utc>,
+    pub profile: ProfileExport,
+    pub sessions: SessionsExport,
+    /// Entradas de auditoría que le tienen como objetivo, de la más reciente
+    /// a la más antigua (las ya archivadas no están en la base de datos)
+    pub audit_logs: Vec<AuditLog>,
+    /// Solicitud de borrado pendiente, si la hay
+    pub erasure: Option<ErasureRequest>,
+}
@@ -1,3 +1,5 @@
+use crate::i18n::Locale;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Type};
 use utoipa::{IntoParams, ToSchema};
@@ -6,12 +8,46 @@ use validator::Validate;
 #[derive(Debug, Serialize, Deserialize, Type, Clone, PartialEq, ToSchema, Default)]
 #[sqlx(type_name = "TEXT", rename_all = "lowercase")]
 pub enum Role {
+    // Los alias admiten la forma de la columna en filtros (`?role=admin`)
+    #[serde(alias = "admin")]
     Admin,
     #[default]
+    #[serde(alias = "user")]
     User,
 }
 
-#[derive(Debug, Serialize, FromRow, ToSchema)]
+impl Role {
+    /// Valor tal como se guarda en la columna `role`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::User => "user",
+        }
+    }
+}
+
+/// Estado de la cuenta. Un usuario suspendido conserva sus datos.
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq, ToSchema, Default)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum UserStatus {
+    #[default]
+    #[serde(alias = "active")]
+    Active,
+    #[serde(alias = "suspended")]
+    Suspended,
+}
+
+impl UserStatus {
+    /// Valor tal como se guarda en la columna `status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserStatus::Active => "active",
+            UserStatus::Suspended => "suspended",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct User {
     pub id: i64,
     pub username: String,
@@ -19,18 +55,69 @@ pub struct User {
     pub password_hash: String,
     #[sqlx(default)] // Maneja casos donde la columna no existía antes (migración suave)
     pub role: Role,
-    // Usamos String por simplicidad inicial (SQLite devuelve texto)
-    pub created_at: String,
+    #[sqlx(default)]
+    pub status: UserStatus,
+    // UTC; SQLite lo guarda como texto de CURRENT_TIMESTAMP
+    pub created_at: DateTime<Utc>,
+    /// Idioma preferido; `None` = se negocia con Accept-Language
+    #[sqlx(default)]
+    pub locale: Option<Locale>,
+    /// Nombre visible; lo indexa la búsqueda junto a `username` y `email`
+    #[sqlx(default)]
+    pub display_name: Option<String>,
+    #[sqlx(default)]
+    pub email: Option<String>,
+    /// Solo se publica como expansión (`include=last_login`)
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub last_login_at: Option<DateTime<Utc>>,
+    /// Crece con cada cambio de los datos que edita un admin (no con el idioma
+    /// ni con el registro de logins); es la parte del `ETag` que comprueba `If-Match`
+    #[sqlx(default)]
+    pub version: i64,
+}
+
+/// Resultado de `GET /api/v1/users`: el usuario y, si hubo búsqueda, el
+/// fragmento que coincidió.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserHit {
+    #[serde(flatten)]
+    pub user: User,
+    /// HTML escapado con la coincidencia entre `<mark>` y `</mark>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<String>,
+}
+
+impl From<User> for UserHit {
+    fn from(user: User) -> Self {
+        Self {
+            user,
+            highlight: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateUserRequest {
-    #[validate(length(min = 3, message = "El usuario debe tener al menos 3 caracteres"))]
+    #[validate(
+        length(min = 3, message = "validation.username_length"),
+        custom(function = "username_chars")
+    )]
     pub username: String,
-    #[validate(length(min = 8, message = "La contraseña debe tener al menos 8 caracteres"))]
+    #[validate(length(min = 8, message = "validation.password_length"))]
     pub password: String,
 }
 
+/// La auditoría separa el usuario objetivo del resto con `:` (`ana:role`), así
+/// que un nombre con `:` se confundiría con las entradas de otro usuario.
+pub fn username_chars(username: &str) -> Result<(), validator::ValidationError> {
+    if username.contains(':') {
+        return Err(validator::ValidationError::new("username_chars")
+            .with_message("validation.username_chars".into()));
+    }
+    Ok(())
+}
+
 fn default_page() -> i64 {
     1
 }
@@ -38,35 +125,332 @@ fn default_limit() -> i64 {
     10
 }
 
-#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+/// Máximo de resultados por página en los listados.
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Campo por el que se ordena el listado de usuarios.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    Username,
+    CreatedAt,
+    Role,
+}
+
+impl UserSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserSort::Username => "username",
+            UserSort::CreatedAt => "created_at",
+            UserSort::Role => "role",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Parámetros de `GET /api/v1/users`. Sin `sort`, el listado va por orden de
+/// alta y la búsqueda (`q`) por relevancia.
+#[derive(Debug, Clone, Deserialize, IntoParams, ToSchema, Validate)]
 pub struct UserSearch {
     pub q: Option<String>,
     #[serde(default = "default_page")]
+    #[validate(range(min = 1, message = "validation.page"))]
     pub page: i64,
     #[serde(default = "default_limit")]
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT, message = "validation.limit"))]
     pub limit: i64,
+    pub sort: Option<UserSort>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub role: Option<Role>,
+    pub status: Option<UserStatus>,
+    /// Alta en o después de este instante (RFC 3339)
+    pub created_from: Option<DateTime<Utc>>,
+    /// Alta antes de este instante (RFC 3339)
+    pub created_to: Option<DateTime<Utc>>,
+    /// Paginación por cursor sobre `(created_at, id)`: vacío para la primera
+    /// página y después el `next_cursor` recibido. Ignora `page`.
+    pub after: Option<String>,
+    /// `q` también busca en el email. No es un parámetro de la URL: lo activa
+    /// el handler solo para los admins.
+    #[serde(skip)]
+    pub search_email: bool,
+}
+
+impl Default for UserSearch {
+    fn default() -> Self {
+        Self {
+            q: None,
+            page: default_page(),
+            limit: default_limit(),
+            sort: None,
+            order: SortOrder::default(),
+            role: None,
+            status: None,
+            created_from: None,
+            created_to: None,
+            after: None,
+            search_email: false,
+        }
+    }
+}
+
+impl UserSearch {
+    /// Texto de búsqueda sin espacios sobrantes; `None` si queda vacío.
+    pub fn text(&self) -> Option<&str> {
+        self.q.as_deref().map(str::trim).filter(|q| !q.is_empty())
+    }
+
+    /// Filas a saltar; con `page < 1` (sin validar) equivale a 0.
+    pub fn offset(&self) -> i64 {
+        ((self.page - 1) * self.limit).max(0)
+    }
+}
+
+/// Parámetros de `GET /api/v1/audit-logs`. Sin `after`, la bitácora completa.
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+pub struct AuditLogQuery {
+    /// Paginación por cursor, de la entrada más reciente a la más antigua:
+    /// vacío para la primera página y después el `next_cursor` recibido
+    pub after: Option<String>,
+    #[serde(default = "default_limit")]
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT, message = "validation.limit"))]
+    pub limit: i64,
+}
+
+/// Parámetros de `GET /api/v1/security-events`, siempre por cursor.
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+pub struct SecurityEventQuery {
+    /// Del evento más reciente al más antiguo: sin valor (o vacío) para la
+    /// primera página y después el `next_cursor` recibido
+    pub after: Option<String>,
+    #[serde(default = "default_limit")]
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT, message = "validation.limit"))]
+    pub limit: i64,
+}
+
+/// Posición en un listado ordenado por `(instante, id)`: la de la última fila
+/// entregada. A diferencia del offset, no se desplaza con inserciones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keyset {
+    pub at: DateTime<Utc>,
+    pub id: i64,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
+/// Una página por keyset; `next` es `None` en la última.
+#[derive(Debug, Clone)]
+pub struct KeysetPage<T> {
+    pub items: Vec<T>,
+    pub next: Option<Keyset>,
+}
+
+impl<T> KeysetPage<T> {
+    /// A partir de `limit + 1` filas pedidas al repositorio: la sobrante solo
+    /// indica que hay otra página, que empieza tras la última entregada.
+    pub fn from_overfetch(mut items: Vec<T>, limit: i64, key: impl Fn(&T) -> Keyset) -> Self {
+        let limit = limit.max(0) as usize;
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(key)
+        } else {
+            None
+        };
+        Self { items, next }
+    }
+}
+
+/// Una página de resultados con el total de coincidencias.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Canje del código de una invitación por la contraseña elegida.
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SetPasswordRequest {
+    pub token: String,
+    #[validate(length(min = 8, message = "validation.password_length"))]
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // Subject (Usuario)
     pub role: Role,  // Rango del usuario
     pub exp: usize,  // Expiration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Locale>, // Idioma preferido
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>, // Id del token (revocación en logout)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<i64>, // Id de la cuenta (un alta posterior con el mismo nombre no hereda la sesión)
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct UpdateLocaleRequest {
+    /// `null` vuelve a negociar el idioma con Accept-Language
+    pub locale: Option<Locale>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct UpdateProfileRequest {
+    /// `null` borra el dato
+    #[validate(length(max = 100, message = "validation.display_name_length"))]
+    pub display_name: Option<String>,
+    #[validate(email(message = "validation.email"))]
+    pub email: Option<String>,
+}
+
+/// Cambios de `PATCH /api/v1/users/{id}`: solo se aplican los campos
+/// presentes; en `display_name` y `email` un texto vacío borra el dato.
+#[derive(Debug, Default, Deserialize, Validate, ToSchema)]
+pub struct UpdateUserRequest {
+    pub role: Option<Role>,
+    pub status: Option<UserStatus>,
+    #[validate(length(max = 100, message = "validation.display_name_length"))]
+    pub display_name: Option<String>,
+    #[validate(custom(function = "empty_or_email"))]
+    pub email: Option<String>,
+}
+
+impl UpdateUserRequest {
+    pub fn is_empty(&self) -> bool {
+        self.role.is_none()
+            && self.status.is_none()
+            && self.display_name.is_none()
+            && self.email.is_none()
+    }
+
+    /// Nombres de los campos presentes (para la auditoría).
+    pub fn fields(&self) -> Vec<&'static str> {
+        [
+            ("role", self.role.is_some()),
+            ("status", self.status.is_some()),
+            ("display_name", self.display_name.is_some()),
+            ("email", self.email.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, present)| present.then_some(name))
+        .collect()
+    }
 }
 
-#[derive(Debug, Serialize, FromRow, ToSchema)]
+/// Condición `If-Match` de una escritura sobre un usuario.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// `*`: basta con que exista
+    Any,
+    /// Versiones aceptadas (los `ETag` enviados)
+    Versions(Vec<i64>),
+}
+
+impl IfMatch {
+    pub fn matches(&self, version: i64) -> bool {
+        match self {
+            IfMatch::Any => true,
+            IfMatch::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+fn empty_or_email(email: &str) -> Result<(), validator::ValidationError> {
+    if email.trim().is_empty() || validator::ValidateEmail::validate_email(&email) {
+        Ok(())
+    } else {
+        Err(validator::ValidationError::new("email").with_message("validation.email".into()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct AuditLog {
     pub id: i64,
     pub admin_username: String,
     pub action: String,
     pub target: String,
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Actor usado en la auditoría para acciones que no ejecuta un humano.
+pub const SYSTEM_ACTOR: &str = "SYSTEM";
+
+/// Acceso rechazado por un guard (`security_events`).
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct SecurityEvent {
+    pub id: i64,
+    /// Código de `AuthFailure` (p. ej. `token_expired`)
+    pub reason: String,
+    pub method: String,
+    pub path: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Tabla cuyos registros contiene un archivo de retención. Cada una tiene su
+/// propia cadena de hashes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Hash, ToSchema)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveKind {
+    AuditLogs,
+    SecurityEvents,
+}
+
+impl ArchiveKind {
+    /// Valor tal como se guarda en la columna `kind` (el nombre de la tabla).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveKind::AuditLogs => "audit_logs",
+            ArchiveKind::SecurityEvents => "security_events",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct AuditArchive {
+    pub id: i64,
+    pub kind: ArchiveKind,
+    pub file_name: String,
+    pub entries: i64,
+    /// Primer y último id archivados de la tabla de `kind`
+    pub first_log_id: i64,
+    pub last_log_id: i64,
+    pub last_hash: String,
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Datos de un archivo de auditoría ya escrito en disco, pendiente de registrar.
+#[derive(Debug)]
+pub struct NewAuditArchive {
+    pub kind: ArchiveKind,
+    pub file_name: String,
+    pub entries: i64,
+    pub first_log_id: i64,
+    pub last_log_id: i64,
+    pub last_hash: String,
+    pub sha256: String,
 }
 
 #[cfg(test)]
@@ -95,7 +479,14 @@ mod tests {
         };
         assert!(req_bad_user.validate().is_err());
 
-        // Caso 3: Password muy corto
+        // Caso 3: Usuario con `:` (separador de la auditoría)
+        let req_colon = CreateUserRequest {
+            username: "ana:x".to_string(),
+            password: "passwordSeguro123".to_string(),
+        };
+        assert!(req_colon.validate().is_err());
+
+        // Caso 4: Password muy corto
         let req_bad_pass = CreateUserRequest {
             username: "usuario_valido".to_string(),
             password: "123".to_string(),
@@ -1,17 +1,216 @@
-use crate::core::models::user::{AuditLog, User};
+use crate::core::models::idempotency::IdempotencyRecord;
+use crate::core::models::privacy::{ErasureRequest, ErasureStatus};
+use crate::core::models::user::{
+    AuditArchive, AuditLog, Keyset, NewAuditArchive, Page, Role, SecurityEvent, UpdateUserRequest,
+    User, UserHit, UserSearch,
+};
+use crate::core::search;
 use crate::error::AppError;
+use crate::i18n::Locale;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+/// Repositorio intercambiable tal como lo guarda `AppState`.
+pub type DynUserRepository = Arc<dyn UserRepository + Send + Sync>;
+
+/// Operaciones que deben aplicarse juntas (p. ej. un cambio y su auditoría).
+/// Nada es visible fuera hasta `commit`; si se descarta sin confirmar, se
+/// deshace todo.
+#[async_trait]
+pub trait UnitOfWork: Send {
+    async fn create_user(&mut self, username: &str, password_hash: &str) -> Result<User, AppError>;
+    /// Si existe al menos un usuario con rol admin.
+    async fn has_admin(&mut self) -> Result<bool, AppError>;
+    async fn get_by_id(&mut self, id: i64) -> Result<Option<User>, AppError>;
+    async fn get_by_username(&mut self, username: &str) -> Result<Option<User>, AppError>;
+    /// Devuelve `false` si no existía ningún usuario con ese id.
+    async fn delete_user(&mut self, id: i64) -> Result<bool, AppError>;
+    /// Devuelve `false` si no existía ningún usuario con ese id.
+    async fn set_role(&mut self, id: i64, role: Role) -> Result<bool, AppError>;
+    /// Devuelve `false` si no existía ningún usuario con ese id.
+    async fn set_password_hash(&mut self, id: i64, password_hash: &str) -> Result<bool, AppError>;
+    /// Bloquea la fila hasta el `commit` si su versión sigue siendo `version`
+    /// (así nadie la cambia entre la comprobación y la escritura). `false` si
+    /// cambió o el id no existe.
+    async fn lock_version(&mut self, id: i64, version: i64) -> Result<bool, AppError>;
+    /// Aplica los campos presentes en una sola escritura (un texto vacío borra
+    /// `display_name`/`email`) e incrementa la versión una vez. `None` si el id
+    /// no existe.
+    async fn update_user(
+        &mut self,
+        id: i64,
+        changes: &UpdateUserRequest,
+    ) -> Result<Option<User>, AppError> {
+        self.update_user_with_password(id, changes, None).await
+    }
+    /// Como `update_user`, sustituyendo además el hash de la contraseña si
+    /// viene, en la misma escritura (una fila de una importación en `upsert`).
+    async fn update_user_with_password(
+        &mut self,
+        id: i64,
+        changes: &UpdateUserRequest,
+        password_hash: Option<&str>,
+    ) -> Result<Option<User>, AppError>;
+    /// Abre una solicitud de borrado; si el usuario ya tiene una pendiente,
+    /// `Conflict("erasure.already_requested")`.
+    async fn request_erasure(
+        &mut self,
+        user_id: i64,
+        pseudonym: &str,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<ErasureRequest, AppError>;
+    /// Cierra con `status` la solicitud pendiente del usuario; `None` si no
+    /// tenía ninguna.
+    async fn resolve_erasure(
+        &mut self,
+        user_id: i64,
+        status: ErasureStatus,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ErasureRequest>, AppError>;
+    /// Sustituye `username` por `pseudonym` en la auditoría, como actor y como
+    /// objetivo (también en `username:...`; los nombres no admiten `:`).
+    /// Devuelve las entradas cambiadas.
+    async fn pseudonymize_audit(
+        &mut self,
+        username: &str,
+        pseudonym: &str,
+    ) -> Result<u64, AppError>;
+    async fn record_audit(
+        &mut self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError>;
+    async fn commit(self: Box<Self>) -> Result<(), AppError>;
+}
 
 #[async_trait]
 pub trait UserRepository {
+    /// Abre una unidad de trabajo transaccional.
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>, AppError>;
+    /// Comprueba que el almacenamiento responde (health check).
+    async fn ping(&self) -> Result<(), AppError>;
     async fn create_user(&self, username: &str, password_hash: &str) -> Result<User, AppError>;
     async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
-    async fn get_all(
+    async fn get_by_id(&self, id: i64) -> Result<Option<User>, AppError>;
+    /// Listado filtrado, ordenado y paginado según `params`; `q` se busca con
+    /// LIKE en `username`, `display_name` y `email`.
+    async fn get_all(&self, params: &UserSearch) -> Result<Page<User>, AppError>;
+    /// Página por keyset: los filtros de `params` (`q` con LIKE), ordenada por
+    /// `(created_at, id)` en el sentido de `params.order`, tras `after`
+    /// (excluido) y hasta `params.limit` filas. Ignora `page` y `sort`.
+    async fn get_all_after(
         &self,
-        q: Option<String>,
-        page: i64,
-        limit: i64,
+        params: &UserSearch,
+        after: Option<&Keyset>,
     ) -> Result<Vec<User>, AppError>;
-    async fn delete_user(&self, id: i64, admin_username: &str) -> Result<(), AppError>;
+    /// Como `get_all` pero con el fragmento que coincidió con `q`. Por defecto
+    /// es el LIKE de `get_all`; SQLite usa FTS5 (por relevancia salvo que se
+    /// pida otro orden con `sort`, y con coincidencia por prefijo).
+    async fn search(&self, params: &UserSearch) -> Result<Page<UserHit>, AppError> {
+        let page = self.get_all(params).await?;
+        Ok(Page {
+            items: search::like_hits(
+                page.items,
+                params.text().unwrap_or_default(),
+                params.search_email,
+            ),
+            total: page.total,
+        })
+    }
+    /// Devuelve `false` si no existía ningún usuario con ese id.
+    async fn delete_user(&self, id: i64) -> Result<bool, AppError>;
+    async fn set_locale(&self, username: &str, locale: Option<Locale>) -> Result<(), AppError>;
+    async fn set_profile(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError>;
+    /// Registra un inicio de sesión correcto.
+    async fn record_login(&self, id: i64, at: DateTime<Utc>) -> Result<(), AppError>;
+    /// Marca un token como revocado hasta su expiración (`exp`, epoch).
+    async fn revoke_token(&self, jti: &str, expires_at: i64) -> Result<(), AppError>;
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, AppError>;
+    /// Reserva `key` en `scope` hasta `expires_at` (purgando antes las claves
+    /// vencidas). `None` si quedó reservada; si ya existía y no ha vencido, el
+    /// registro existente.
+    async fn reserve_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError>;
+    /// Guarda la respuesta de una clave reservada y su nueva expiración.
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        status: i32,
+        headers: &str,
+        body: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError>;
+    /// Libera la clave para que un reintento vuelva a ejecutar la petición.
+    async fn release_idempotency_key(&self, key: &str, scope: &str) -> Result<(), AppError>;
+    /// Añade una entrada a la bitácora de auditoría.
+    async fn record_audit(
+        &self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError>;
     async fn get_audit_logs(&self) -> Result<Vec<AuditLog>, AppError>;
+    /// Bitácora de la entrada más reciente a la más antigua por
+    /// `(timestamp, id)`, tras `after` (excluido) y hasta `limit` entradas.
+    async fn get_audit_logs_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError>;
+    /// Registros anteriores a `cutoff`, ordenados por id ascendente.
+    async fn get_audit_logs_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError>;
+    /// Entradas cuyo objetivo es `username` (o `username:...`), de la más
+    /// reciente a la más antigua.
+    async fn get_audit_logs_for(&self, username: &str) -> Result<Vec<AuditLog>, AppError>;
+    /// Guarda un acceso rechazado por un guard.
+    async fn record_security_event(
+        &self,
+        reason: &str,
+        method: &str,
+        path: &str,
+    ) -> Result<(), AppError>;
+    /// Eventos de seguridad del más reciente al más antiguo por
+    /// `(timestamp, id)`, tras `after` (excluido) y hasta `limit` eventos.
+    async fn get_security_events_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError>;
+    /// Eventos de seguridad anteriores a `cutoff`, ordenados por id ascendente.
+    async fn get_security_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError>;
+    /// Todos los tipos, del más reciente al más antiguo.
+    async fn get_audit_archives(&self) -> Result<Vec<AuditArchive>, AppError>;
+    /// Registra el archivo, purga de la tabla de su `kind` los registros que
+    /// contiene y audita la operación, todo en una sola transacción.
+    async fn commit_audit_archive(
+        &self,
+        archive: NewAuditArchive,
+        cutoff: DateTime<Utc>,
+    ) -> Result<AuditArchive, AppError>;
+    async fn get_pending_erasure(&self, user_id: i64) -> Result<Option<ErasureRequest>, AppError>;
+    /// Solicitudes pendientes cuyo periodo de gracia terminó antes de `now`,
+    /// de la más antigua a la más reciente.
+    async fn get_due_erasures(&self, now: DateTime<Utc>) -> Result<Vec<ErasureRequest>, AppError>;
 }
@@ -0,0 +1,142 @@
+//! Resaltado de coincidencias en la búsqueda de usuarios.
+//!
+//! FTS5 marca el fragmento con `MARK_START`/`MARK_END` (caracteres de control
+//! que no aparecen en los datos) y aquí se convierten en `<mark>` tras escapar
+//! el resto: el resultado se puede insertar como HTML sin riesgo.
+use crate::core::models::user::{User, UserHit};
+
+pub const MARK_START: char = '\u{2}';
+pub const MARK_END: char = '\u{3}';
+
+/// Escapa `text` como HTML y sustituye los marcadores por `<mark>`.
+pub fn marked_to_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Campos donde se busca, en orden de preferencia para el resaltado; el email
+/// solo con `email` (búsquedas de admins).
+fn fields(user: &User, email: bool) -> impl Iterator<Item = &str> {
+    [
+        Some(user.username.as_str()),
+        user.display_name.as_deref(),
+        user.email.as_deref().filter(|_| email),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Patrón `%q%` para `LIKE ... ESCAPE '\'`: `%`, `_` y `\` de `q` se buscan
+/// literalmente.
+pub fn like_pattern(q: &str) -> String {
+    let mut pattern = String::with_capacity(q.len() + 2);
+    pattern.push('%');
+    for c in q.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Equivalente al `LIKE '%q%'` de SQLite (sin distinción de mayúsculas en
+/// ASCII) sobre los campos buscables.
+pub fn matches(user: &User, q: &str, email: bool) -> bool {
+    let needle = q.to_ascii_lowercase();
+    fields(user, email).any(|field| field.to_ascii_lowercase().contains(&needle))
+}
+
+/// Resaltado para los backends sin FTS: el primer campo que contiene `q`.
+pub fn highlight(user: &User, q: &str, email: bool) -> Option<String> {
+    let needle = q.to_ascii_lowercase();
+    fields(user, email).find_map(|field| {
+        // `to_ascii_lowercase` conserva las posiciones en bytes
+        let start = field.to_ascii_lowercase().find(&needle)?;
+        let end = start + needle.len();
+        Some(marked_to_html(&format!(
+            "{}{}{}{}{}",
+            &field[..start],
+            MARK_START,
+            &field[start..end],
+            MARK_END,
+            &field[end..]
+        )))
+    })
+}
+
+/// Resultados de un `LIKE` con su resaltado.
+pub fn like_hits(users: Vec<User>, q: &str, email: bool) -> Vec<UserHit> {
+    users
+        .into_iter()
+        .map(|user| UserHit {
+            highlight: highlight(&user, q, email),
+            user,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::user::{Role, UserStatus};
+
+    fn user(username: &str, email: Option<&str>) -> User {
+        User {
+            id: 1,
+            username: username.into(),
+            password_hash: String::new(),
+            role: Role::User,
+            status: UserStatus::Active,
+            created_at: chrono::Utc::now(),
+            locale: None,
+            display_name: None,
+            email: email.map(Into::into),
+            last_login_at: None,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn highlight_escapes_html_and_marks_match() {
+        assert_eq!(
+            marked_to_html("<b>\u{2}ana\u{3}</b> & co"),
+            "&lt;b&gt;<mark>ana</mark>&lt;/b&gt; &amp; co"
+        );
+
+        let ana = user("Ana<script>", Some("ana@example.com"));
+        assert_eq!(
+            highlight(&ana, "AN", false).as_deref(),
+            Some("<mark>An</mark>a&lt;script&gt;")
+        );
+        assert_eq!(
+            highlight(&ana, "example", true).as_deref(),
+            Some("ana@<mark>example</mark>.com")
+        );
+        assert!(matches(&ana, "EXAMPLE", true));
+        assert!(highlight(&ana, "zzz", true).is_none());
+        assert!(!matches(&ana, "zzz", true));
+        // Sin búsqueda por email, ni coincide ni se filtra en el resaltado
+        assert!(!matches(&ana, "example", false));
+        assert!(highlight(&ana, "example", false).is_none());
+    }
+
+    #[test]
+    fn like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern("ana"), "%ana%");
+        assert_eq!(like_pattern("10%_a\\b"), "%10\\%\\_a\\\\b%");
+    }
+}
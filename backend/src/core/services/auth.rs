@@ -0,0 +1,397 @@
+//! Casos de uso de autenticación: credenciales, emisión y validación de tokens.
+use crate::core::{
+    clock::Clock,
+    models::user::{Claims, User, UserStatus},
+    repository::DynUserRepository,
+    services::user::RESET_PASSWORD_ACTION,
+};
+use crate::error::{AppError, AuthFailure};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chrono::Duration;
+use jsonwebtoken::{
+    decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Vigencia de un token de sesión.
+pub const SESSION_TTL_HOURS: i64 = 24;
+/// Vigencia del código para elegir contraseña que llega con una invitación.
+pub const PASSWORD_TOKEN_TTL_HOURS: i64 = 72;
+
+const SET_PASSWORD_PURPOSE: &str = "set_password";
+
+/// Claims del código para elegir contraseña. No sirven como sesión (no hay
+/// `sub` ni `role`) y `pwd` resume el hash guardado al emitirlo: en cuanto la
+/// contraseña cambia, el código deja de valer.
+#[derive(Serialize, Deserialize)]
+struct PasswordClaims {
+    uid: i64,
+    purpose: String,
+    pwd: String,
+    exp: usize,
+}
+
+fn hash_fingerprint(password_hash: &str) -> String {
+    hex::encode(&Sha256::digest(password_hash.as_bytes())[..8])
+}
+
+/// Claves para firmar y validar los tokens de sesión.
+#[derive(Clone)]
+pub struct JwtKeys {
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+}
+
+/// Decodifica y valida (firma y expiración) un token de sesión.
+pub fn decode_token(keys: &JwtKeys, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    decode::<Claims>(token, &keys.decoding, &Validation::default()).map(|data| data.claims)
+}
+
+/// Hash Argon2 con salt aleatorio, listo para guardar en `password_hash`.
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| {
+            tracing::error!("❌ Fallo al generar hash: {}", e);
+            AppError::AuthError("auth.hash_failed".to_string())
+        })
+}
+
+/// Identificador único del token (`jti`) para poder revocarlo.
+fn new_token_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Sesión recién abierta: el token firmado y el usuario que la abrió.
+pub struct Session {
+    pub token: String,
+    pub user: User,
+}
+
+/// Sesión validada: los claims del token y la cuenta tal como está ahora en
+/// el repositorio.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub claims: Claims,
+    pub user: User,
+}
+
+pub struct AuthService {
+    users: DynUserRepository,
+    keys: JwtKeys,
+    clock: Arc<dyn Clock>,
+}
+
+impl AuthService {
+    pub fn new(users: DynUserRepository, keys: JwtKeys, clock: Arc<dyn Clock>) -> Self {
+        Self { users, keys, clock }
+    }
+
+    /// Verifica las credenciales y emite un token de `SESSION_TTL_HOURS`.
+    /// Usuario inexistente y contraseña errónea dan el mismo error; una cuenta
+    /// suspendida (con la contraseña correcta) es 403.
+    pub async fn login(&self, username: &str, password: &str) -> Result<Session, AppError> {
+        let user = self
+            .users
+            .get_by_username(username)
+            .await?
+            .ok_or(AppError::AuthError("auth.invalid_credentials".to_string()))?;
+
+        let parsed_hash = PasswordHash::new(&user.password_hash)
+            .map_err(|_| AppError::AuthError("auth.credential_check_failed".to_string()))?;
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .map_err(|_| AppError::AuthError("auth.invalid_credentials".to_string()))?;
+        if user.status == UserStatus::Suspended {
+            return Err(AppError::Guard(AuthFailure::SuspendedAccount));
+        }
+
+        let expiration = self
+            .clock
+            .now()
+            .checked_add_signed(Duration::hours(SESSION_TTL_HOURS))
+            .expect("Tiempo inválido")
+            .timestamp();
+        let claims = Claims {
+            sub: user.username.clone(),
+            role: user.role.clone(),
+            exp: expiration as usize,
+            locale: user.locale,
+            jti: Some(new_token_id()),
+            uid: Some(user.id),
+        };
+        let token = self.issue(&claims)?;
+        self.users.record_login(user.id, self.clock.now()).await?;
+        Ok(Session { token, user })
+    }
+
+    /// Revoca el token hasta su expiración. Un token ilegible o sin `jti`
+    /// no tiene nada que revocar.
+    pub async fn logout(&self, token: &str) -> Result<(), AppError> {
+        if let Ok(claims) = decode_token(&self.keys, token) {
+            if let Some(jti) = &claims.jti {
+                self.users.revoke_token(jti, claims.exp as i64).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Firma unos claims (p. ej. para re-emitir la sesión con otra preferencia).
+    pub fn issue(&self, claims: &Claims) -> Result<String, AppError> {
+        encode(&Header::default(), claims, &self.keys.encoding)
+            .map_err(|_| AppError::AuthError("auth.token_failed".to_string()))
+    }
+
+    /// Código de un solo uso con el que `user` elige su contraseña (lo recibe
+    /// en la invitación de una importación).
+    pub fn password_token(&self, user: &User) -> Result<String, AppError> {
+        let expiration = self
+            .clock
+            .now()
+            .checked_add_signed(Duration::hours(PASSWORD_TOKEN_TTL_HOURS))
+            .expect("Tiempo inválido")
+            .timestamp();
+        let claims = PasswordClaims {
+            uid: user.id,
+            purpose: SET_PASSWORD_PURPOSE.to_string(),
+            pwd: hash_fingerprint(&user.password_hash),
+            exp: expiration as usize,
+        };
+        encode(&Header::default(), &claims, &self.keys.encoding)
+            .map_err(|_| AppError::AuthError("auth.token_failed".to_string()))
+    }
+
+    /// Canjea un código de `password_token`: guarda la nueva contraseña y lo
+    /// audita. Caducado, ya usado o ilegible dan el mismo error.
+    pub async fn set_password(&self, token: &str, password: &str) -> Result<(), AppError> {
+        let invalid = || AppError::AuthError("auth.password_token_invalid".to_string());
+        let claims = decode::<PasswordClaims>(token, &self.keys.decoding, &Validation::default())
+            .map_err(|_| invalid())?
+            .claims;
+        if claims.purpose != SET_PASSWORD_PURPOSE {
+            return Err(invalid());
+        }
+        let user = self
+            .users
+            .get_by_id(claims.uid)
+            .await?
+            .filter(|user| hash_fingerprint(&user.password_hash) == claims.pwd)
+            .ok_or_else(invalid)?;
+
+        let password_hash = hash_password(password)?;
+        let mut uow = self.users.begin().await?;
+        uow.set_password_hash(user.id, &password_hash).await?;
+        uow.record_audit(&user.username, RESET_PASSWORD_ACTION, &user.username)
+            .await?;
+        uow.commit().await
+    }
+
+    /// Valida firma, expiración y revocación de un token, y que la cuenta siga
+    /// existiendo y no esté suspendida: el borrado y la suspensión cortan
+    /// también las sesiones ya abiertas. El rol de los claims devueltos es el
+    /// guardado, no el copiado en el token al hacer login.
+    pub async fn authenticate(&self, token: &str) -> Result<Principal, AuthFailure> {
+        let mut claims = decode_token(&self.keys, token).map_err(|e| match e.kind() {
+            ErrorKind::ExpiredSignature => AuthFailure::Expired,
+            ErrorKind::InvalidSignature => AuthFailure::InvalidSignature,
+            _ => AuthFailure::InvalidToken,
+        })?;
+
+        if let Some(jti) = &claims.jti {
+            // Si la DB falla no dejamos pasar: la sesión podría estar revocada
+            let revoked = self
+                .users
+                .is_token_revoked(jti)
+                .await
+                .map_err(|_| AuthFailure::InvalidToken)?;
+            if revoked {
+                return Err(AuthFailure::RevokedSession);
+            }
+        }
+        let user = self
+            .users
+            .get_by_username(&claims.sub)
+            .await
+            .map_err(|_| AuthFailure::InvalidToken)?
+            // Con `uid`, un alta posterior con el mismo nombre es otra cuenta
+            .filter(|user| claims.uid.is_none_or(|uid| uid == user.id))
+            .ok_or(AuthFailure::RevokedSession)?;
+        if user.status == UserStatus::Suspended {
+            return Err(AuthFailure::SuspendedAccount);
+        }
+        claims.role = user.role.clone();
+        Ok(Principal { claims, user })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::FixedClock;
+    use crate::core::models::user::Role;
+    use crate::data::memory_repository::InMemoryRepository;
+    use chrono::Utc;
+
+    fn service(clock: FixedClock) -> AuthService {
+        let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+        AuthService::new(users, JwtKeys::from_secret(b"test"), Arc::new(clock))
+    }
+
+    async fn with_user(service: &AuthService) {
+        let hash = hash_password("secreto123").unwrap();
+        service.users.create_user("ana", &hash).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn login_issues_a_valid_session() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+
+        let session = auth.login("ana", "secreto123").await.unwrap();
+        assert_eq!(session.user.username, "ana");
+
+        let principal = auth.authenticate(&session.token).await.unwrap();
+        assert_eq!(principal.claims.sub, "ana");
+        assert_eq!(principal.claims.uid, Some(session.user.id));
+        assert!(principal.claims.jti.is_some());
+    }
+
+    #[tokio::test]
+    async fn login_rejects_bad_credentials_alike() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+
+        for (user, password) in [("ana", "incorrecta"), ("nadie", "secreto123")] {
+            match auth.login(user, password).await {
+                Err(AppError::AuthError(key)) => assert_eq!(key, "auth.invalid_credentials"),
+                _ => panic!("{} debería fallar con credenciales inválidas", user),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn clock_drives_expiry_and_logout_revokes() {
+        let stale = service(FixedClock(Utc::now() - Duration::days(2)));
+        with_user(&stale).await;
+        let session = stale.login("ana", "secreto123").await.unwrap();
+        assert_eq!(
+            stale.authenticate(&session.token).await.unwrap_err(),
+            AuthFailure::Expired
+        );
+
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+        let session = auth.login("ana", "secreto123").await.unwrap();
+        auth.logout(&session.token).await.unwrap();
+        assert_eq!(
+            auth.authenticate(&session.token).await.unwrap_err(),
+            AuthFailure::RevokedSession
+        );
+    }
+
+    #[tokio::test]
+    async fn suspension_blocks_login_and_open_sessions() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+        let session = auth.login("ana", "secreto123").await.unwrap();
+
+        let mut uow = auth.users.begin().await.unwrap();
+        let suspend = crate::core::models::user::UpdateUserRequest {
+            status: Some(UserStatus::Suspended),
+            ..Default::default()
+        };
+        uow.update_user(session.user.id, &suspend).await.unwrap();
+        uow.commit().await.unwrap();
+
+        assert!(matches!(
+            auth.login("ana", "secreto123").await,
+            Err(AppError::Guard(AuthFailure::SuspendedAccount))
+        ));
+        // Con la contraseña mal no se revela que la cuenta existe
+        assert!(matches!(
+            auth.login("ana", "incorrecta").await,
+            Err(AppError::AuthError(_))
+        ));
+        assert_eq!(
+            auth.authenticate(&session.token).await.unwrap_err(),
+            AuthFailure::SuspendedAccount
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_accounts_lose_their_sessions() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+        let session = auth.login("ana", "secreto123").await.unwrap();
+        auth.users.delete_user(session.user.id).await.unwrap();
+        assert_eq!(
+            auth.authenticate(&session.token).await.unwrap_err(),
+            AuthFailure::RevokedSession
+        );
+
+        // Otra cuenta con el mismo nombre no hereda la sesión
+        with_user(&auth).await;
+        assert_eq!(
+            auth.authenticate(&session.token).await.unwrap_err(),
+            AuthFailure::RevokedSession
+        );
+    }
+
+    #[tokio::test]
+    async fn role_changes_apply_to_open_sessions() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+        let session = auth.login("ana", "secreto123").await.unwrap();
+
+        let mut uow = auth.users.begin().await.unwrap();
+        uow.set_role(session.user.id, Role::Admin).await.unwrap();
+        uow.commit().await.unwrap();
+
+        let principal = auth.authenticate(&session.token).await.unwrap();
+        assert_eq!(principal.user.role, Role::Admin);
+        assert_eq!(principal.claims.role, Role::Admin);
+    }
+
+    #[tokio::test]
+    async fn password_tokens_work_once_and_are_not_sessions() {
+        let auth = service(FixedClock(Utc::now()));
+        with_user(&auth).await;
+        let user = auth.users.get_by_username("ana").await.unwrap().unwrap();
+        let token = auth.password_token(&user).unwrap();
+        assert_eq!(
+            auth.authenticate(&token).await.unwrap_err(),
+            AuthFailure::InvalidToken
+        );
+
+        auth.set_password(&token, "elegida-123").await.unwrap();
+        assert!(auth.login("ana", "elegida-123").await.is_ok());
+        assert!(matches!(
+            auth.set_password(&token, "otra-vez-123").await,
+            Err(AppError::AuthError(key)) if key == "auth.password_token_invalid"
+        ));
+        let session = auth.login("ana", "elegida-123").await.unwrap();
+        assert!(auth
+            .set_password(&session.token, "otra-vez-123")
+            .await
+            .is_err());
+    }
+}
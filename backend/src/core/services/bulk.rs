@@ -0,0 +1,465 @@
+//! Alta masiva de usuarios (filas ya leídas del CSV/NDJSON), exportación por
+//! lotes y operaciones en lote sobre usuarios existentes. Cada importación o
+//! exportación queda auditada como una sola acción; en los lotes de
+//! operaciones, una entrada por operación enlazada con `batch:<id>`.
+use crate::core::{
+    mailer::{Email, Mailer},
+    models::{
+        bulk::{
+            BatchItemOutcome, BatchOperation, BatchOutcome, BatchRequest, ExportOptions,
+            ImportMode, ImportOptions, ImportOutcome, ImportRow, RowIssue, MAX_IMPORT_ROWS,
+        },
+        user::{IfMatch, Keyset, KeysetPage, UpdateUserRequest, User, UserSearch, UserStatus},
+    },
+    repository::{DynUserRepository, UnitOfWork},
+    services::auth::{hash_password, AuthService, PASSWORD_TOKEN_TTL_HOURS},
+    services::user::{self, CHANGE_ROLE_ACTION, DELETE_USER_ACTION, SUSPEND_USER_ACTION},
+};
+use crate::error::AppError;
+use crate::i18n::{self, Locale};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// Acción auditada por cada importación confirmada.
+pub const IMPORT_USERS_ACTION: &str = "IMPORT_USERS";
+/// Acción auditada por cada exportación.
+pub const EXPORT_USERS_ACTION: &str = "EXPORT_USERS";
+/// Usuarios por consulta al exportar.
+pub const EXPORT_CHUNK: i64 = 500;
+/// Longitud de la contraseña aleatoria de una cuenta invitada hasta que elige
+/// la suya (no se envía a nadie).
+const INVITE_PASSWORD_LEN: usize = 32;
+
+/// Identificador de lote para la auditoría.
+pub fn new_batch_id() -> String {
+    let mut bytes = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Una operación del lote dentro de `uow`, con su entrada de auditoría. La
+/// versión se exige y comprueba como en `PATCH`/`DELETE` de un usuario.
+async fn apply_operation(
+    uow: &mut dyn UnitOfWork,
+    operation: &BatchOperation,
+    require_if_match: bool,
+    batch_id: &str,
+    admin_username: &str,
+) -> Result<(), AppError> {
+    let if_match = match operation.version() {
+        Some(version) => Some(IfMatch::Versions(vec![version])),
+        None if require_if_match => {
+            return Err(AppError::PreconditionRequired(
+                "precondition.if_match_required".to_string(),
+            ))
+        }
+        None => None,
+    };
+    let id = operation.id();
+    let (action, target) = match operation {
+        BatchOperation::Delete { .. } => {
+            let user = user::delete_in(uow, id, if_match.as_ref())
+                .await?
+                .ok_or(AppError::NotFound("user.not_found".to_string()))?;
+            (DELETE_USER_ACTION, user.username)
+        }
+        BatchOperation::Suspend { .. } => {
+            let changes = UpdateUserRequest {
+                status: Some(UserStatus::Suspended),
+                ..Default::default()
+            };
+            let (user, _) = user::update_in(uow, id, &changes, if_match.as_ref()).await?;
+            (SUSPEND_USER_ACTION, user.username)
+        }
+        BatchOperation::ChangeRole { role, .. } => {
+            let changes = UpdateUserRequest {
+                role: Some(role.clone()),
+                ..Default::default()
+            };
+            let (user, _) = user::update_in(uow, id, &changes, if_match.as_ref()).await?;
+            let target = format!("{}:{}", user.username, role.as_str());
+            (CHANGE_ROLE_ACTION, target)
+        }
+    };
+    // Con `:` tras el username para que la exportación y el borrado RGPD la encuentren
+    let target = format!("{}:batch:{}", target, batch_id);
+    uow.record_audit(admin_username, action, &target).await
+}
+
+/// Fila validada y lista para escribir.
+struct Planned {
+    row: ImportRow,
+    existing: Option<User>,
+    password_hash: Option<String>,
+    /// Alta sin contraseña: recibe por email un código para elegirla
+    invited: bool,
+}
+
+pub struct BulkService {
+    users: DynUserRepository,
+    mailer: Arc<dyn Mailer>,
+    auth: AuthService,
+    require_if_match: bool,
+}
+
+impl BulkService {
+    pub fn new(
+        users: DynUserRepository,
+        mailer: Arc<dyn Mailer>,
+        auth: AuthService,
+        require_if_match: bool,
+    ) -> Self {
+        Self {
+            users,
+            mailer,
+            auth,
+            require_if_match,
+        }
+    }
+
+    /// Valida todas las filas (`Err` = fila ilegible, con el motivo) y, si
+    /// ninguna falla y no es `dry_run`, las escribe en una sola transacción
+    /// auditada como un lote; cada cambio de rol de un usuario existente lleva
+    /// además su `CHANGE_ROLE`. Con algún error no se escribe nada.
+    pub async fn import(
+        &self,
+        rows: Vec<Result<ImportRow, String>>,
+        options: &ImportOptions,
+        admin_username: &str,
+    ) -> Result<ImportOutcome, AppError> {
+        if rows.is_empty() {
+            return Err(AppError::Validation("import.empty".to_string()));
+        }
+        if rows.len() > MAX_IMPORT_ROWS {
+            return Err(AppError::Validation("import.too_many_rows".to_string()));
+        }
+        if options.invite && !self.mailer.delivers() {
+            return Err(AppError::Validation(
+                "import.invite_without_mailer".to_string(),
+            ));
+        }
+
+        let mut outcome = ImportOutcome {
+            dry_run: options.dry_run,
+            rows: rows.len(),
+            ..Default::default()
+        };
+        let mut plan = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, row) in rows.into_iter().enumerate() {
+            let number = index + 1;
+            let row = match row {
+                Ok(row) => normalize(row),
+                Err(detail) => {
+                    let mut errors = ValidationErrors::new();
+                    let mut error = issue("parse", "import.invalid_row");
+                    error.add_param(Cow::Borrowed("detail"), &detail);
+                    errors.add("row", error);
+                    outcome.issues.push(RowIssue {
+                        row: number,
+                        username: None,
+                        errors,
+                    });
+                    continue;
+                }
+            };
+
+            let mut errors = row.validate().err().unwrap_or_default();
+            if !seen.insert(row.username.clone()) {
+                errors.add("username", issue("duplicate", "import.duplicate_username"));
+            }
+            let existing = self.users.get_by_username(&row.username).await?;
+            match &existing {
+                Some(_) if options.mode == ImportMode::Create => {
+                    errors.add("username", issue("conflict", "user.username_taken"));
+                }
+                None if row.password.is_none() && !options.invite => {
+                    errors.add("password", issue("required", "import.password_required"));
+                }
+                None if row.password.is_none() && row.email.is_none() => {
+                    errors.add("email", issue("required", "import.email_required"));
+                }
+                _ => {}
+            }
+            if !errors.is_empty() {
+                outcome.issues.push(RowIssue {
+                    row: number,
+                    username: Some(row.username),
+                    errors,
+                });
+                continue;
+            }
+
+            match &existing {
+                Some(_) => outcome.updated += 1,
+                None if row.password.is_none() => {
+                    outcome.created += 1;
+                    outcome.invited += 1;
+                }
+                None => outcome.created += 1,
+            }
+            plan.push(Planned {
+                row,
+                existing,
+                password_hash: None,
+                invited: false,
+            });
+        }
+        if options.dry_run || !outcome.issues.is_empty() {
+            return Ok(outcome);
+        }
+
+        // Argon2 es caro: cientos de hashes fuera del runtime y antes de abrir
+        // la transacción (que bloquea las escrituras mientras dura)
+        let plan = tokio::task::spawn_blocking(move || hash_plan(plan))
+            .await
+            .map_err(|e| AppError::Internal(format!("Hash de la importación: {}", e)))??;
+
+        let batch_id = new_batch_id();
+        let mut invitations = Vec::new();
+        let mut uow = self.users.begin().await?;
+        for planned in &plan {
+            let row = &planned.row;
+            // Otra vez dentro de la transacción: si alguien dio de alta o borró
+            // el usuario desde la validación, no se aplica nada
+            let existing = uow.get_by_username(&row.username).await?;
+            if existing.is_some() != planned.existing.is_some() {
+                return Err(AppError::Conflict("import.concurrent_change".to_string()));
+            }
+            let existed = existing.is_some();
+            let (user, password_hash) = match existing {
+                Some(user) => (user, planned.password_hash.as_deref()),
+                None => {
+                    let hash = planned.password_hash.as_deref().ok_or_else(|| {
+                        AppError::Internal(format!("Fila sin contraseña: {}", row.username))
+                    })?;
+                    (uow.create_user(&row.username, hash).await?, None)
+                }
+            };
+
+            // Contraseña, rol y perfil en una sola escritura: la versión sube una vez
+            let changes = UpdateUserRequest {
+                role: row.role.clone().filter(|role| *role != user.role),
+                display_name: row.display_name.clone(),
+                email: row.email.clone(),
+                ..Default::default()
+            };
+            if !changes.is_empty() || password_hash.is_some() {
+                uow.update_user_with_password(user.id, &changes, password_hash)
+                    .await?;
+            }
+            if let Some(role) = changes.role.as_ref().filter(|_| existed) {
+                // Ascender o degradar una cuenta existente queda auditado por
+                // separado, como en `users:batch`
+                let target = format!("{}:{}:batch:{}", user.username, role.as_str(), batch_id);
+                uow.record_audit(admin_username, CHANGE_ROLE_ACTION, &target)
+                    .await?;
+            }
+            if let (true, Some(to)) = (planned.invited, &row.email) {
+                invitations.push((user, to.clone()));
+            }
+        }
+        uow.record_audit(
+            admin_username,
+            IMPORT_USERS_ACTION,
+            &format!(
+                "batch:{} created={} updated={}",
+                batch_id, outcome.created, outcome.updated
+            ),
+        )
+        .await?;
+        uow.commit().await?;
+        tracing::info!(
+            "📥 Importación {}: {} altas, {} actualizaciones",
+            batch_id,
+            outcome.created,
+            outcome.updated
+        );
+
+        // Tras confirmar: un correo fallido no deshace el alta
+        for (user, to) in invitations {
+            let email = invitation(&user.username, &to, &self.auth.password_token(&user)?);
+            if let Err(e) = self.mailer.send(email).await {
+                tracing::warn!("⚠️ Invitación no enviada a {}: {}", user.username, e);
+            }
+        }
+        outcome.batch_id = Some(batch_id);
+        Ok(outcome)
+    }
+
+    /// Aplica las operaciones en orden, cada una auditada con su acción y
+    /// `batch:<id>` en el objetivo. Con `atomic`, en una sola transacción que
+    /// se deshace (auditoría incluida) con el primer fallo; sin él, cada una
+    /// se confirma por separado y un fallo no detiene las demás.
+    pub async fn apply_batch(
+        &self,
+        request: &BatchRequest,
+        admin_username: &str,
+    ) -> Result<BatchOutcome, AppError> {
+        request.validate()?;
+        let batch_id = new_batch_id();
+        let mut items = Vec::with_capacity(request.operations.len());
+
+        if request.atomic {
+            let mut uow = self.users.begin().await?;
+            for (index, operation) in request.operations.iter().enumerate() {
+                let result = apply_operation(
+                    uow.as_mut(),
+                    operation,
+                    self.require_if_match,
+                    &batch_id,
+                    admin_username,
+                )
+                .await;
+                let failed = result.is_err();
+                items.push(BatchItemOutcome {
+                    index,
+                    id: operation.id(),
+                    result,
+                });
+                if failed {
+                    return Ok(BatchOutcome {
+                        batch_id,
+                        atomic: true,
+                        committed: false,
+                        items,
+                    });
+                }
+            }
+            uow.commit().await?;
+        } else {
+            for (index, operation) in request.operations.iter().enumerate() {
+                let result = async {
+                    let mut uow = self.users.begin().await?;
+                    apply_operation(
+                        uow.as_mut(),
+                        operation,
+                        self.require_if_match,
+                        &batch_id,
+                        admin_username,
+                    )
+                    .await?;
+                    uow.commit().await
+                }
+                .await;
+                items.push(BatchItemOutcome {
+                    index,
+                    id: operation.id(),
+                    result,
+                });
+            }
+        }
+
+        let applied = items.iter().filter(|item| item.result.is_ok()).count();
+        tracing::info!(
+            "🧺 Lote {}: {} de {} operaciones aplicadas",
+            batch_id,
+            applied,
+            items.len()
+        );
+        Ok(BatchOutcome {
+            batch_id,
+            atomic: request.atomic,
+            committed: true,
+            items,
+        })
+    }
+
+    /// Registra la exportación en la auditoría y devuelve el id del lote.
+    pub async fn start_export(
+        &self,
+        options: &ExportOptions,
+        admin_username: &str,
+    ) -> Result<String, AppError> {
+        let batch_id = new_batch_id();
+        self.users
+            .record_audit(
+                admin_username,
+                EXPORT_USERS_ACTION,
+                &format!("batch:{} format={}", batch_id, options.format.as_str()),
+            )
+            .await?;
+        Ok(batch_id)
+    }
+
+    /// Siguiente bloque de la exportación, por orden de alta.
+    pub async fn export_chunk(
+        &self,
+        options: &ExportOptions,
+        after: Option<Keyset>,
+    ) -> Result<KeysetPage<User>, AppError> {
+        let params = UserSearch {
+            role: options.role.clone(),
+            status: options.status,
+            limit: EXPORT_CHUNK + 1,
+            ..Default::default()
+        };
+        let users = self.users.get_all_after(&params, after.as_ref()).await?;
+        Ok(KeysetPage::from_overfetch(users, EXPORT_CHUNK, |u| {
+            Keyset {
+                at: u.created_at,
+                id: u.id,
+            }
+        }))
+    }
+}
+
+fn issue(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Borrowed(message))
+}
+
+/// Espacios sobrantes fuera y celdas vacías como ausentes (en CSV no hay `null`).
+fn normalize(row: ImportRow) -> ImportRow {
+    let clean = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    ImportRow {
+        username: row.username.trim().to_string(),
+        password: row.password.filter(|p| !p.is_empty()),
+        role: row.role,
+        display_name: clean(row.display_name),
+        email: clean(row.email),
+    }
+}
+
+fn hash_plan(plan: Vec<Planned>) -> Result<Vec<Planned>, AppError> {
+    plan.into_iter()
+        .map(|mut planned| {
+            let mut password = planned.row.password.clone();
+            if password.is_none() && planned.existing.is_none() {
+                password = Some(
+                    rand::thread_rng()
+                        .sample_iter(&Alphanumeric)
+                        .take(INVITE_PASSWORD_LEN)
+                        .map(char::from)
+                        .collect(),
+                );
+                planned.invited = true;
+            }
+            planned.password_hash = password.map(|p| hash_password(&p)).transpose()?;
+            Ok(planned)
+        })
+        .collect()
+}
+
+fn invitation(username: &str, to: &str, token: &str) -> Email {
+    let locale = Locale::default();
+    Email {
+        to: to.to_string(),
+        subject: i18n::t(locale, "invite.subject"),
+        body: i18n::t_with(
+            locale,
+            "invite.body",
+            &[
+                ("username", username.to_string()),
+                ("token", token.to_string()),
+                ("hours", PASSWORD_TOKEN_TTL_HOURS.to_string()),
+            ],
+        ),
+    }
+}
@@ -0,0 +1,239 @@
+//! Derechos sobre los datos personales: exportación y supresión de la cuenta.
+//!
+//! El borrado no es inmediato: la solicitud queda pendiente durante el periodo
+//! de gracia (cancelable) y después `run_due` borra el usuario. La auditoría
+//! no se pierde ni se rompe: sus referencias al username pasan a un seudónimo
+//! aleatorio. Los archivos ya generados por la retención no se reescriben
+//! (están encadenados por hash).
+use crate::core::{
+    clock::Clock,
+    models::{
+        privacy::{DataExport, ErasureRequest, ErasureStatus, ProfileExport, SessionsExport},
+        user::{User, SYSTEM_ACTOR},
+    },
+    repository::DynUserRepository,
+};
+use crate::error::AppError;
+use chrono::Duration;
+use rand::RngCore;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Acción auditada al solicitar el borrado de la propia cuenta.
+pub const REQUEST_ERASURE_ACTION: &str = "REQUEST_ERASURE";
+/// Acción auditada al cancelar la solicitud durante el periodo de gracia.
+pub const CANCEL_ERASURE_ACTION: &str = "CANCEL_ERASURE";
+/// Acción auditada (como `SYSTEM`, con el seudónimo) al ejecutar el borrado.
+pub const ERASE_USER_ACTION: &str = "ERASE_USER";
+
+/// Seudónimo que sustituye al username en la auditoría: aleatorio, para que
+/// no se pueda derivar del usuario ni de su id.
+fn new_pseudonym() -> String {
+    let mut bytes = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("anon-{}", hex::encode(bytes))
+}
+
+pub struct PrivacyService {
+    users: DynUserRepository,
+    clock: Arc<dyn Clock>,
+    grace_days: i64,
+}
+
+impl PrivacyService {
+    pub fn new(users: DynUserRepository, clock: Arc<dyn Clock>, grace_days: i64) -> Self {
+        Self {
+            users,
+            clock,
+            grace_days,
+        }
+    }
+
+    async fn user(&self, username: &str) -> Result<User, AppError> {
+        self.users
+            .get_by_username(username)
+            .await?
+            .ok_or(AppError::NotFound("user.not_found".to_string()))
+    }
+
+    /// Todo lo que guardamos sobre el usuario.
+    pub async fn export(&self, username: &str) -> Result<DataExport, AppError> {
+        let user = self.user(username).await?;
+        Ok(DataExport {
+            generated_at: self.clock.now(),
+            profile: ProfileExport::from(&user),
+            sessions: SessionsExport {
+                last_login_at: user.last_login_at,
+            },
+            audit_logs: self.users.get_audit_logs_for(username).await?,
+            erasure: self.users.get_pending_erasure(user.id).await?,
+        })
+    }
+
+    /// La solicitud pendiente del usuario, si la hay.
+    pub async fn pending_erasure(&self, username: &str) -> Result<ErasureRequest, AppError> {
+        let user = self.user(username).await?;
+        self.users
+            .get_pending_erasure(user.id)
+            .await?
+            .ok_or(AppError::NotFound("erasure.not_found".to_string()))
+    }
+
+    /// Programa el borrado para dentro de `grace_days` y lo audita. Con una
+    /// solicitud ya pendiente, `Conflict`.
+    pub async fn request_erasure(&self, username: &str) -> Result<ErasureRequest, AppError> {
+        let user = self.user(username).await?;
+        let scheduled_for = self.clock.now() + Duration::days(self.grace_days);
+        let mut uow = self.users.begin().await?;
+        let request = uow
+            .request_erasure(user.id, &new_pseudonym(), scheduled_for)
+            .await?;
+        uow.record_audit(username, REQUEST_ERASURE_ACTION, username)
+            .await?;
+        uow.commit().await?;
+        Ok(request)
+    }
+
+    /// Anula la solicitud pendiente (solo durante el periodo de gracia: después
+    /// ya no existe el usuario).
+    pub async fn cancel_erasure(&self, username: &str) -> Result<ErasureRequest, AppError> {
+        let user = self.user(username).await?;
+        let mut uow = self.users.begin().await?;
+        let request = uow
+            .resolve_erasure(user.id, ErasureStatus::Cancelled, self.clock.now())
+            .await?
+            .ok_or(AppError::NotFound("erasure.not_found".to_string()))?;
+        uow.record_audit(username, CANCEL_ERASURE_ACTION, username)
+            .await?;
+        uow.commit().await?;
+        Ok(request)
+    }
+
+    /// Ejecuta las solicitudes cuyo periodo de gracia terminó: en una
+    /// transacción por usuario, seudonimiza la auditoría, borra la cuenta y
+    /// cierra la solicitud. Devuelve las ejecutadas.
+    pub async fn run_due(&self) -> Result<Vec<ErasureRequest>, AppError> {
+        let now = self.clock.now();
+        let mut done = Vec::new();
+        for request in self.users.get_due_erasures(now).await? {
+            let mut uow = self.users.begin().await?;
+            // Si un admin ya lo borró, solo queda cerrar la solicitud
+            if let Some(user) = uow.get_by_id(request.user_id).await? {
+                uow.pseudonymize_audit(&user.username, &request.pseudonym)
+                    .await?;
+                uow.delete_user(user.id).await?;
+            }
+            let Some(request) = uow
+                .resolve_erasure(request.user_id, ErasureStatus::Completed, now)
+                .await?
+            else {
+                continue;
+            };
+            uow.record_audit(SYSTEM_ACTOR, ERASE_USER_ACTION, &request.pseudonym)
+                .await?;
+            uow.commit().await?;
+            tracing::info!("🧹 Cuenta borrada: {}", request.pseudonym);
+            done.push(request);
+        }
+        Ok(done)
+    }
+
+    /// Ejecuta `run_due` cada `every` en segundo plano.
+    pub fn spawn(self, every: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run_due().await {
+                    tracing::error!("❌ Fallo al ejecutar borrados de cuentas: {:?}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::FixedClock;
+    use crate::data::memory_repository::InMemoryRepository;
+    use chrono::Utc;
+
+    fn service(users: &DynUserRepository, days_from_now: i64) -> PrivacyService {
+        let clock = FixedClock(Utc::now() + Duration::days(days_from_now));
+        PrivacyService::new(users.clone(), Arc::new(clock), 7)
+    }
+
+    #[tokio::test]
+    async fn erasure_waits_for_the_grace_period_and_pseudonymizes_audit() {
+        let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+        let ana = users.create_user("ana", "hash").await.unwrap();
+        users.create_user("anabel", "hash").await.unwrap();
+        users
+            .record_audit("root", "CHANGE_ROLE", "ana:admin")
+            .await
+            .unwrap();
+        users
+            .record_audit("root", "CHANGE_ROLE", "anabel:admin")
+            .await
+            .unwrap();
+
+        let request = service(&users, 0).request_erasure("ana").await.unwrap();
+        assert!(matches!(
+            service(&users, 0).request_erasure("ana").await,
+            Err(AppError::Conflict(_))
+        ));
+
+        // Dentro del plazo no pasa nada
+        assert!(service(&users, 6).run_due().await.unwrap().is_empty());
+        assert!(users.get_by_id(ana.id).await.unwrap().is_some());
+
+        let done = service(&users, 8).run_due().await.unwrap();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].status, ErasureStatus::Completed);
+        assert!(users.get_by_id(ana.id).await.unwrap().is_none());
+
+        // Ningún rastro del username; el resto de la auditoría intacta
+        let logs = users.get_audit_logs().await.unwrap();
+        let pseudonym = &request.pseudonym;
+        let rows: Vec<_> = logs
+            .iter()
+            .map(|l| {
+                (
+                    l.admin_username.as_str(),
+                    l.action.as_str(),
+                    l.target.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (SYSTEM_ACTOR, ERASE_USER_ACTION, pseudonym.as_str()),
+                (pseudonym, REQUEST_ERASURE_ACTION, pseudonym),
+                ("root", "CHANGE_ROLE", "anabel:admin"),
+                ("root", "CHANGE_ROLE", &format!("{}:admin", pseudonym)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_are_not_executed() {
+        let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+        users.create_user("ana", "hash").await.unwrap();
+
+        let privacy = service(&users, 0);
+        privacy.request_erasure("ana").await.unwrap();
+        let cancelled = privacy.cancel_erasure("ana").await.unwrap();
+        assert_eq!(cancelled.status, ErasureStatus::Cancelled);
+        assert!(matches!(
+            privacy.cancel_erasure("ana").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        assert!(service(&users, 30).run_due().await.unwrap().is_empty());
+        assert!(users.get_by_username("ana").await.unwrap().is_some());
+        // Se puede volver a pedir
+        privacy.request_erasure("ana").await.unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+//! Casos de uso de gestión de usuarios.
+use crate::core::{
+    models::user::{
+        AuditLog, IfMatch, Keyset, KeysetPage, Page, Role, SecurityEvent, UpdateUserRequest, User,
+        UserHit, UserSearch, UserSort, SYSTEM_ACTOR,
+    },
+    repository::{DynUserRepository, UnitOfWork},
+    search,
+    services::auth::hash_password,
+};
+use crate::error::AppError;
+use crate::i18n::Locale;
+use validator::Validate;
+
+/// Acción registrada en la auditoría al borrar un usuario.
+pub const DELETE_USER_ACTION: &str = "DELETE_USER";
+/// Acción registrada en la auditoría al cambiar el rol de un usuario.
+pub const CHANGE_ROLE_ACTION: &str = "CHANGE_ROLE";
+/// Acción registrada en la auditoría al restablecer una contraseña.
+pub const RESET_PASSWORD_ACTION: &str = "RESET_PASSWORD";
+/// Acción registrada al suspender un usuario desde un lote.
+pub const SUSPEND_USER_ACTION: &str = "SUSPEND_USER";
+/// Acción registrada al editar un usuario (`usuario:campo1,campo2`).
+pub const UPDATE_USER_ACTION: &str = "UPDATE_USER";
+/// Acción registrada al crear el primer admin desde la configuración.
+pub const BOOTSTRAP_ADMIN_ACTION: &str = "BOOTSTRAP_ADMIN";
+/// Objetivo auditado cuando el id borrado no existía.
+pub const UNKNOWN_TARGET: &str = "Fantasma";
+
+pub struct UserService {
+    users: DynUserRepository,
+}
+
+impl UserService {
+    pub fn new(users: DynUserRepository) -> Self {
+        Self { users }
+    }
+
+    /// Alta con la contraseña ya validada; solo se guarda su hash.
+    pub async fn register(&self, username: &str, password: &str) -> Result<User, AppError> {
+        let password_hash = hash_password(password)?;
+        self.users.create_user(username, &password_hash).await
+    }
+
+    /// Sin `q`, el listado; con `q`, los resultados del buscador del
+    /// repositorio (con el fragmento resaltado). Rechaza `page < 1` y un
+    /// `limit` fuera de `1..=MAX_PAGE_LIMIT`.
+    pub async fn search(&self, params: UserSearch) -> Result<Page<UserHit>, AppError> {
+        params.validate()?;
+        if params.text().is_some() {
+            return self.users.search(&params).await;
+        }
+        let page = self.users.get_all(&params).await?;
+        Ok(Page {
+            items: page.items.into_iter().map(UserHit::from).collect(),
+            total: page.total,
+        })
+    }
+
+    /// Página por keyset `(created_at, id)` tras `after`: estable aunque se
+    /// inserten usuarios entre páginas. Aplica los filtros y `q` (LIKE, con
+    /// resaltado); el único orden posible es por alta, así que otro `sort` es
+    /// `validation.cursor_sort`.
+    pub async fn search_after(
+        &self,
+        params: UserSearch,
+        after: Option<Keyset>,
+    ) -> Result<KeysetPage<UserHit>, AppError> {
+        params.validate()?;
+        if matches!(params.sort, Some(UserSort::Username | UserSort::Role)) {
+            return Err(AppError::Validation("validation.cursor_sort".to_string()));
+        }
+        let overfetch = UserSearch {
+            limit: params.limit + 1,
+            ..params.clone()
+        };
+        let users = self.users.get_all_after(&overfetch, after.as_ref()).await?;
+        let page = KeysetPage::from_overfetch(users, params.limit, |u| Keyset {
+            at: u.created_at,
+            id: u.id,
+        });
+        let items = match params.text() {
+            Some(q) => search::like_hits(page.items, q, params.search_email),
+            None => page.items.into_iter().map(UserHit::from).collect(),
+        };
+        Ok(KeysetPage {
+            items,
+            next: page.next,
+        })
+    }
+
+    /// Bitácora por keyset, de la entrada más reciente a la más antigua.
+    pub async fn audit_logs_after(
+        &self,
+        after: Option<Keyset>,
+        limit: i64,
+    ) -> Result<KeysetPage<AuditLog>, AppError> {
+        let logs = self
+            .users
+            .get_audit_logs_after(after.as_ref(), limit + 1)
+            .await?;
+        Ok(KeysetPage::from_overfetch(logs, limit, |log| Keyset {
+            at: log.timestamp,
+            id: log.id,
+        }))
+    }
+
+    /// Eventos de seguridad por keyset, del más reciente al más antiguo.
+    pub async fn security_events_after(
+        &self,
+        after: Option<Keyset>,
+        limit: i64,
+    ) -> Result<KeysetPage<SecurityEvent>, AppError> {
+        let events = self
+            .users
+            .get_security_events_after(after.as_ref(), limit + 1)
+            .await?;
+        Ok(KeysetPage::from_overfetch(events, limit, |event| Keyset {
+            at: event.timestamp,
+            id: event.id,
+        }))
+    }
+
+    pub async fn get(&self, id: i64) -> Result<User, AppError> {
+        self.users
+            .get_by_id(id)
+            .await?
+            .ok_or(AppError::NotFound("user.not_found".to_string()))
+    }
+
+    /// Borra el usuario y deja constancia de quién lo hizo, en una sola
+    /// transacción. Borrar un id inexistente también se audita (salvo que
+    /// venga `If-Match`: entonces es `PreconditionFailed`).
+    pub async fn delete(
+        &self,
+        id: i64,
+        if_match: Option<&IfMatch>,
+        admin_username: &str,
+    ) -> Result<(), AppError> {
+        let mut uow = self.users.begin().await?;
+        let target = delete_in(uow.as_mut(), id, if_match)
+            .await?
+            .map(|user| user.username)
+            .unwrap_or(UNKNOWN_TARGET.to_string());
+        uow.record_audit(admin_username, DELETE_USER_ACTION, &target)
+            .await?;
+        uow.commit().await
+    }
+
+    /// Aplica los cambios presentes y lo audita (`usuario:campos`) en una sola
+    /// transacción. Con `If-Match`, la versión se comprueba y la fila queda
+    /// bloqueada hasta el commit: dos ediciones con el mismo `ETag` no se pisan.
+    pub async fn update(
+        &self,
+        id: i64,
+        changes: &UpdateUserRequest,
+        if_match: Option<&IfMatch>,
+        admin_username: &str,
+    ) -> Result<User, AppError> {
+        changes.validate()?;
+        if changes.is_empty() {
+            return Err(AppError::Validation("validation.empty_update".to_string()));
+        }
+        let mut uow = self.users.begin().await?;
+        let (user, updated) = update_in(uow.as_mut(), id, changes, if_match).await?;
+        let target = format!("{}:{}", user.username, changes.fields().join(","));
+        uow.record_audit(admin_username, UPDATE_USER_ACTION, &target)
+            .await?;
+        uow.commit().await?;
+        Ok(updated)
+    }
+
+    /// Cambia el rol y lo audita (`usuario:rol`) en una sola transacción.
+    pub async fn change_role(
+        &self,
+        id: i64,
+        role: Role,
+        admin_username: &str,
+    ) -> Result<User, AppError> {
+        let mut uow = self.users.begin().await?;
+        if !uow.set_role(id, role.clone()).await? {
+            return Err(AppError::NotFound("user.not_found".to_string()));
+        }
+        let user = uow
+            .get_by_id(id)
+            .await?
+            .ok_or(AppError::NotFound("user.not_found".to_string()))?;
+        let target = format!("{}:{}", user.username, role.as_str());
+        uow.record_audit(admin_username, CHANGE_ROLE_ACTION, &target)
+            .await?;
+        uow.commit().await?;
+        Ok(user)
+    }
+
+    /// Sustituye la contraseña (ya validada) y lo audita en una sola transacción.
+    pub async fn reset_password(
+        &self,
+        id: i64,
+        password: &str,
+        admin_username: &str,
+    ) -> Result<(), AppError> {
+        let password_hash = hash_password(password)?;
+        let mut uow = self.users.begin().await?;
+        let user = uow
+            .get_by_id(id)
+            .await?
+            .ok_or(AppError::NotFound("user.not_found".to_string()))?;
+        uow.set_password_hash(id, &password_hash).await?;
+        uow.record_audit(admin_username, RESET_PASSWORD_ACTION, &user.username)
+            .await?;
+        uow.commit().await
+    }
+
+    /// Crea el primer administrador si la base aún no tiene ninguno, auditado
+    /// como `SYSTEM`. Con un admin existente no hace nada y devuelve `None`.
+    pub async fn bootstrap_admin(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<User>, AppError> {
+        let password_hash = hash_password(password)?;
+        let mut uow = self.users.begin().await?;
+        if uow.has_admin().await? {
+            return Ok(None);
+        }
+        // Nunca se asciende una cuenta existente: su contraseña no es la configurada.
+        // Es un fallo de configuración que solo ve el operador al arrancar
+        let mut user = match uow.create_user(username, &password_hash).await {
+            Err(AppError::Conflict(_)) => {
+                return Err(AppError::Internal(format!(
+                    "bootstrap_admin.username '{}' ya existe como usuario sin rol admin: \
+                     configure otro nombre o ascienda esa cuenta con `backend-admin promote`",
+                    username
+                )))
+            }
+            created => created?,
+        };
+        uow.set_role(user.id, Role::Admin).await?;
+        user.role = Role::Admin;
+        uow.record_audit(SYSTEM_ACTOR, BOOTSTRAP_ADMIN_ACTION, username)
+            .await?;
+        uow.commit().await?;
+        Ok(Some(user))
+    }
+
+    pub async fn set_locale(&self, username: &str, locale: Option<Locale>) -> Result<(), AppError> {
+        self.users.set_locale(username, locale).await
+    }
+
+    /// Un texto vacío cuenta como borrar el dato.
+    pub async fn set_profile(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        fn clean(value: Option<&str>) -> Option<&str> {
+            value.map(str::trim).filter(|v| !v.is_empty())
+        }
+        self.users
+            .set_profile(username, clean(display_name), clean(email))
+            .await
+    }
+}
+
+/// Lo que hace `UserService::delete` dentro de una unidad de trabajo ya
+/// abierta (los lotes comparten una), sin auditar. Devuelve el usuario
+/// borrado, o `None` si el id no existía.
+pub(crate) async fn delete_in(
+    uow: &mut dyn UnitOfWork,
+    id: i64,
+    if_match: Option<&IfMatch>,
+) -> Result<Option<User>, AppError> {
+    let user = uow.get_by_id(id).await?;
+    if let Some(if_match) = if_match {
+        let user = user.as_ref().ok_or(precondition_failed())?;
+        lock_matching(uow, user, if_match).await?;
+    }
+    uow.delete_user(id).await?;
+    Ok(user)
+}
+
+/// Lo que hace `UserService::update` dentro de una unidad de trabajo ya
+/// abierta, sin validar ni auditar: el usuario antes y después del cambio.
+pub(crate) async fn update_in(
+    uow: &mut dyn UnitOfWork,
+    id: i64,
+    changes: &UpdateUserRequest,
+    if_match: Option<&IfMatch>,
+) -> Result<(User, User), AppError> {
+    let user = uow
+        .get_by_id(id)
+        .await?
+        .ok_or(AppError::NotFound("user.not_found".to_string()))?;
+    if let Some(if_match) = if_match {
+        lock_matching(uow, &user, if_match).await?;
+    }
+    let updated = uow
+        .update_user(id, changes)
+        .await?
+        .ok_or(AppError::NotFound("user.not_found".to_string()))?;
+    Ok((user, updated))
+}
+
+fn precondition_failed() -> AppError {
+    AppError::PreconditionFailed("precondition.failed".to_string())
+}
+
+/// Comprueba `If-Match` contra la versión leída y bloquea la fila en esa
+/// versión; si otra escritura se coló entre medias, también falla.
+async fn lock_matching(
+    uow: &mut dyn UnitOfWork,
+    user: &User,
+    if_match: &IfMatch,
+) -> Result<(), AppError> {
+    if !if_match.matches(user.version) || !uow.lock_version(user.id, user.version).await? {
+        return Err(precondition_failed());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::memory_repository::InMemoryRepository;
+    use std::sync::Arc;
+
+    fn service() -> (UserService, DynUserRepository) {
+        let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+        (UserService::new(users.clone()), users)
+    }
+
+    #[tokio::test]
+    async fn register_stores_only_the_hash() {
+        let (service, users) = service();
+        service.register("ana", "secreto123").await.unwrap();
+
+        let stored = users.get_by_username("ana").await.unwrap().unwrap();
+        assert_ne!(stored.password_hash, "secreto123");
+        assert!(stored.password_hash.starts_with("$argon2"));
+    }
+
+    #[tokio::test]
+    async fn delete_is_audited_even_for_unknown_ids() {
+        let (service, users) = service();
+        let user = service.register("victima", "secreto123").await.unwrap();
+
+        service.delete(user.id, None, "admin").await.unwrap();
+        service.delete(9999, None, "admin").await.unwrap();
+
+        assert!(users.get_by_username("victima").await.unwrap().is_none());
+        let logs = users.get_audit_logs().await.unwrap();
+        let targets: Vec<_> = logs.iter().map(|l| l.target.as_str()).collect();
+        assert_eq!(targets, [UNKNOWN_TARGET, "victima"]);
+        assert!(logs.iter().all(|l| l.action == DELETE_USER_ACTION));
+        assert!(logs.iter().all(|l| l.admin_username == "admin"));
+    }
+
+    #[tokio::test]
+    async fn change_role_is_audited() {
+        let (service, users) = service();
+        let user = service.register("ana", "secreto123").await.unwrap();
+
+        let updated = service
+            .change_role(user.id, Role::Admin, "root")
+            .await
+            .unwrap();
+        assert_eq!(updated.role, Role::Admin);
+
+        let logs = users.get_audit_logs().await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].action, CHANGE_ROLE_ACTION);
+        assert_eq!(logs[0].target, "ana:admin");
+
+        match service.change_role(9999, Role::User, "root").await {
+            Err(AppError::NotFound(_)) => {}
+            other => panic!("se esperaba NotFound, llegó {:?}", other.map(|u| u.id)),
+        }
+        assert_eq!(users.get_audit_logs().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn update_checks_if_match_before_writing() {
+        let (service, users) = service();
+        let user = users.create_user("ana", "hash").await.unwrap();
+        let changes = UpdateUserRequest {
+            display_name: Some("Ana".into()),
+            ..Default::default()
+        };
+
+        let stale = IfMatch::Versions(vec![user.version + 1]);
+        assert!(matches!(
+            service
+                .update(user.id, &changes, Some(&stale), "root")
+                .await,
+            Err(AppError::PreconditionFailed(_))
+        ));
+        assert!(users.get_audit_logs().await.unwrap().is_empty());
+
+        let current = IfMatch::Versions(vec![user.version]);
+        let updated = service
+            .update(user.id, &changes, Some(&current), "root")
+            .await
+            .unwrap();
+        assert_eq!(updated.version, user.version + 1);
+        assert_eq!(updated.display_name.as_deref(), Some("Ana"));
+        // La misma etiqueta ya no sirve para una segunda escritura
+        assert!(matches!(
+            service.delete(user.id, Some(&current), "root").await,
+            Err(AppError::PreconditionFailed(_))
+        ));
+        let logs = users.get_audit_logs().await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].action, UPDATE_USER_ACTION);
+        assert_eq!(logs[0].target, "ana:display_name");
+    }
+
+    #[tokio::test]
+    async fn reset_password_replaces_the_hash_and_is_audited() {
+        let (service, users) = service();
+        let user = service.register("ana", "secreto123").await.unwrap();
+
+        service
+            .reset_password(user.id, "otra-clave-1", "cli:root")
+            .await
+            .unwrap();
+
+        let stored = users.get_by_id(user.id).await.unwrap().unwrap();
+        assert_ne!(stored.password_hash, user.password_hash);
+        let logs = users.get_audit_logs().await.unwrap();
+        assert_eq!(logs[0].action, RESET_PASSWORD_ACTION);
+        assert_eq!(logs[0].target, "ana");
+        assert_eq!(logs[0].admin_username, "cli:root");
+
+        assert!(matches!(
+            service
+                .reset_password(9999, "otra-clave-1", "cli:root")
+                .await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bootstrap_admin_only_runs_without_admins() {
+        let (service, users) = service();
+        service.register("ana", "secreto123").await.unwrap();
+
+        let admin = service
+            .bootstrap_admin("root", "secreto123")
+            .await
+            .unwrap()
+            .expect("sin admins debe crearlo");
+        assert_eq!(admin.role, Role::Admin);
+        let stored = users.get_by_username("root").await.unwrap().unwrap();
+        assert_eq!(stored.role, Role::Admin);
+
+        let logs = users.get_audit_logs().await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].admin_username, SYSTEM_ACTOR);
+        assert_eq!(logs[0].action, BOOTSTRAP_ADMIN_ACTION);
+        assert_eq!(logs[0].target, "root");
+
+        // Con un admin ya existente (aunque sea otro) no vuelve a actuar
+        assert!(service
+            .bootstrap_admin("otro", "secreto123")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(users.get_by_username("otro").await.unwrap().is_none());
+        assert_eq!(users.get_audit_logs().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bootstrap_admin_refuses_existing_non_admin_username() {
+        let (service, users) = service();
+        service.register("root", "secreto123").await.unwrap();
+
+        let err = service
+            .bootstrap_admin("root", "otraclave1")
+            .await
+            .expect_err("no debe ascender una cuenta existente");
+        assert!(matches!(&err, AppError::Internal(msg) if msg.contains("'root'")));
+        let stored = users.get_by_username("root").await.unwrap().unwrap();
+        assert_eq!(stored.role, Role::User);
+        assert!(users.get_audit_logs().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+//! Política de retención de la auditoría: exporta los registros antiguos de
+//! `audit_logs` y `security_events` a NDJSON comprimido (encadenados por hash,
+//! una cadena por tabla) y los purga de la base de datos.
+use crate::core::{
+    clock::Clock,
+    models::user::{ArchiveKind, AuditArchive, AuditLog, NewAuditArchive, SecurityEvent},
+    repository::DynUserRepository,
+};
+use crate::data::TIMESTAMP_FORMAT;
+use crate::error::AppError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Hash previo del primer registro archivado cuando aún no existe ningún archivo.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Máximo de registros por archivo generado.
+const BATCH_SIZE: i64 = 5_000;
+
+/// Registro de una tabla sujeta a retención.
+#[async_trait]
+pub trait Archivable: Serialize + DeserializeOwned + Send + Sync + Sized {
+    const KIND: ArchiveKind;
+    /// Prefijo del nombre de sus archivos.
+    const PREFIX: &'static str;
+
+    fn id(&self) -> i64;
+    /// Campos que entran en el hash, en orden.
+    fn chain_fields(&self) -> Vec<String>;
+    /// Registros anteriores a `cutoff`, ordenados por id ascendente.
+    async fn before(
+        repo: &DynUserRepository,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Self>, AppError>;
+}
+
+#[async_trait]
+impl Archivable for AuditLog {
+    const KIND: ArchiveKind = ArchiveKind::AuditLogs;
+    const PREFIX: &'static str = "audit";
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn chain_fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.admin_username.clone(),
+            self.action.clone(),
+            self.target.clone(),
+            self.timestamp.format(TIMESTAMP_FORMAT).to_string(),
+        ]
+    }
+
+    async fn before(
+        repo: &DynUserRepository,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Self>, AppError> {
+        repo.get_audit_logs_before(cutoff, limit).await
+    }
+}
+
+#[async_trait]
+impl Archivable for SecurityEvent {
+    const KIND: ArchiveKind = ArchiveKind::SecurityEvents;
+    const PREFIX: &'static str = "security";
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn chain_fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.reason.clone(),
+            self.method.clone(),
+            self.path.clone(),
+            self.timestamp.format(TIMESTAMP_FORMAT).to_string(),
+        ]
+    }
+
+    async fn before(
+        repo: &DynUserRepository,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Self>, AppError> {
+        repo.get_security_events_before(cutoff, limit).await
+    }
+}
+
+/// Línea del NDJSON: el registro original más su eslabón de la cadena.
+#[derive(Serialize)]
+struct ArchivedEntry<'a, T> {
+    #[serde(flatten)]
+    record: &'a T,
+    prev_hash: &'a str,
+    hash: &'a str,
+}
+
+/// Línea del NDJSON tal como se lee al verificar.
+#[derive(Deserialize)]
+struct StoredEntry<T> {
+    #[serde(flatten)]
+    record: T,
+    prev_hash: String,
+    hash: String,
+}
+
+/// Resultado de verificar un archivo registrado en `audit_archives`.
+#[derive(Debug, Serialize)]
+pub struct ArchiveCheck {
+    pub kind: ArchiveKind,
+    pub file_name: String,
+    pub entries: i64,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// Calcula el hash de un registro encadenado al anterior (SHA-256 en hex).
+pub fn chain_hash<T: Archivable>(prev_hash: &str, record: &T) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    for field in record.chain_fields() {
+        hasher.update(b"\x1f");
+        hasher.update(field.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+pub struct AuditArchiver {
+    repo: DynUserRepository,
+    clock: Arc<dyn Clock>,
+    dir: PathBuf,
+    retention_days: i64,
+}
+
+impl AuditArchiver {
+    pub fn new(
+        repo: DynUserRepository,
+        clock: Arc<dyn Clock>,
+        dir: impl Into<PathBuf>,
+        retention_days: i64,
+    ) -> Self {
+        Self {
+            repo,
+            clock,
+            dir: dir.into(),
+            retention_days,
+        }
+    }
+
+    /// Archiva todo lo que supera la retención y devuelve los archivos generados.
+    pub async fn run(&self) -> Result<Vec<AuditArchive>, AppError> {
+        let cutoff = self.clock.now() - chrono::Duration::days(self.retention_days);
+        let mut created = self.archive::<AuditLog>(cutoff).await?;
+        created.extend(self.archive::<SecurityEvent>(cutoff).await?);
+        Ok(created)
+    }
+
+    async fn archive<T: Archivable>(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<Vec<AuditArchive>, AppError> {
+        // La cadena continúa desde el último archivo registrado de la tabla
+        let mut prev_hash = self
+            .repo
+            .get_audit_archives()
+            .await?
+            .into_iter()
+            .find(|a| a.kind == T::KIND)
+            .map(|a| a.last_hash)
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+        let mut created = Vec::new();
+        loop {
+            let records = T::before(&self.repo, cutoff, BATCH_SIZE).await?;
+            if records.is_empty() {
+                break;
+            }
+
+            let archive = self.write_archive(&records, &prev_hash).await?;
+            let path = self.dir.join(&archive.file_name);
+            prev_hash = archive.last_hash.clone();
+
+            match self.repo.commit_audit_archive(archive, cutoff).await {
+                Ok(record) => {
+                    tracing::info!(
+                        "🗄️ Auditoría archivada: {} ({} registros)",
+                        record.file_name,
+                        record.entries
+                    );
+                    created.push(record);
+                }
+                Err(e) => {
+                    // Sin registro en la DB el archivo es huérfano: se descarta
+                    tracing::error!(file = %path.display(), "Archivo de auditoría descartado: {}", e);
+                    let _ = fs::remove_file(&path);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    async fn write_archive<T: Archivable>(
+        &self,
+        records: &[T],
+        prev_hash: &str,
+    ) -> Result<NewAuditArchive, AppError> {
+        let mut ndjson = Vec::new();
+        let mut hash = prev_hash.to_string();
+        for record in records {
+            let next = chain_hash(&hash, record);
+            let entry = ArchivedEntry {
+                record,
+                prev_hash: &hash,
+                hash: &next,
+            };
+            serde_json::to_writer(&mut ndjson, &entry)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            ndjson.push(b'\n');
+            hash = next;
+        }
+
+        let first_log_id = records[0].id();
+        let last_log_id = records[records.len() - 1].id();
+        let file_name = format!(
+            "{}-{}-{}-{}.ndjson.gz",
+            T::PREFIX,
+            first_log_id,
+            last_log_id,
+            self.clock.now().format("%Y%m%d%H%M%S")
+        );
+        let dir = self.dir.clone();
+        let name = file_name.clone();
+
+        let sha256 = tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&ndjson)?;
+            let compressed = encoder.finish()?;
+
+            // Escritura atómica: temporal + rename
+            fs::create_dir_all(&dir)?;
+            let tmp = dir.join(format!("{}.tmp", name));
+            fs::write(&tmp, &compressed)?;
+            fs::rename(&tmp, dir.join(&name))?;
+            Ok(hex::encode(Sha256::digest(&compressed)))
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| AppError::Internal(format!("No se pudo escribir {}: {}", file_name, e)))?;
+
+        Ok(NewAuditArchive {
+            kind: T::KIND,
+            file_name,
+            entries: records.len() as i64,
+            first_log_id,
+            last_log_id,
+            last_hash: hash,
+            sha256,
+        })
+    }
+}
+
+/// Comprueba los archivos de `dir` contra su registro: sha256 del fichero,
+/// cada eslabón de la cadena, número de registros, hash final y continuidad
+/// entre los archivos de una misma tabla (del más antiguo al más reciente).
+pub fn verify_archives(dir: &Path, archives: &[AuditArchive]) -> Vec<ArchiveCheck> {
+    let mut ordered: Vec<&AuditArchive> = archives.iter().collect();
+    ordered.sort_by_key(|a| a.id);
+
+    let mut prev_hashes: HashMap<ArchiveKind, String> = HashMap::new();
+    ordered
+        .into_iter()
+        .map(|archive| {
+            let prev_hash = prev_hashes
+                .get(&archive.kind)
+                .map_or(GENESIS_HASH, String::as_str);
+            let error = match archive.kind {
+                ArchiveKind::AuditLogs => verify_archive::<AuditLog>(dir, archive, prev_hash),
+                ArchiveKind::SecurityEvents => {
+                    verify_archive::<SecurityEvent>(dir, archive, prev_hash)
+                }
+            }
+            .err();
+            // La cadena sigue desde el hash registrado aunque este archivo falle,
+            // así un fallo no arrastra a los siguientes
+            prev_hashes.insert(archive.kind, archive.last_hash.clone());
+            ArchiveCheck {
+                kind: archive.kind,
+                file_name: archive.file_name.clone(),
+                entries: archive.entries,
+                ok: error.is_none(),
+                error,
+            }
+        })
+        .collect()
+}
+
+fn verify_archive<T: Archivable>(
+    dir: &Path,
+    archive: &AuditArchive,
+    prev_hash: &str,
+) -> Result<(), String> {
+    let compressed = fs::read(dir.join(&archive.file_name))
+        .map_err(|e| format!("No se pudo leer el archivo: {}", e))?;
+    if hex::encode(Sha256::digest(&compressed)) != archive.sha256 {
+        return Err("El sha256 del archivo no coincide con el registrado".to_string());
+    }
+
+    let mut hash = prev_hash.to_string();
+    let mut entries = 0i64;
+    for (index, line) in BufReader::new(GzDecoder::new(compressed.as_slice()))
+        .lines()
+        .enumerate()
+    {
+        let line_no = index + 1;
+        let line = line.map_err(|e| format!("Línea {}: {}", line_no, e))?;
+        let entry: StoredEntry<T> = serde_json::from_str(&line)
+            .map_err(|e| format!("Línea {}: JSON inválido: {}", line_no, e))?;
+        if entry.prev_hash != hash {
+            return Err(format!("Línea {}: la cadena está rota", line_no));
+        }
+        let expected = chain_hash(&hash, &entry.record);
+        if entry.hash != expected {
+            return Err(format!("Línea {}: el registro fue alterado", line_no));
+        }
+        hash = expected;
+        entries += 1;
+    }
+
+    if entries != archive.entries {
+        return Err(format!(
+            "Se esperaban {} registros y hay {}",
+            archive.entries, entries
+        ));
+    }
+    if hash != archive.last_hash {
+        return Err("El último hash no coincide con el registrado".to_string());
+    }
+    Ok(())
+}
+
+impl AuditArchiver {
+    /// Ejecuta el archivado periódicamente en segundo plano.
+    pub fn spawn(self, every: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run().await {
+                    tracing::error!("❌ Fallo al archivar auditoría: {:?}", e);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,378 @@
+//! Copias de seguridad en caliente de SQLite (`VACUUM INTO`), con retención,
+//! compresión opcional y un manifiesto por copia con su checksum.
+use crate::core::clock::Clock;
+use crate::data::db::{DbOptions, DbPool, MigrationState};
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// Extensión del manifiesto que acompaña a cada copia.
+const MANIFEST_EXT: &str = "json";
+
+/// Copia registrada en el directorio de backups (contenido del manifiesto).
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub size_bytes: u64,
+    /// SHA-256 del archivo tal como está en disco (comprimido o no)
+    pub sha256: String,
+    pub compressed: bool,
+    /// Última migración aplicada en la base copiada
+    pub schema_version: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Resultado de `restore`.
+#[derive(Debug, Serialize)]
+pub struct RestoreReport {
+    pub file_name: String,
+    /// Dónde quedó la base sustituida (si existía)
+    pub previous: Option<String>,
+    /// Migraciones que el binario aplicará sobre la copia restaurada
+    pub pending_migrations: usize,
+}
+
+pub struct BackupManager {
+    pool: SqlitePool,
+    clock: Arc<dyn Clock>,
+    dir: PathBuf,
+    /// Copias que se conservan (0 = sin límite)
+    retention: usize,
+    compress: bool,
+}
+
+impl BackupManager {
+    pub fn new(
+        pool: SqlitePool,
+        clock: Arc<dyn Clock>,
+        dir: impl Into<PathBuf>,
+        retention: usize,
+        compress: bool,
+    ) -> Self {
+        Self {
+            pool,
+            clock,
+            dir: dir.into(),
+            retention,
+            compress,
+        }
+    }
+
+    /// `None` si la base no es SQLite (las copias de Postgres son cosa de `pg_dump`).
+    pub fn for_db(
+        db: &DbPool,
+        clock: Arc<dyn Clock>,
+        dir: impl Into<PathBuf>,
+        retention: usize,
+        compress: bool,
+    ) -> Option<Self> {
+        match db {
+            DbPool::Sqlite(pool) => Some(Self::new(pool.clone(), clock, dir, retention, compress)),
+            #[cfg(feature = "postgres")]
+            _ => None,
+        }
+    }
+
+    /// Copia consistente sin detener el servidor; después aplica la retención.
+    pub async fn create(&self) -> Result<BackupInfo, AppError> {
+        fs::create_dir_all(&self.dir).map_err(|e| io_error(&self.dir, e))?;
+
+        let created_at = self.clock.now();
+        let stem = format!("backup-{}", created_at.format("%Y%m%d%H%M%S%3f"));
+        let file_name = if self.compress {
+            format!("{}.db.gz", stem)
+        } else {
+            format!("{}.db", stem)
+        };
+        let path = self.dir.join(&file_name);
+        if path.exists() {
+            tracing::warn!("⚠️ La copia {} ya existe", path.display());
+            return Err(AppError::Conflict("backup.exists".to_string()));
+        }
+
+        let raw = self.dir.join(format!("{}.db.tmp", stem));
+        let raw_name = raw.to_string_lossy().to_string();
+        sqlx::query("VACUUM INTO $1")
+            .bind(&raw_name)
+            .execute(&self.pool)
+            .await?;
+
+        let schema_version: Option<i64> =
+            sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let compress = self.compress;
+        let target = path.clone();
+        let written = tokio::task::spawn_blocking(move || -> io::Result<(u64, String)> {
+            let result = finish_file(&raw, &target, compress);
+            let _ = fs::remove_file(&raw);
+            result
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        let (size_bytes, sha256) = written.map_err(|e| io_error(&path, e))?;
+
+        let info = BackupInfo {
+            file_name,
+            size_bytes,
+            sha256,
+            compressed: compress,
+            schema_version,
+            created_at,
+        };
+        if let Err(e) = write_manifest(&self.dir, &info) {
+            // Una copia sin manifiesto no se lista ni se puede verificar
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        tracing::info!(
+            "💾 Copia de seguridad creada: {} ({} bytes)",
+            info.file_name,
+            info.size_bytes
+        );
+
+        self.prune()?;
+        Ok(info)
+    }
+
+    /// Copias disponibles, la más reciente primero.
+    pub fn list(&self) -> Result<Vec<BackupInfo>, AppError> {
+        list_backups(&self.dir)
+    }
+
+    fn prune(&self) -> Result<(), AppError> {
+        if self.retention == 0 {
+            return Ok(());
+        }
+        for old in self.list()?.into_iter().skip(self.retention) {
+            let path = self.dir.join(&old.file_name);
+            fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+            let _ = fs::remove_file(manifest_path(&self.dir, &old.file_name));
+            tracing::info!(
+                "🧹 Copia de seguridad eliminada por retención: {}",
+                old.file_name
+            );
+        }
+        Ok(())
+    }
+
+    /// Crea copias periódicamente en segundo plano.
+    pub fn spawn(self: Arc<Self>, every: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            // El primer tick es inmediato: la primera copia espera un intervalo
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.create().await {
+                    tracing::error!("❌ Fallo al crear copia de seguridad: {:?}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Copias descritas por los manifiestos de `dir`, la más reciente primero.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>, AppError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(dir, e)),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| io_error(dir, e))?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(MANIFEST_EXT) {
+            continue;
+        }
+        let content = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+        match serde_json::from_str::<BackupInfo>(&content) {
+            Ok(info) => backups.push(info),
+            Err(e) => tracing::warn!("⚠️ Manifiesto ilegible {}: {}", path.display(), e),
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// Restaura `file_name` sobre la base de `database_url`, que no debe estar en uso
+/// (servidor detenido). Antes de sustituir nada comprueba el checksum y que el
+/// esquema de la copia sea compatible con este binario. La base anterior se
+/// conserva junto a la nueva como `<base>.pre-restore-<fecha>`.
+pub async fn restore(
+    database_url: &str,
+    dir: &Path,
+    file_name: &str,
+    clock: &dyn Clock,
+) -> Result<RestoreReport, AppError> {
+    let info = list_backups(dir)?
+        .into_iter()
+        .find(|b| b.file_name == file_name)
+        .ok_or_else(|| {
+            tracing::warn!("⚠️ No existe la copia {} en {}", file_name, dir.display());
+            AppError::NotFound("backup.not_found".to_string())
+        })?;
+
+    let db_path = sqlite_path(database_url)?;
+    // Un `-wal` vacío es lo que deja un cierre limpio; con datos, la base sigue
+    // abierta o el servidor cayó sin consolidarlo
+    for suffix in ["-wal", "-journal"] {
+        let sidecar = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        let pending = match fs::metadata(&sidecar) {
+            Ok(meta) => suffix == "-journal" || meta.len() > 0,
+            Err(_) => false,
+        };
+        if pending {
+            tracing::warn!("⚠️ {} existe: la base sigue abierta", sidecar.display());
+            return Err(AppError::Conflict("backup.database_in_use".to_string()));
+        }
+    }
+
+    // 1. Checksum y copia descomprimida junto a la base (mismo sistema de archivos)
+    let staged = PathBuf::from(format!("{}.restore", db_path.display()));
+    let source = dir.join(&info.file_name);
+    let (expected, compressed, target) = (info.sha256.clone(), info.compressed, staged.clone());
+    tokio::task::spawn_blocking(move || stage_file(&source, &target, &expected, compressed))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+
+    // 2. El esquema de la copia no puede ir por delante del binario
+    let pending = match check_schema(&staged).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            let _ = fs::remove_file(&staged);
+            return Err(e);
+        }
+    };
+
+    // 3. Intercambio de archivos
+    let previous = if db_path.exists() {
+        let aside = PathBuf::from(format!(
+            "{}.pre-restore-{}",
+            db_path.display(),
+            clock.now().format("%Y%m%d%H%M%S")
+        ));
+        fs::rename(&db_path, &aside).map_err(|e| io_error(&db_path, e))?;
+        for suffix in ["-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", db_path.display(), suffix));
+        }
+        Some(aside.display().to_string())
+    } else {
+        None
+    };
+    fs::rename(&staged, &db_path).map_err(|e| io_error(&db_path, e))?;
+
+    tracing::info!("♻️ Base restaurada desde {}", info.file_name);
+    Ok(RestoreReport {
+        file_name: info.file_name,
+        previous,
+        pending_migrations: pending,
+    })
+}
+
+/// Devuelve cuántas migraciones quedan pendientes en la copia.
+async fn check_schema(path: &Path) -> Result<usize, AppError> {
+    // Sin WAL: no deja `-wal`/`-shm` de la copia junto a la base
+    let options = DbOptions {
+        sqlite_journal_mode: "delete".into(),
+        ..DbOptions::default()
+    };
+    let db = DbPool::connect_with(&format!("sqlite://{}", path.display()), &options).await?;
+    let status = db.migration_status().await;
+    db.close().await;
+
+    let status = status?;
+    if let Some(m) = status.iter().find(|m| {
+        matches!(
+            m.state,
+            MigrationState::Unknown | MigrationState::ChecksumMismatch
+        )
+    }) {
+        tracing::warn!(
+            "⚠️ La copia no es compatible con este binario: migración {} ({:?})",
+            m.version,
+            m.state
+        );
+        return Err(AppError::Conflict("backup.incompatible_schema".to_string()));
+    }
+    Ok(status
+        .iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .count())
+}
+
+fn sqlite_path(database_url: &str) -> Result<PathBuf, AppError> {
+    let options = SqliteConnectOptions::from_str(database_url)?;
+    Ok(options.get_filename().into_owned())
+}
+
+/// Comprime (o renombra) la copia cruda y devuelve su tamaño y SHA-256.
+fn finish_file(raw: &Path, target: &Path, compress: bool) -> io::Result<(u64, String)> {
+    let bytes = if compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        io::copy(&mut fs::File::open(raw)?, &mut encoder)?;
+        let compressed = encoder.finish()?;
+        fs::write(target, &compressed)?;
+        compressed
+    } else {
+        fs::rename(raw, target)?;
+        fs::read(target)?
+    };
+    Ok((bytes.len() as u64, hex::encode(Sha256::digest(&bytes))))
+}
+
+fn stage_file(
+    source: &Path,
+    target: &Path,
+    sha256: &str,
+    compressed: bool,
+) -> Result<(), AppError> {
+    let bytes = fs::read(source).map_err(|e| io_error(source, e))?;
+    if hex::encode(Sha256::digest(&bytes)) != sha256 {
+        tracing::warn!(
+            "⚠️ El checksum de {} no coincide con su manifiesto",
+            source.display()
+        );
+        return Err(AppError::Conflict("backup.checksum_mismatch".to_string()));
+    }
+
+    let content = if compressed {
+        let mut raw = Vec::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut raw)
+            .map_err(|e| io_error(source, e))?;
+        raw
+    } else {
+        bytes
+    };
+    fs::write(target, content).map_err(|e| io_error(target, e))
+}
+
+fn manifest_path(dir: &Path, file_name: &str) -> PathBuf {
+    dir.join(format!("{}.{}", file_name, MANIFEST_EXT))
+}
+
+fn write_manifest(dir: &Path, info: &BackupInfo) -> Result<(), AppError> {
+    let path = manifest_path(dir, &info.file_name);
+    let json = serde_json::to_vec_pretty(info).map_err(|e| AppError::Internal(e.to_string()))?;
+    fs::write(&path, json).map_err(|e| io_error(&path, e))
+}
+
+fn io_error(path: &Path, e: io::Error) -> AppError {
+    AppError::Internal(format!("{}: {}", path.display(), e))
+}
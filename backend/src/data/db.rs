@@ -0,0 +1,315 @@
+//! Conexión a la base de datos elegida por `database_url`, compartida por el
+//! servidor y `backend-admin`.
+use crate::core::repository::DynUserRepository;
+use crate::data::user_repository::SqliteRepository;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    pool::PoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    Database, Pool, SqlitePool,
+};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+#[cfg(feature = "postgres")]
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations_pg");
+
+/// `postgres://` / `postgresql://` usan PostgreSQL; cualquier otra URL, SQLite.
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Situación de una migración respecto a la base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Aplicada, pero el archivo cambió desde entonces
+    ChecksumMismatch,
+    /// Aplicada en la base pero desconocida para este binario (la base es más nueva)
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+    /// Tiene `.down.sql` y se puede revertir
+    pub reversible: bool,
+}
+
+/// Ajustes del pool y de cada conexión. Los `sqlite_*` se ignoran con PostgreSQL.
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    /// Sentencias preparadas que guarda cada conexión
+    pub statement_cache_capacity: usize,
+    /// `wal`, `delete`, `truncate`, `persist`, `memory` u `off`
+    pub sqlite_journal_mode: String,
+    /// `off`, `normal`, `full` o `extra`
+    pub sqlite_synchronous: String,
+    /// Espera ante una base bloqueada antes de fallar con SQLITE_BUSY
+    pub sqlite_busy_timeout: Duration,
+    pub sqlite_foreign_keys: bool,
+}
+
+impl Default for DbOptions {
+    /// WAL + `synchronous = normal`: lectores y escritor no se bloquean entre sí
+    /// y cada commit evita un fsync (sigue siendo seguro ante caídas del proceso).
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            statement_cache_capacity: 100,
+            sqlite_journal_mode: "wal".into(),
+            sqlite_synchronous: "normal".into(),
+            sqlite_busy_timeout: Duration::from_secs(5),
+            sqlite_foreign_keys: true,
+        }
+    }
+}
+
+impl DbOptions {
+    fn pool<DB: Database>(&self) -> PoolOptions<DB> {
+        PoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+    }
+
+    fn sqlite(&self, url: &str) -> Result<SqliteConnectOptions, AppError> {
+        let journal_mode =
+            SqliteJournalMode::from_str(&self.sqlite_journal_mode).map_err(|_| {
+                AppError::Internal(format!(
+                    "sqlite_journal_mode inválido: {}",
+                    self.sqlite_journal_mode
+                ))
+            })?;
+        let synchronous = SqliteSynchronous::from_str(&self.sqlite_synchronous).map_err(|_| {
+            AppError::Internal(format!(
+                "sqlite_synchronous inválido: {}",
+                self.sqlite_synchronous
+            ))
+        })?;
+        Ok(SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(journal_mode)
+            .synchronous(synchronous)
+            .busy_timeout(self.sqlite_busy_timeout)
+            .foreign_keys(self.sqlite_foreign_keys)
+            .statement_cache_capacity(self.statement_cache_capacity))
+    }
+}
+
+#[derive(Clone)]
+pub enum DbPool {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::PgPool),
+}
+
+impl DbPool {
+    /// Conecta sin migrar con los [`DbOptions`] por defecto.
+    pub async fn connect(url: &str) -> Result<Self, AppError> {
+        Self::connect_with(url, &DbOptions::default()).await
+    }
+
+    /// Conecta sin migrar. En SQLite crea el archivo si no existe.
+    pub async fn connect_with(url: &str, options: &DbOptions) -> Result<Self, AppError> {
+        if is_postgres_url(url) {
+            return Self::connect_postgres(url, options).await;
+        }
+
+        let pool: SqlitePoolOptions = options.pool();
+        let pool = pool.connect_with(options.sqlite(url)?).await?;
+        Ok(DbPool::Sqlite(pool))
+    }
+
+    #[cfg(feature = "postgres")]
+    async fn connect_postgres(url: &str, options: &DbOptions) -> Result<Self, AppError> {
+        let connect = sqlx::postgres::PgConnectOptions::from_str(url)?
+            .statement_cache_capacity(options.statement_cache_capacity);
+        let pool: sqlx::postgres::PgPoolOptions = options.pool();
+        Ok(DbPool::Postgres(pool.connect_with(connect).await?))
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    async fn connect_postgres(_url: &str, _options: &DbOptions) -> Result<Self, AppError> {
+        Err(AppError::Internal(
+            "database_url apunta a PostgreSQL pero el binario se compiló sin la feature `postgres`"
+                .to_string(),
+        ))
+    }
+
+    /// Nombre legible del backend (la URL puede llevar credenciales).
+    pub fn backend_name(&self) -> &'static str {
+        match self {
+            DbPool::Sqlite(_) => "SQLite",
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(_) => "PostgreSQL",
+        }
+    }
+
+    pub fn migrator(&self) -> &'static Migrator {
+        match self {
+            DbPool::Sqlite(_) => &SQLITE_MIGRATOR,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(_) => &POSTGRES_MIGRATOR,
+        }
+    }
+
+    /// Aplica las migraciones pendientes.
+    pub async fn migrate(&self) -> Result<(), AppError> {
+        let result = match self {
+            DbPool::Sqlite(pool) => self.migrator().run(pool).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => self.migrator().run(pool).await,
+        };
+        result.map_err(|e| AppError::Internal(format!("Fallo al migrar: {}", e)))
+    }
+
+    /// Migraciones del binario y de la base, ordenadas por versión.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>, AppError> {
+        let mut applied = match self {
+            DbPool::Sqlite(pool) => applied_checksums(pool).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => applied_checksums(pool).await,
+        }
+        .map_err(|e| AppError::Internal(format!("Fallo al leer migraciones: {}", e)))?;
+
+        let migrator = self.migrator();
+        let reversible = |version: i64| {
+            migrator
+                .iter()
+                .any(|m| m.version == version && m.migration_type.is_down_migration())
+        };
+
+        let mut status: Vec<MigrationStatus> = migrator
+            .iter()
+            .filter(|m| m.migration_type.is_up_migration())
+            .map(|m| MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                state: match applied.remove(&m.version) {
+                    None => MigrationState::Pending,
+                    Some(checksum) if checksum == *m.checksum => MigrationState::Applied,
+                    Some(_) => MigrationState::ChecksumMismatch,
+                },
+                reversible: reversible(m.version),
+            })
+            .collect();
+        // Lo que queda en la base no lo conoce este binario
+        status.extend(applied.into_keys().map(|version| MigrationStatus {
+            version,
+            description: String::new(),
+            state: MigrationState::Unknown,
+            reversible: false,
+        }));
+        status.sort_by_key(|m| m.version);
+        Ok(status)
+    }
+
+    /// Falla si la base tiene migraciones que este binario no conoce: arrancar
+    /// un binario antiguo contra un esquema más nuevo puede corromper datos.
+    pub async fn ensure_not_ahead(&self) -> Result<(), AppError> {
+        let unknown: Vec<String> = self
+            .migration_status()
+            .await?
+            .into_iter()
+            .filter(|m| m.state == MigrationState::Unknown)
+            .map(|m| m.version.to_string())
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::Internal(format!(
+            "La base tiene migraciones desconocidas para este binario ({}): actualice el binario",
+            unknown.join(", ")
+        )))
+    }
+
+    /// Migraciones que `revert(target)` desharía, de la más nueva a la más
+    /// antigua. Falla si alguna no tiene `.down.sql`.
+    pub async fn revert_plan(&self, target: i64) -> Result<Vec<MigrationStatus>, AppError> {
+        self.ensure_not_ahead().await?;
+        let mut plan: Vec<MigrationStatus> = self
+            .migration_status()
+            .await?
+            .into_iter()
+            .filter(|m| m.version > target && m.state != MigrationState::Pending)
+            .collect();
+        plan.reverse();
+
+        if let Some(m) = plan.iter().find(|m| !m.reversible) {
+            return Err(AppError::Internal(format!(
+                "La migración {} ({}) no tiene .down.sql: no se puede revertir",
+                m.version, m.description
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Revierte las migraciones aplicadas posteriores a `target` (0 = todas).
+    pub async fn revert(&self, target: i64) -> Result<Vec<MigrationStatus>, AppError> {
+        let plan = self.revert_plan(target).await?;
+        let result = match self {
+            DbPool::Sqlite(pool) => self.migrator().undo(pool, target).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => self.migrator().undo(pool, target).await,
+        };
+        result.map_err(|e| AppError::Internal(format!("Fallo al revertir: {}", e)))?;
+        Ok(plan)
+    }
+
+    /// Cierra el pool. En SQLite vuelca antes el WAL a la base y lo deja vacío:
+    /// sqlx cierra las conexiones en segundo plano y el `-wal` puede sobrevivir
+    /// al proceso, pero vacío no impide restaurar una copia.
+    pub async fn close(&self) {
+        match self {
+            DbPool::Sqlite(pool) => {
+                if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                    .execute(pool)
+                    .await
+                {
+                    tracing::warn!("⚠️ No se pudo consolidar el WAL: {}", e);
+                }
+                pool.close().await
+            }
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    pub fn repository(&self) -> DynUserRepository {
+        match self {
+            DbPool::Sqlite(pool) => Arc::new(SqliteRepository::new(pool.clone())),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => {
+                Arc::new(crate::data::pg_repository::PgRepository::new(pool.clone()))
+            }
+        }
+    }
+}
+
+async fn applied_checksums<DB>(
+    pool: &Pool<DB>,
+) -> Result<HashMap<i64, Vec<u8>>, sqlx::migrate::MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+    Ok(applied
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect())
+}
@@ -0,0 +1,719 @@
+//! Implementación en memoria de `UserRepository` para tests rápidos.
+//! Replica la semántica de `SqliteRepository`; `tests/repository_conformance.rs`
+//! ejecuta la misma batería contra ambas para mantenerlas alineadas.
+use crate::core::{
+    models::idempotency::IdempotencyRecord,
+    models::privacy::{ErasureRequest, ErasureStatus},
+    models::user::{
+        ArchiveKind, AuditArchive, AuditLog, Keyset, NewAuditArchive, Page, Role, SecurityEvent,
+        SortOrder, UpdateUserRequest, User, UserSearch, UserSort, UserStatus, SYSTEM_ACTOR,
+    },
+    repository::{UnitOfWork, UserRepository},
+    search,
+};
+use crate::error::AppError;
+use crate::i18n::Locale;
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard, OwnedMutexGuard};
+
+#[derive(Default, Clone)]
+struct Store {
+    users: Vec<User>,
+    audit_logs: Vec<AuditLog>,
+    audit_archives: Vec<AuditArchive>,
+    security_events: Vec<SecurityEvent>,
+    revoked_tokens: HashMap<String, i64>,
+    erasure_requests: Vec<ErasureRequest>,
+    idempotency_keys: Vec<IdempotencyRecord>,
+    // Como AUTOINCREMENT: los ids nunca se reutilizan
+    last_user_id: i64,
+    last_audit_id: i64,
+    last_archive_id: i64,
+    last_security_event_id: i64,
+    last_erasure_id: i64,
+}
+
+impl Store {
+    fn insert_user(&mut self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        if self.users.iter().any(|u| u.username == username) {
+            return Err(AppError::Conflict("user.username_taken".to_string()));
+        }
+
+        self.last_user_id += 1;
+        let user = User {
+            id: self.last_user_id,
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+            role: Role::User,
+            status: UserStatus::Active,
+            created_at: now(),
+            locale: None,
+            display_name: None,
+            email: None,
+            last_login_at: None,
+            version: 1,
+        };
+        self.users.push(user.clone());
+        Ok(user)
+    }
+
+    fn push_audit(&mut self, admin_username: &str, action: &str, target: &str) {
+        self.last_audit_id += 1;
+        self.audit_logs.push(AuditLog {
+            id: self.last_audit_id,
+            admin_username: admin_username.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            timestamp: now(),
+        });
+    }
+}
+
+/// El objetivo de auditoría se refiere a `username` (también `username:...`).
+fn targets(target: &str, username: &str) -> bool {
+    target
+        .strip_prefix(username)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+/// Con la precisión de `CURRENT_TIMESTAMP` de SQLite (segundos).
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(0)
+}
+
+/// Usuarios que cumplen los filtros de `params`, en orden de inserción.
+/// `q` es el LIKE de SQLite: sin distinción de mayúsculas en ASCII.
+fn filtered<'a>(users: &'a [User], params: &UserSearch) -> Vec<&'a User> {
+    let needle = params.text();
+    users
+        .iter()
+        .filter(|u| needle.is_none_or(|needle| search::matches(u, needle, params.search_email)))
+        .filter(|u| params.role.as_ref().is_none_or(|role| &u.role == role))
+        .filter(|u| params.status.is_none_or(|status| u.status == status))
+        .filter(|u| params.created_from.is_none_or(|from| u.created_at >= from))
+        .filter(|u| params.created_to.is_none_or(|to| u.created_at < to))
+        .collect()
+}
+
+#[derive(Default)]
+pub struct InMemoryRepository {
+    store: Arc<Mutex<Store>>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().await
+    }
+}
+
+/// Trabaja sobre una copia del almacén y la publica en `commit`. Mantiene el
+/// lock mientras vive, como el bloqueo de escritura de SQLite: no se debe
+/// llamar al repositorio desde la misma tarea hasta confirmar o soltarla.
+pub struct InMemoryUnitOfWork {
+    guard: OwnedMutexGuard<Store>,
+    staged: Store,
+}
+
+#[async_trait]
+impl UnitOfWork for InMemoryUnitOfWork {
+    async fn create_user(&mut self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        self.staged.insert_user(username, password_hash)
+    }
+
+    async fn has_admin(&mut self) -> Result<bool, AppError> {
+        Ok(self.staged.users.iter().any(|u| u.role == Role::Admin))
+    }
+
+    async fn get_by_id(&mut self, id: i64) -> Result<Option<User>, AppError> {
+        Ok(self.staged.users.iter().find(|u| u.id == id).cloned())
+    }
+
+    async fn get_by_username(&mut self, username: &str) -> Result<Option<User>, AppError> {
+        Ok(self
+            .staged
+            .users
+            .iter()
+            .find(|u| u.username == username)
+            .cloned())
+    }
+
+    async fn delete_user(&mut self, id: i64) -> Result<bool, AppError> {
+        let before = self.staged.users.len();
+        self.staged.users.retain(|u| u.id != id);
+        Ok(self.staged.users.len() < before)
+    }
+
+    async fn set_role(&mut self, id: i64, role: Role) -> Result<bool, AppError> {
+        match self.staged.users.iter_mut().find(|u| u.id == id) {
+            Some(user) => {
+                user.role = role;
+                user.version += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_password_hash(&mut self, id: i64, password_hash: &str) -> Result<bool, AppError> {
+        match self.staged.users.iter_mut().find(|u| u.id == id) {
+            Some(user) => {
+                user.password_hash = password_hash.to_string();
+                user.version += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn lock_version(&mut self, id: i64, version: i64) -> Result<bool, AppError> {
+        // El lock del almacén ya se tiene mientras vive la unidad de trabajo
+        Ok(self
+            .staged
+            .users
+            .iter()
+            .any(|u| u.id == id && u.version == version))
+    }
+
+    async fn update_user_with_password(
+        &mut self,
+        id: i64,
+        changes: &UpdateUserRequest,
+        password_hash: Option<&str>,
+    ) -> Result<Option<User>, AppError> {
+        fn cleared(value: &str) -> Option<String> {
+            Some(value.trim()).filter(|v| !v.is_empty()).map(Into::into)
+        }
+        let Some(user) = self.staged.users.iter_mut().find(|u| u.id == id) else {
+            return Ok(None);
+        };
+        if let Some(role) = &changes.role {
+            user.role = role.clone();
+        }
+        if let Some(status) = changes.status {
+            user.status = status;
+        }
+        if let Some(display_name) = &changes.display_name {
+            user.display_name = cleared(display_name);
+        }
+        if let Some(email) = &changes.email {
+            user.email = cleared(email);
+        }
+        if let Some(password_hash) = password_hash {
+            user.password_hash = password_hash.to_string();
+        }
+        user.version += 1;
+        Ok(Some(user.clone()))
+    }
+
+    async fn request_erasure(
+        &mut self,
+        user_id: i64,
+        pseudonym: &str,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<ErasureRequest, AppError> {
+        let requests = &self.staged.erasure_requests;
+        if requests
+            .iter()
+            .any(|r| r.user_id == user_id && r.status == ErasureStatus::Pending)
+            || requests.iter().any(|r| r.pseudonym == pseudonym)
+        {
+            return Err(AppError::Conflict("erasure.already_requested".to_string()));
+        }
+        self.staged.last_erasure_id += 1;
+        let request = ErasureRequest {
+            id: self.staged.last_erasure_id,
+            user_id,
+            pseudonym: pseudonym.to_string(),
+            status: ErasureStatus::Pending,
+            requested_at: now(),
+            scheduled_for: scheduled_for.trunc_subsecs(0),
+            resolved_at: None,
+        };
+        self.staged.erasure_requests.push(request.clone());
+        Ok(request)
+    }
+
+    async fn resolve_erasure(
+        &mut self,
+        user_id: i64,
+        status: ErasureStatus,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ErasureRequest>, AppError> {
+        Ok(self
+            .staged
+            .erasure_requests
+            .iter_mut()
+            .find(|r| r.user_id == user_id && r.status == ErasureStatus::Pending)
+            .map(|request| {
+                request.status = status;
+                request.resolved_at = Some(at.trunc_subsecs(0));
+                request.clone()
+            }))
+    }
+
+    async fn pseudonymize_audit(
+        &mut self,
+        username: &str,
+        pseudonym: &str,
+    ) -> Result<u64, AppError> {
+        let mut changed = 0;
+        for log in self.staged.audit_logs.iter_mut() {
+            if targets(&log.target, username) {
+                log.target = format!("{}{}", pseudonym, &log.target[username.len()..]);
+                changed += 1;
+            }
+            if log.admin_username == username {
+                log.admin_username = pseudonym.to_string();
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn record_audit(
+        &mut self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        self.staged.push_audit(admin_username, action, target);
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        let Self { mut guard, staged } = *self;
+        *guard = staged;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryRepository {
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>, AppError> {
+        let guard = self.store.clone().lock_owned().await;
+        let staged = guard.clone();
+        Ok(Box::new(InMemoryUnitOfWork { guard, staged }))
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn create_user(&self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        self.store().await.insert_user(username, password_hash)
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .users
+            .iter()
+            .find(|u| u.username == username)
+            .cloned())
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<User>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .users
+            .iter()
+            .find(|u| u.id == id)
+            .cloned())
+    }
+
+    async fn get_all(&self, params: &UserSearch) -> Result<Page<User>, AppError> {
+        let store = self.store().await;
+        let mut users = filtered(&store.users, params);
+
+        // Sin `sort`, orden de inserción; los empates se deshacen por id
+        if let Some(sort) = params.sort {
+            users.sort_by(|a, b| {
+                let order = match sort {
+                    UserSort::Username => a
+                        .username
+                        .to_ascii_lowercase()
+                        .cmp(&b.username.to_ascii_lowercase()),
+                    UserSort::CreatedAt => a.created_at.cmp(&b.created_at),
+                    UserSort::Role => a.role.as_str().cmp(b.role.as_str()),
+                }
+                .then(a.id.cmp(&b.id));
+                match params.order {
+                    SortOrder::Asc => order,
+                    SortOrder::Desc => order.reverse(),
+                }
+            });
+        }
+
+        // OFFSET negativo equivale a 0 y LIMIT negativo a "sin límite"
+        let limit = if params.limit < 0 {
+            usize::MAX
+        } else {
+            params.limit as usize
+        };
+        Ok(Page {
+            total: users.len() as i64,
+            items: users
+                .into_iter()
+                .skip(params.offset() as usize)
+                .take(limit)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn get_all_after(
+        &self,
+        params: &UserSearch,
+        after: Option<&Keyset>,
+    ) -> Result<Vec<User>, AppError> {
+        let store = self.store().await;
+        let mut users = filtered(&store.users, params);
+        users.sort_by_key(|u| (u.created_at, u.id));
+        if params.order == SortOrder::Desc {
+            users.reverse();
+        }
+        Ok(users
+            .into_iter()
+            .filter(|u| {
+                after.is_none_or(|after| match params.order {
+                    SortOrder::Asc => (u.created_at, u.id) > (after.at, after.id),
+                    SortOrder::Desc => (u.created_at, u.id) < (after.at, after.id),
+                })
+            })
+            .take(params.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_user(&self, id: i64) -> Result<bool, AppError> {
+        let mut store = self.store().await;
+        let before = store.users.len();
+        store.users.retain(|u| u.id != id);
+        Ok(store.users.len() < before)
+    }
+
+    async fn set_locale(&self, username: &str, locale: Option<Locale>) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        if let Some(user) = store.users.iter_mut().find(|u| u.username == username) {
+            user.locale = locale;
+        }
+        Ok(())
+    }
+
+    async fn set_profile(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        if let Some(user) = store.users.iter_mut().find(|u| u.username == username) {
+            user.display_name = display_name.map(Into::into);
+            user.email = email.map(Into::into);
+            user.version += 1;
+        }
+        Ok(())
+    }
+
+    async fn record_login(&self, id: i64, at: DateTime<Utc>) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        if let Some(user) = store.users.iter_mut().find(|u| u.id == id) {
+            user.last_login_at = Some(at.trunc_subsecs(0));
+        }
+        Ok(())
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: i64) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        let now = Utc::now().timestamp();
+        store.revoked_tokens.retain(|_, exp| *exp >= now);
+        store
+            .revoked_tokens
+            .entry(jti.to_string())
+            .or_insert(expires_at);
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        Ok(self.store().await.revoked_tokens.contains_key(jti))
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError> {
+        let mut store = self.store().await;
+        store.idempotency_keys.retain(|r| r.expires_at > now);
+        if let Some(existing) = store
+            .idempotency_keys
+            .iter()
+            .find(|r| r.key == key && r.scope == scope)
+        {
+            return Ok(Some(existing.clone()));
+        }
+        store.idempotency_keys.push(IdempotencyRecord {
+            key: key.to_string(),
+            scope: scope.to_string(),
+            fingerprint: fingerprint.to_string(),
+            status: None,
+            headers: None,
+            body: None,
+            created_at: Utc::now().trunc_subsecs(0),
+            expires_at: expires_at.trunc_subsecs(0),
+        });
+        Ok(None)
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        status: i32,
+        headers: &str,
+        body: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        if let Some(record) = store
+            .idempotency_keys
+            .iter_mut()
+            .find(|r| r.key == key && r.scope == scope)
+        {
+            record.status = Some(status);
+            record.headers = Some(headers.to_string());
+            record.body = Some(body.to_vec());
+            record.expires_at = expires_at.trunc_subsecs(0);
+        }
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str, scope: &str) -> Result<(), AppError> {
+        self.store()
+            .await
+            .idempotency_keys
+            .retain(|r| !(r.key == key && r.scope == scope));
+        Ok(())
+    }
+
+    async fn record_audit(
+        &self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        self.store()
+            .await
+            .push_audit(admin_username, action, target);
+        Ok(())
+    }
+
+    async fn get_audit_logs(&self) -> Result<Vec<AuditLog>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .audit_logs
+            .iter()
+            .rev()
+            .cloned()
+            .collect())
+    }
+
+    async fn get_audit_logs_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        let store = self.store().await;
+        let mut logs: Vec<&AuditLog> = store
+            .audit_logs
+            .iter()
+            .filter(|log| after.is_none_or(|after| (log.timestamp, log.id) < (after.at, after.id)))
+            .collect();
+        logs.sort_by_key(|log| std::cmp::Reverse((log.timestamp, log.id)));
+        Ok(logs
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_audit_logs_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .audit_logs
+            .iter()
+            .filter(|log| log.timestamp < cutoff)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_audit_logs_for(&self, username: &str) -> Result<Vec<AuditLog>, AppError> {
+        let store = self.store().await;
+        let mut logs: Vec<AuditLog> = store
+            .audit_logs
+            .iter()
+            .filter(|log| targets(&log.target, username))
+            .cloned()
+            .collect();
+        logs.sort_by_key(|log| std::cmp::Reverse((log.timestamp, log.id)));
+        Ok(logs)
+    }
+
+    async fn record_security_event(
+        &self,
+        reason: &str,
+        method: &str,
+        path: &str,
+    ) -> Result<(), AppError> {
+        let mut store = self.store().await;
+        store.last_security_event_id += 1;
+        let event = SecurityEvent {
+            id: store.last_security_event_id,
+            reason: reason.to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            timestamp: now(),
+        };
+        store.security_events.push(event);
+        Ok(())
+    }
+
+    async fn get_security_events_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        let store = self.store().await;
+        let mut events: Vec<&SecurityEvent> = store
+            .security_events
+            .iter()
+            .filter(|event| {
+                after.is_none_or(|after| (event.timestamp, event.id) < (after.at, after.id))
+            })
+            .collect();
+        events.sort_by_key(|event| std::cmp::Reverse((event.timestamp, event.id)));
+        Ok(events
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_security_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .security_events
+            .iter()
+            .filter(|event| event.timestamp < cutoff)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_audit_archives(&self) -> Result<Vec<AuditArchive>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .audit_archives
+            .iter()
+            .rev()
+            .cloned()
+            .collect())
+    }
+
+    async fn commit_audit_archive(
+        &self,
+        archive: NewAuditArchive,
+        cutoff: DateTime<Utc>,
+    ) -> Result<AuditArchive, AppError> {
+        // Un único lock: equivale a la transacción de SQLite
+        let mut store = self.store().await;
+        if store
+            .audit_archives
+            .iter()
+            .any(|a| a.file_name == archive.file_name)
+        {
+            return Err(AppError::Conflict("audit.archive_exists".to_string()));
+        }
+
+        store.last_archive_id += 1;
+        let record = AuditArchive {
+            id: store.last_archive_id,
+            kind: archive.kind,
+            file_name: archive.file_name,
+            entries: archive.entries,
+            first_log_id: archive.first_log_id,
+            last_log_id: archive.last_log_id,
+            last_hash: archive.last_hash,
+            sha256: archive.sha256,
+            created_at: now(),
+        };
+        store.audit_archives.push(record.clone());
+
+        let archived = |id: i64, timestamp: DateTime<Utc>| {
+            (record.first_log_id..=record.last_log_id).contains(&id) && timestamp < cutoff
+        };
+        let action = match record.kind {
+            ArchiveKind::AuditLogs => {
+                store
+                    .audit_logs
+                    .retain(|log| !archived(log.id, log.timestamp));
+                "ARCHIVE_AUDIT_LOGS"
+            }
+            ArchiveKind::SecurityEvents => {
+                store
+                    .security_events
+                    .retain(|event| !archived(event.id, event.timestamp));
+                "ARCHIVE_SECURITY_EVENTS"
+            }
+        };
+        store.push_audit(SYSTEM_ACTOR, action, &record.file_name);
+        Ok(record)
+    }
+
+    async fn get_pending_erasure(&self, user_id: i64) -> Result<Option<ErasureRequest>, AppError> {
+        Ok(self
+            .store()
+            .await
+            .erasure_requests
+            .iter()
+            .find(|r| r.user_id == user_id && r.status == ErasureStatus::Pending)
+            .cloned())
+    }
+
+    async fn get_due_erasures(&self, now: DateTime<Utc>) -> Result<Vec<ErasureRequest>, AppError> {
+        let store = self.store().await;
+        let mut due: Vec<ErasureRequest> = store
+            .erasure_requests
+            .iter()
+            .filter(|r| r.status == ErasureStatus::Pending && r.scheduled_for <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|r| (r.scheduled_for, r.id));
+        Ok(due)
+    }
+}
@@ -1 +1,25 @@
+use crate::error::AppError;
+use sqlx::error::ErrorKind;
+
+pub mod audit_archive;
+pub mod backup;
+pub mod db;
+pub mod memory_repository;
+#[cfg(feature = "postgres")]
+pub mod pg_repository;
 pub mod user_repository;
+
+/// Formato de `CURRENT_TIMESTAMP` en SQLite. Las fechas se comparan como
+/// texto en esa base, y es también la forma canónica en la cadena de hashes
+/// de auditoría.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Traduce una violación de UNIQUE a `AppError::Conflict` con el mensaje dado.
+pub(crate) fn conflict_on_unique(e: sqlx::Error, message: &str) -> AppError {
+    if let Some(db_err) = e.as_database_error() {
+        if db_err.kind() == ErrorKind::UniqueViolation {
+            return AppError::Conflict(message.to_string());
+        }
+    }
+    AppError::Database(e)
+}
@@ -0,0 +1,698 @@
+//! Implementación PostgreSQL de `UserRepository` (feature `postgres`).
+//! Usa el esquema de `migrations_pg/` y conserva la semántica de `SqliteRepository`.
+//! Las consultas se comprueban en tiempo de ejecución: la metadata offline de
+//! `.sqlx/` se genera contra SQLite.
+use super::conflict_on_unique;
+use crate::core::{
+    models::idempotency::IdempotencyRecord,
+    models::privacy::{ErasureRequest, ErasureStatus},
+    models::user::{
+        ArchiveKind, AuditArchive, AuditLog, Keyset, NewAuditArchive, Page, Role, SecurityEvent,
+        SortOrder, UpdateUserRequest, User, UserSearch, UserStatus, SYSTEM_ACTOR,
+    },
+    repository::{UnitOfWork, UserRepository},
+    search,
+};
+use crate::error::AppError;
+use crate::i18n::Locale;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// Columnas de `ErasureRequest`.
+const ERASURE_COLUMNS: &str =
+    "id, user_id, pseudonym, status, requested_at, scheduled_for, resolved_at";
+
+/// Filtros de `UserSearch` ($1 texto, $2 rol, $3 estado, $4..$5 rango de alta);
+/// el texto se busca en el email solo con `search_email`.
+fn user_filters(params: &UserSearch) -> String {
+    format!(
+        "($1::text IS NULL OR username ILIKE $1 ESCAPE '\\' OR display_name ILIKE $1 ESCAPE '\\'{})
+    AND ($2::text IS NULL OR role = $2) AND ($3::text IS NULL OR status = $3)
+    AND ($4::timestamptz IS NULL OR created_at >= $4) AND ($5::timestamptz IS NULL OR created_at < $5)",
+        if params.search_email {
+            " OR email ILIKE $1 ESCAPE '\\'"
+        } else {
+            ""
+        }
+    )
+}
+
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Unidad de trabajo sobre una transacción; al soltarse sin `commit` hace rollback.
+pub struct PgUnitOfWork {
+    tx: Transaction<'static, Postgres>,
+}
+
+#[async_trait]
+impl UnitOfWork for PgUnitOfWork {
+    async fn create_user(&mut self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        let result = sqlx::query_as::<_, User>(
+            "INSERT INTO users (username, password_hash) VALUES ($1, $2) RETURNING id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version"
+        )
+        .bind(username)
+        .bind(password_hash)
+        .fetch_one(&mut *self.tx)
+        .await;
+
+        result.map_err(|e| conflict_on_unique(e, "user.username_taken"))
+    }
+
+    async fn has_admin(&mut self) -> Result<bool, AppError> {
+        let found = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE role = 'admin')",
+        )
+        .fetch_one(&mut *self.tx)
+        .await?;
+        Ok(found)
+    }
+
+    async fn get_by_id(&mut self, id: i64) -> Result<Option<User>, AppError> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_by_username(&mut self, username: &str) -> Result<Option<User>, AppError> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version FROM users WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn delete_user(&mut self, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_role(&mut self, id: i64, role: Role) -> Result<bool, AppError> {
+        let result = sqlx::query("UPDATE users SET role = $1, version = version + 1 WHERE id = $2")
+            .bind(role)
+            .bind(id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_password_hash(&mut self, id: i64, password_hash: &str) -> Result<bool, AppError> {
+        let result =
+            sqlx::query("UPDATE users SET password_hash = $1, version = version + 1 WHERE id = $2")
+                .bind(password_hash)
+                .bind(id)
+                .execute(&mut *self.tx)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn lock_version(&mut self, id: i64, version: i64) -> Result<bool, AppError> {
+        let locked = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM users WHERE id = $1 AND version = $2 FOR UPDATE",
+        )
+        .bind(id)
+        .bind(version)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+        Ok(locked.is_some())
+    }
+
+    async fn update_user_with_password(
+        &mut self,
+        id: i64,
+        changes: &UpdateUserRequest,
+        password_hash: Option<&str>,
+    ) -> Result<Option<User>, AppError> {
+        sqlx::query_as::<_, User>(
+            "UPDATE users SET
+                role = COALESCE($1, role),
+                status = COALESCE($2, status),
+                display_name = CASE WHEN $3::text IS NULL THEN display_name ELSE NULLIF($3, '') END,
+                email = CASE WHEN $4::text IS NULL THEN email ELSE NULLIF($4, '') END,
+                password_hash = COALESCE($5, password_hash),
+                version = version + 1
+            WHERE id = $6
+            RETURNING id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version",
+        )
+        .bind(changes.role.as_ref().map(Role::as_str))
+        .bind(changes.status.as_ref().map(UserStatus::as_str))
+        .bind(changes.display_name.as_deref().map(str::trim))
+        .bind(changes.email.as_deref().map(str::trim))
+        .bind(password_hash)
+        .bind(id)
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn request_erasure(
+        &mut self,
+        user_id: i64,
+        pseudonym: &str,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<ErasureRequest, AppError> {
+        sqlx::query_as::<_, ErasureRequest>(&format!(
+            "INSERT INTO erasure_requests (user_id, pseudonym, scheduled_for) VALUES ($1, $2, $3) RETURNING {}",
+            ERASURE_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(pseudonym)
+        .bind(scheduled_for)
+        .fetch_one(&mut *self.tx)
+        .await
+        .map_err(|e| conflict_on_unique(e, "erasure.already_requested"))
+    }
+
+    async fn resolve_erasure(
+        &mut self,
+        user_id: i64,
+        status: ErasureStatus,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ErasureRequest>, AppError> {
+        sqlx::query_as::<_, ErasureRequest>(&format!(
+            "UPDATE erasure_requests SET status = $1, resolved_at = $2 WHERE user_id = $3 AND status = 'pending' RETURNING {}",
+            ERASURE_COLUMNS
+        ))
+        .bind(status)
+        .bind(at)
+        .bind(user_id)
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn pseudonymize_audit(
+        &mut self,
+        username: &str,
+        pseudonym: &str,
+    ) -> Result<u64, AppError> {
+        let targets = sqlx::query(
+            "UPDATE audit_logs SET target = $2 || substr(target, length($1) + 1)
+            WHERE target = $1 OR substr(target, 1, length($1) + 1) = $1 || ':'",
+        )
+        .bind(username)
+        .bind(pseudonym)
+        .execute(&mut *self.tx)
+        .await?;
+        let actors =
+            sqlx::query("UPDATE audit_logs SET admin_username = $2 WHERE admin_username = $1")
+                .bind(username)
+                .bind(pseudonym)
+                .execute(&mut *self.tx)
+                .await?;
+        Ok(targets.rows_affected() + actors.rows_affected())
+    }
+
+    async fn record_audit(
+        &mut self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query("INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)")
+            .bind(admin_username)
+            .bind(action)
+            .bind(target)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgRepository {
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>, AppError> {
+        let tx = self.pool.begin().await?;
+        Ok(Box::new(PgUnitOfWork { tx }))
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn create_user(&self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        let result = sqlx::query_as::<_, User>(
+            "INSERT INTO users (username, password_hash) VALUES ($1, $2) RETURNING id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version"
+        )
+        .bind(username)
+        .bind(password_hash)
+        .fetch_one(&self.pool)
+        .await;
+
+        result.map_err(|e| conflict_on_unique(e, "user.username_taken"))
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version FROM users WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_all(&self, params: &UserSearch) -> Result<Page<User>, AppError> {
+        // Postgres rechaza LIMIT negativo; SQLite lo trata como "sin límite"
+        let limit = (params.limit >= 0).then_some(params.limit);
+        let search = params.text().map(search::like_pattern);
+        // ILIKE replica el LIKE sin distinción de mayúsculas de SQLite,
+        // lower() + "C" su COLLATE NOCASE y el id el orden de inserción (rowid)
+        let items = sqlx::query_as::<_, User>(&format!(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version
+            FROM users WHERE {}
+            ORDER BY
+                CASE WHEN $6::text = 'username' AND $7::text = 'asc' THEN lower(username) COLLATE \"C\" END ASC,
+                CASE WHEN $6 = 'username' AND $7 = 'desc' THEN lower(username) COLLATE \"C\" END DESC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'asc' THEN created_at END ASC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'desc' THEN created_at END DESC,
+                CASE WHEN $6 = 'role' AND $7 = 'asc' THEN role END ASC,
+                CASE WHEN $6 = 'role' AND $7 = 'desc' THEN role END DESC,
+                CASE WHEN $6 IS NOT NULL AND $7 = 'desc' THEN id END DESC,
+                id ASC
+            LIMIT $8 OFFSET $9",
+            user_filters(params)
+        ))
+        .bind(&search)
+        .bind(params.role.as_ref().map(Role::as_str))
+        .bind(params.status.map(|s| s.as_str()))
+        .bind(params.created_from)
+        .bind(params.created_to)
+        .bind(params.sort.map(|s| s.as_str()))
+        .bind(params.order.as_str())
+        .bind(limit)
+        .bind(params.offset())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM users WHERE {}",
+            user_filters(params)
+        ))
+        .bind(&search)
+        .bind(params.role.as_ref().map(Role::as_str))
+        .bind(params.status.map(|s| s.as_str()))
+        .bind(params.created_from)
+        .bind(params.created_to)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Page { items, total })
+    }
+
+    async fn get_all_after(
+        &self,
+        params: &UserSearch,
+        after: Option<&Keyset>,
+    ) -> Result<Vec<User>, AppError> {
+        let search = params.text().map(search::like_pattern);
+        let (cmp, dir) = match params.order {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+        let users = sqlx::query_as::<_, User>(&format!(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version
+            FROM users WHERE {}
+                AND ($6::timestamptz IS NULL OR (created_at, id) {} ($6, $7))
+            ORDER BY created_at {}, id {}
+            LIMIT $8",
+            user_filters(params),
+            cmp,
+            dir,
+            dir
+        ))
+        .bind(&search)
+        .bind(params.role.as_ref().map(Role::as_str))
+        .bind(params.status.map(|s| s.as_str()))
+        .bind(params.created_from)
+        .bind(params.created_to)
+        .bind(after.map(|k| k.at))
+        .bind(after.map(|k| k.id))
+        .bind(params.limit.max(0))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<User>, AppError> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, password_hash, role, status, created_at, locale, display_name, email, last_login_at, version FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn delete_user(&self, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_locale(&self, username: &str, locale: Option<Locale>) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET locale = $1 WHERE username = $2")
+            .bind(locale)
+            .bind(username)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_profile(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET display_name = $1, email = $2, version = version + 1 WHERE username = $3")
+            .bind(display_name)
+            .bind(email)
+            .bind(username)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_login(&self, id: i64, at: DateTime<Utc>) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET last_login_at = $1 WHERE id = $2")
+            .bind(at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: i64) -> Result<(), AppError> {
+        sqlx::query(
+            "DELETE FROM revoked_tokens WHERE expires_at < extract(epoch FROM now())::BIGINT",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING",
+        )
+        .bind(jti)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let found = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1)",
+        )
+        .bind(jti)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(found)
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        let reserved = sqlx::query(
+            "INSERT INTO idempotency_keys (key, scope, fingerprint, expires_at) VALUES ($1, $2, $3, $4) ON CONFLICT (key, scope) DO NOTHING",
+        )
+        .bind(key)
+        .bind(scope)
+        .bind(fingerprint)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        if reserved.rows_affected() > 0 {
+            return Ok(None);
+        }
+        sqlx::query_as::<_, IdempotencyRecord>(
+            "SELECT key, scope, fingerprint, status, headers, body, created_at, expires_at FROM idempotency_keys WHERE key = $1 AND scope = $2",
+        )
+        .bind(key)
+        .bind(scope)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        status: i32,
+        headers: &str,
+        body: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET status = $1, headers = $2, body = $3, expires_at = $4 WHERE key = $5 AND scope = $6",
+        )
+        .bind(status)
+        .bind(headers)
+        .bind(body)
+        .bind(expires_at)
+        .bind(key)
+        .bind(scope)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str, scope: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE key = $1 AND scope = $2")
+            .bind(key)
+            .bind(scope)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_audit(
+        &self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query("INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)")
+            .bind(admin_username)
+            .bind(action)
+            .bind(target)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_audit_logs(&self) -> Result<Vec<AuditLog>, AppError> {
+        sqlx::query_as::<_, AuditLog>(
+            "SELECT id, admin_username, action, target, timestamp FROM audit_logs ORDER BY id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_logs_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        sqlx::query_as::<_, AuditLog>(
+            "SELECT id, admin_username, action, target, timestamp FROM audit_logs
+            WHERE ($1::timestamptz IS NULL OR (timestamp, id) < ($1, $2))
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $3",
+        )
+        .bind(after.map(|k| k.at))
+        .bind(after.map(|k| k.id))
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_logs_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        sqlx::query_as::<_, AuditLog>(
+            "SELECT id, admin_username, action, target, timestamp FROM audit_logs WHERE timestamp < $1 ORDER BY id ASC LIMIT $2",
+        )
+        .bind(cutoff)
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_logs_for(&self, username: &str) -> Result<Vec<AuditLog>, AppError> {
+        sqlx::query_as::<_, AuditLog>(
+            "SELECT id, admin_username, action, target, timestamp FROM audit_logs
+            WHERE target = $1 OR substr(target, 1, length($1) + 1) = $1 || ':'
+            ORDER BY timestamp DESC, id DESC",
+        )
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn record_security_event(
+        &self,
+        reason: &str,
+        method: &str,
+        path: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query("INSERT INTO security_events (reason, method, path) VALUES ($1, $2, $3)")
+            .bind(reason)
+            .bind(method)
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_security_events_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        sqlx::query_as::<_, SecurityEvent>(
+            "SELECT id, reason, method, path, timestamp FROM security_events
+            WHERE ($1::timestamptz IS NULL OR (timestamp, id) < ($1, $2))
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $3",
+        )
+        .bind(after.map(|k| k.at))
+        .bind(after.map(|k| k.id))
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_security_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        sqlx::query_as::<_, SecurityEvent>(
+            "SELECT id, reason, method, path, timestamp FROM security_events WHERE timestamp < $1 ORDER BY id ASC LIMIT $2",
+        )
+        .bind(cutoff)
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_archives(&self) -> Result<Vec<AuditArchive>, AppError> {
+        sqlx::query_as::<_, AuditArchive>(
+            "SELECT id, kind, file_name, entries, first_log_id, last_log_id, last_hash, sha256, created_at FROM audit_archives ORDER BY id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn commit_audit_archive(
+        &self,
+        archive: NewAuditArchive,
+        cutoff: DateTime<Utc>,
+    ) -> Result<AuditArchive, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let record = sqlx::query_as::<_, AuditArchive>(
+            "INSERT INTO audit_archives (kind, file_name, entries, first_log_id, last_log_id, last_hash, sha256) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, kind, file_name, entries, first_log_id, last_log_id, last_hash, sha256, created_at",
+        )
+        .bind(archive.kind)
+        .bind(&archive.file_name)
+        .bind(archive.entries)
+        .bind(archive.first_log_id)
+        .bind(archive.last_log_id)
+        .bind(&archive.last_hash)
+        .bind(&archive.sha256)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| conflict_on_unique(e, "audit.archive_exists"))?;
+
+        let action = match archive.kind {
+            ArchiveKind::AuditLogs => "ARCHIVE_AUDIT_LOGS",
+            ArchiveKind::SecurityEvents => "ARCHIVE_SECURITY_EVENTS",
+        };
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE id BETWEEN $1 AND $2 AND timestamp < $3",
+            archive.kind.as_str()
+        ))
+        .bind(archive.first_log_id)
+        .bind(archive.last_log_id)
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)")
+            .bind(SYSTEM_ACTOR)
+            .bind(action)
+            .bind(&archive.file_name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(record)
+    }
+
+    async fn get_pending_erasure(&self, user_id: i64) -> Result<Option<ErasureRequest>, AppError> {
+        sqlx::query_as::<_, ErasureRequest>(&format!(
+            "SELECT {} FROM erasure_requests WHERE user_id = $1 AND status = 'pending'",
+            ERASURE_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_due_erasures(&self, now: DateTime<Utc>) -> Result<Vec<ErasureRequest>, AppError> {
+        sqlx::query_as::<_, ErasureRequest>(&format!(
+            "SELECT {} FROM erasure_requests WHERE status = 'pending' AND scheduled_for <= $1 ORDER BY scheduled_for, id",
+            ERASURE_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+}
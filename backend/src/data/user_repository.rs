@@ -1,10 +1,21 @@
+//! Repositorio SQLite. Las consultas usan las macros de sqlx: se validan contra
+//! las migraciones al compilar (metadata offline en `.sqlx/`).
+use super::{conflict_on_unique, TIMESTAMP_FORMAT};
 use crate::core::{
-    models::user::{AuditLog, User},
-    repository::UserRepository,
+    models::idempotency::IdempotencyRecord,
+    models::privacy::{ErasureRequest, ErasureStatus},
+    models::user::{
+        ArchiveKind, AuditArchive, AuditLog, Keyset, NewAuditArchive, Page, Role, SecurityEvent,
+        SortOrder, UpdateUserRequest, User, UserHit, UserSearch, UserStatus, SYSTEM_ACTOR,
+    },
+    repository::{UnitOfWork, UserRepository},
+    search,
 };
 use crate::error::AppError;
+use crate::i18n::Locale;
 use async_trait::async_trait;
-use sqlx::{error::ErrorKind, SqlitePool};
+use chrono::{DateTime, Utc};
+use sqlx::{Sqlite, SqlitePool, Transaction};
 
 pub struct SqliteRepository {
     pool: SqlitePool,
@@ -16,86 +27,883 @@ impl SqliteRepository {
     }
 }
 
+/// Unidad de trabajo sobre una transacción; al soltarse sin `commit` hace rollback.
+pub struct SqliteUnitOfWork {
+    tx: Transaction<'static, Sqlite>,
+}
+
+#[async_trait]
+impl UnitOfWork for SqliteUnitOfWork {
+    async fn create_user(&mut self, username: &str, password_hash: &str) -> Result<User, AppError> {
+        let result = sqlx::query_as!(
+            User,
+            r#"INSERT INTO users (username, password_hash) VALUES ($1, $2) RETURNING id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!""#,
+            username,
+            password_hash
+        )
+        .fetch_one(&mut *self.tx)
+        .await;
+
+        result.map_err(|e| conflict_on_unique(e, "user.username_taken"))
+    }
+
+    async fn has_admin(&mut self) -> Result<bool, AppError> {
+        let found = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM users WHERE role = 'admin') AS "found!: bool""#
+        )
+        .fetch_one(&mut *self.tx)
+        .await?;
+        Ok(found)
+    }
+
+    async fn get_by_id(&mut self, id: i64) -> Result<Option<User>, AppError> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!" FROM users WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_by_username(&mut self, username: &str) -> Result<Option<User>, AppError> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!" FROM users WHERE username = $1"#,
+            username
+        )
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn delete_user(&mut self, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query!("DELETE FROM users WHERE id = $1", id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_role(&mut self, id: i64, role: Role) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "UPDATE users SET role = $1, version = version + 1 WHERE id = $2",
+            role,
+            id
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_password_hash(&mut self, id: i64, password_hash: &str) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $1, version = version + 1 WHERE id = $2",
+            password_hash,
+            id
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn lock_version(&mut self, id: i64, version: i64) -> Result<bool, AppError> {
+        // Escritura sin cambios: toma el bloqueo de escritura de la transacción
+        let result = sqlx::query!(
+            "UPDATE users SET version = version WHERE id = $1 AND version = $2",
+            id,
+            version
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_user_with_password(
+        &mut self,
+        id: i64,
+        changes: &UpdateUserRequest,
+        password_hash: Option<&str>,
+    ) -> Result<Option<User>, AppError> {
+        let role = changes.role.as_ref().map(Role::as_str);
+        let status = changes.status.as_ref().map(UserStatus::as_str);
+        let display_name = changes.display_name.as_deref().map(str::trim);
+        let email = changes.email.as_deref().map(str::trim);
+        sqlx::query_as!(
+            User,
+            r#"UPDATE users SET
+                role = COALESCE($1, role),
+                status = COALESCE($2, status),
+                display_name = CASE WHEN $3 IS NULL THEN display_name ELSE NULLIF($3, '') END,
+                email = CASE WHEN $4 IS NULL THEN email ELSE NULLIF($4, '') END,
+                password_hash = COALESCE($5, password_hash),
+                version = version + 1
+            WHERE id = $6
+            RETURNING id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!""#,
+            role,
+            status,
+            display_name,
+            email,
+            password_hash,
+            id
+        )
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn request_erasure(
+        &mut self,
+        user_id: i64,
+        pseudonym: &str,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<ErasureRequest, AppError> {
+        let scheduled_for = scheduled_for.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query_as!(
+            ErasureRequest,
+            r#"INSERT INTO erasure_requests (user_id, pseudonym, scheduled_for) VALUES ($1, $2, $3) RETURNING id as "id!", user_id as "user_id!", pseudonym as "pseudonym!", status as "status!: ErasureStatus", requested_at as "requested_at!: DateTime<Utc>", scheduled_for as "scheduled_for!: DateTime<Utc>", resolved_at as "resolved_at: DateTime<Utc>""#,
+            user_id,
+            pseudonym,
+            scheduled_for
+        )
+        .fetch_one(&mut *self.tx)
+        .await
+        .map_err(|e| conflict_on_unique(e, "erasure.already_requested"))
+    }
+
+    async fn resolve_erasure(
+        &mut self,
+        user_id: i64,
+        status: ErasureStatus,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ErasureRequest>, AppError> {
+        let at = at.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query_as!(
+            ErasureRequest,
+            r#"UPDATE erasure_requests SET status = $1, resolved_at = $2 WHERE user_id = $3 AND status = 'pending' RETURNING id as "id!", user_id as "user_id!", pseudonym as "pseudonym!", status as "status!: ErasureStatus", requested_at as "requested_at!: DateTime<Utc>", scheduled_for as "scheduled_for!: DateTime<Utc>", resolved_at as "resolved_at: DateTime<Utc>""#,
+            status,
+            at,
+            user_id
+        )
+        .fetch_optional(&mut *self.tx)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn pseudonymize_audit(
+        &mut self,
+        username: &str,
+        pseudonym: &str,
+    ) -> Result<u64, AppError> {
+        let targets = sqlx::query!(
+            "UPDATE audit_logs SET target = $2 || substr(target, length($1) + 1)
+            WHERE target = $1 OR substr(target, 1, length($1) + 1) = $1 || ':'",
+            username,
+            pseudonym
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        let actors = sqlx::query!(
+            "UPDATE audit_logs SET admin_username = $2 WHERE admin_username = $1",
+            username,
+            pseudonym
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(targets.rows_affected() + actors.rows_affected())
+    }
+
+    async fn record_audit(
+        &mut self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            "INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)",
+            admin_username,
+            action,
+            target
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.tx.commit().await?;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl UserRepository for SqliteRepository {
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>, AppError> {
+        let tx = self.pool.begin().await?;
+        Ok(Box::new(SqliteUnitOfWork { tx }))
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query!("SELECT 1 AS ping")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     async fn create_user(&self, username: &str, password_hash: &str) -> Result<User, AppError> {
-        let result = sqlx::query_as::<_, User>(
-            "INSERT INTO users (username, password_hash) VALUES ($1, $2) RETURNING id, username, password_hash, role, created_at"
+        let result = sqlx::query_as!(
+            User,
+            r#"INSERT INTO users (username, password_hash) VALUES ($1, $2) RETURNING id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!""#,
+            username,
+            password_hash
         )
-        .bind(username)
-        .bind(password_hash)
         .fetch_one(&self.pool)
         .await;
 
-        match result {
-            Ok(user) => Ok(user),
-            Err(e) => {
-                if let Some(db_err) = e.as_database_error() {
-                    if db_err.kind() == ErrorKind::UniqueViolation {
-                        return Err(AppError::Conflict(
-                            "El nombre de usuario ya existe".to_string(),
-                        ));
-                    }
-                }
-                Err(AppError::Database(e))
-            }
-        }
+        result.map_err(|e| conflict_on_unique(e, "user.username_taken"))
     }
 
     async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
-        sqlx::query_as::<_, User>(
-            "SELECT id, username, password_hash, role, created_at FROM users WHERE username = $1",
+        sqlx::query_as!(
+            User,
+            r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!" FROM users WHERE username = $1"#,
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_by_id(&self, id: i64) -> Result<Option<User>, AppError> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!" FROM users WHERE id = $1"#,
+            id
         )
-        .bind(username)
         .fetch_optional(&self.pool)
         .await
         .map_err(AppError::Database)
     }
 
-    async fn get_all(
+    async fn get_all(&self, params: &UserSearch) -> Result<Page<User>, AppError> {
+        // Consulta fija (verificada al compilar): cada filtro se anula con NULL y
+        // el orden lo eligen los CASE según `sort`/`order`
+        let f = Filters::new(params);
+        let search = params.text().map(search::like_pattern);
+        let items = sqlx::query_as!(
+            User,
+            r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!"
+            FROM users
+            WHERE ($1 IS NULL OR username LIKE $1 ESCAPE '\' OR display_name LIKE $1 ESCAPE '\' OR ($10 AND email LIKE $1 ESCAPE '\'))
+                AND ($2 IS NULL OR role = $2) AND ($3 IS NULL OR status = $3)
+                AND ($4 IS NULL OR created_at >= $4) AND ($5 IS NULL OR created_at < $5)
+            ORDER BY
+                CASE WHEN $6 = 'username' AND $7 = 'asc' THEN username COLLATE NOCASE END ASC,
+                CASE WHEN $6 = 'username' AND $7 = 'desc' THEN username COLLATE NOCASE END DESC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'asc' THEN created_at END ASC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'desc' THEN created_at END DESC,
+                CASE WHEN $6 = 'role' AND $7 = 'asc' THEN role END ASC,
+                CASE WHEN $6 = 'role' AND $7 = 'desc' THEN role END DESC,
+                CASE WHEN $6 IS NOT NULL AND $7 = 'desc' THEN id END DESC,
+                id ASC
+            LIMIT $8 OFFSET $9"#,
+            search,
+            f.role,
+            f.status,
+            f.created_from,
+            f.created_to,
+            f.sort,
+            f.order,
+            params.limit,
+            f.offset,
+            f.email
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "total!: i64" FROM users
+            WHERE ($1 IS NULL OR username LIKE $1 ESCAPE '\' OR display_name LIKE $1 ESCAPE '\' OR ($6 AND email LIKE $1 ESCAPE '\'))
+                AND ($2 IS NULL OR role = $2) AND ($3 IS NULL OR status = $3)
+                AND ($4 IS NULL OR created_at >= $4) AND ($5 IS NULL OR created_at < $5)"#,
+            search,
+            f.role,
+            f.status,
+            f.created_from,
+            f.created_to,
+            f.email
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Page { items, total })
+    }
+
+    async fn get_all_after(
         &self,
-        q: Option<String>,
-        page: i64,
-        limit: i64,
+        params: &UserSearch,
+        after: Option<&Keyset>,
     ) -> Result<Vec<User>, AppError> {
-        let offset = (page - 1) * limit;
-        let result = match q {
-            Some(ref text) if !text.is_empty() => {
-                let search = format!("%{}%", text);
-                sqlx::query_as::<_, User>("SELECT id, username, password_hash, role, created_at FROM users WHERE username LIKE $1 LIMIT $2 OFFSET $3")
-                    .bind(search).bind(limit).bind(offset)
-                    .fetch_all(&self.pool).await
-            },
-            _ => {
-                sqlx::query_as::<_, User>("SELECT id, username, password_hash, role, created_at FROM users LIMIT $1 OFFSET $2")
-                    .bind(limit).bind(offset)
-                    .fetch_all(&self.pool).await
+        // Una consulta por sentido: con ORDER BY fijo, SQLite recorre el índice
+        // (created_at, id) desde el cursor en vez de ordenar todas las filas
+        let f = Filters::new(params);
+        let search = params.text().map(search::like_pattern);
+        let after_at = after.map(|k| k.at.format(TIMESTAMP_FORMAT).to_string());
+        let after_id = after.map(|k| k.id);
+        let users = match params.order {
+            SortOrder::Asc => {
+                sqlx::query_as!(
+                    User,
+                    r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!"
+                    FROM users
+                    WHERE ($1 IS NULL OR username LIKE $1 ESCAPE '\' OR display_name LIKE $1 ESCAPE '\' OR ($9 AND email LIKE $1 ESCAPE '\'))
+                        AND ($2 IS NULL OR role = $2) AND ($3 IS NULL OR status = $3)
+                        AND ($4 IS NULL OR created_at >= $4) AND ($5 IS NULL OR created_at < $5)
+                        AND ($6 IS NULL OR (created_at, id) > ($6, $7))
+                    ORDER BY created_at ASC, id ASC
+                    LIMIT $8"#,
+                    search,
+                    f.role,
+                    f.status,
+                    f.created_from,
+                    f.created_to,
+                    after_at,
+                    after_id,
+                    params.limit,
+                    f.email
+                )
+                .fetch_all(&self.pool)
+                .await?
             }
+            SortOrder::Desc => {
+                sqlx::query_as!(
+                    User,
+                    r#"SELECT id as "id!", username as "username!", password_hash as "password_hash!", role as "role!: Role", status as "status!: UserStatus", created_at as "created_at!: DateTime<Utc>", locale as "locale?: Locale", display_name, email, last_login_at as "last_login_at: DateTime<Utc>", version as "version!"
+                    FROM users
+                    WHERE ($1 IS NULL OR username LIKE $1 ESCAPE '\' OR display_name LIKE $1 ESCAPE '\' OR ($9 AND email LIKE $1 ESCAPE '\'))
+                        AND ($2 IS NULL OR role = $2) AND ($3 IS NULL OR status = $3)
+                        AND ($4 IS NULL OR created_at >= $4) AND ($5 IS NULL OR created_at < $5)
+                        AND ($6 IS NULL OR (created_at, id) < ($6, $7))
+                    ORDER BY created_at DESC, id DESC
+                    LIMIT $8"#,
+                    search,
+                    f.role,
+                    f.status,
+                    f.created_from,
+                    f.created_to,
+                    after_at,
+                    after_id,
+                    params.limit,
+                    f.email
+                )
+                .fetch_all(&self.pool)
+                .await?
+            }
+        };
+        Ok(users)
+    }
+
+    async fn search(&self, params: &UserSearch) -> Result<Page<UserHit>, AppError> {
+        let q = params.text().unwrap_or_default();
+        // Sin palabras que indexar (p. ej. "@@") no hay consulta FTS posible
+        let Some(query) = fts_query(q, params.search_email) else {
+            let page = self.get_all(params).await?;
+            return Ok(Page {
+                items: search::like_hits(page.items, q, params.search_email),
+                total: page.total,
+            });
         };
-        result.map_err(AppError::Database)
-    }
-
-    async fn delete_user(&self, id: i64, admin_username: &str) -> Result<(), AppError> {
-        // Transacción implícita o lógica de negocio encapsulada
-        let target = sqlx::query_scalar::<_, String>("SELECT username FROM users WHERE id = $1")
-            .bind(id)
-            .fetch_optional(&self.pool)
-            .await?
-            .unwrap_or("Fantasma".to_string());
-        sqlx::query("DELETE FROM users WHERE id = $1")
-            .bind(id)
+        let f = Filters::new(params);
+        // snippet(): char(2)/char(3) son search::MARK_START/MARK_END.
+        // Sin `sort`, por relevancia: bm25() pesa más el username que el nombre
+        // visible y este más que el email; a igual puntuación, el username más
+        // corto (el más parecido a lo buscado)
+        let rows = sqlx::query!(
+            r#"SELECT u.id as "id!", u.username as "username!", u.password_hash as "password_hash!", u.role as "role!: Role", u.status as "status!: UserStatus", u.created_at as "created_at!: DateTime<Utc>", u.locale as "locale?: Locale", u.display_name as "display_name?", u.email as "email?", u.last_login_at as "last_login_at: DateTime<Utc>", u.version as "version!",
+                snippet(users_fts, -1, char(2), char(3), '…', 10) as "highlight!: String"
+            FROM users_fts JOIN users u ON u.id = users_fts.rowid
+            WHERE users_fts MATCH $1
+                AND ($2 IS NULL OR u.role = $2) AND ($3 IS NULL OR u.status = $3)
+                AND ($4 IS NULL OR u.created_at >= $4) AND ($5 IS NULL OR u.created_at < $5)
+            ORDER BY
+                CASE WHEN $6 = 'username' AND $7 = 'asc' THEN u.username COLLATE NOCASE END ASC,
+                CASE WHEN $6 = 'username' AND $7 = 'desc' THEN u.username COLLATE NOCASE END DESC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'asc' THEN u.created_at END ASC,
+                CASE WHEN $6 = 'created_at' AND $7 = 'desc' THEN u.created_at END DESC,
+                CASE WHEN $6 = 'role' AND $7 = 'asc' THEN u.role END ASC,
+                CASE WHEN $6 = 'role' AND $7 = 'desc' THEN u.role END DESC,
+                CASE WHEN $6 IS NOT NULL AND $7 = 'desc' THEN u.id END DESC,
+                CASE WHEN $6 IS NOT NULL THEN u.id END ASC,
+                bm25(users_fts, 10.0, 5.0, 1.0), length(u.username), u.id
+            LIMIT $8 OFFSET $9"#,
+            query,
+            f.role,
+            f.status,
+            f.created_from,
+            f.created_to,
+            f.sort,
+            f.order,
+            params.limit,
+            f.offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "total!: i64"
+            FROM users_fts JOIN users u ON u.id = users_fts.rowid
+            WHERE users_fts MATCH $1
+                AND ($2 IS NULL OR u.role = $2) AND ($3 IS NULL OR u.status = $3)
+                AND ($4 IS NULL OR u.created_at >= $4) AND ($5 IS NULL OR u.created_at < $5)"#,
+            query,
+            f.role,
+            f.status,
+            f.created_from,
+            f.created_to
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let items = rows
+            .into_iter()
+            .map(|row| UserHit {
+                highlight: Some(search::marked_to_html(&row.highlight)),
+                user: User {
+                    id: row.id,
+                    username: row.username,
+                    password_hash: row.password_hash,
+                    role: row.role,
+                    status: row.status,
+                    created_at: row.created_at,
+                    locale: row.locale,
+                    display_name: row.display_name,
+                    email: row.email,
+                    last_login_at: row.last_login_at,
+                    version: row.version,
+                },
+            })
+            .collect();
+        Ok(Page { items, total })
+    }
+
+    async fn delete_user(&self, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query!("DELETE FROM users WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_locale(&self, username: &str, locale: Option<Locale>) -> Result<(), AppError> {
+        sqlx::query!(
+            "UPDATE users SET locale = $1 WHERE username = $2",
+            locale,
+            username
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn set_profile(
+        &self,
+        username: &str,
+        display_name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            "UPDATE users SET display_name = $1, email = $2, version = version + 1 WHERE username = $3",
+            display_name,
+            email,
+            username
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_login(&self, id: i64, at: DateTime<Utc>) -> Result<(), AppError> {
+        // Mismo formato que CURRENT_TIMESTAMP
+        let at = at.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query!("UPDATE users SET last_login_at = $1 WHERE id = $2", at, id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: i64) -> Result<(), AppError> {
+        // Los revocados ya vencidos no aportan nada: se purgan de paso
+        sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at < strftime('%s', 'now')")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query!(
+            "INSERT OR IGNORE INTO revoked_tokens (jti, expires_at) VALUES ($1, $2)",
+            jti,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let revoked = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1) AS "revoked!: bool""#,
+            jti
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(revoked)
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError> {
+        let now = now.format(TIMESTAMP_FORMAT).to_string();
+        let expires_at = expires_at.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query!("DELETE FROM idempotency_keys WHERE expires_at <= $1", now)
             .execute(&self.pool)
             .await?;
-        sqlx::query("INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, 'DELETE_USER', $2)").bind(admin_username).bind(target).execute(&self.pool).await?;
+        let reserved = sqlx::query!(
+            "INSERT OR IGNORE INTO idempotency_keys (key, scope, fingerprint, expires_at) VALUES ($1, $2, $3, $4)",
+            key,
+            scope,
+            fingerprint,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+        if reserved.rows_affected() > 0 {
+            return Ok(None);
+        }
+        sqlx::query_as!(
+            IdempotencyRecord,
+            r#"SELECT key as "key!", scope as "scope!", fingerprint as "fingerprint!", status as "status: i32", headers, body, created_at as "created_at!: DateTime<Utc>", expires_at as "expires_at!: DateTime<Utc>" FROM idempotency_keys WHERE key = $1 AND scope = $2"#,
+            key,
+            scope
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        scope: &str,
+        status: i32,
+        headers: &str,
+        body: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let expires_at = expires_at.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query!(
+            "UPDATE idempotency_keys SET status = $1, headers = $2, body = $3, expires_at = $4 WHERE key = $5 AND scope = $6",
+            status,
+            headers,
+            body,
+            expires_at,
+            key,
+            scope
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str, scope: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            "DELETE FROM idempotency_keys WHERE key = $1 AND scope = $2",
+            key,
+            scope
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_audit(
+        &self,
+        admin_username: &str,
+        action: &str,
+        target: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            "INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)",
+            admin_username,
+            action,
+            target
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
     async fn get_audit_logs(&self) -> Result<Vec<AuditLog>, AppError> {
-        sqlx::query_as::<_, AuditLog>(
-            "SELECT id, admin_username, action, target, timestamp FROM audit_logs ORDER BY id DESC",
+        sqlx::query_as!(
+            AuditLog,
+            r#"SELECT id as "id!", admin_username as "admin_username!", action as "action!", target as "target!", timestamp as "timestamp!: DateTime<Utc>" FROM audit_logs ORDER BY id DESC"#
         )
         .fetch_all(&self.pool)
         .await
         .map_err(AppError::Database)
     }
+
+    async fn get_audit_logs_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        let after_at = after.map(|k| k.at.format(TIMESTAMP_FORMAT).to_string());
+        let after_id = after.map(|k| k.id);
+        sqlx::query_as!(
+            AuditLog,
+            r#"SELECT id as "id!", admin_username as "admin_username!", action as "action!", target as "target!", timestamp as "timestamp!: DateTime<Utc>" FROM audit_logs
+            WHERE ($1 IS NULL OR (timestamp, id) < ($1, $2))
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $3"#,
+            after_at,
+            after_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_logs_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<AuditLog>, AppError> {
+        // Mismo formato que la columna para que la comparación de texto sea válida
+        let cutoff = cutoff.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query_as!(
+            AuditLog,
+            r#"SELECT id as "id!", admin_username as "admin_username!", action as "action!", target as "target!", timestamp as "timestamp!: DateTime<Utc>" FROM audit_logs WHERE timestamp < $1 ORDER BY id ASC LIMIT $2"#,
+            cutoff,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_logs_for(&self, username: &str) -> Result<Vec<AuditLog>, AppError> {
+        sqlx::query_as!(
+            AuditLog,
+            r#"SELECT id as "id!", admin_username as "admin_username!", action as "action!", target as "target!", timestamp as "timestamp!: DateTime<Utc>" FROM audit_logs
+            WHERE target = $1 OR substr(target, 1, length($1) + 1) = $1 || ':'
+            ORDER BY timestamp DESC, id DESC"#,
+            username
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn record_security_event(
+        &self,
+        reason: &str,
+        method: &str,
+        path: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            "INSERT INTO security_events (reason, method, path) VALUES ($1, $2, $3)",
+            reason,
+            method,
+            path
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_security_events_after(
+        &self,
+        after: Option<&Keyset>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        let after_at = after.map(|k| k.at.format(TIMESTAMP_FORMAT).to_string());
+        let after_id = after.map(|k| k.id);
+        sqlx::query_as!(
+            SecurityEvent,
+            r#"SELECT id as "id!", reason as "reason!", method as "method!", path as "path!", timestamp as "timestamp!: DateTime<Utc>" FROM security_events
+            WHERE ($1 IS NULL OR (timestamp, id) < ($1, $2))
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $3"#,
+            after_at,
+            after_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_security_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<SecurityEvent>, AppError> {
+        let cutoff = cutoff.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query_as!(
+            SecurityEvent,
+            r#"SELECT id as "id!", reason as "reason!", method as "method!", path as "path!", timestamp as "timestamp!: DateTime<Utc>" FROM security_events WHERE timestamp < $1 ORDER BY id ASC LIMIT $2"#,
+            cutoff,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_audit_archives(&self) -> Result<Vec<AuditArchive>, AppError> {
+        sqlx::query_as!(
+            AuditArchive,
+            r#"SELECT id as "id!", kind as "kind!: ArchiveKind", file_name as "file_name!", entries as "entries!", first_log_id as "first_log_id!", last_log_id as "last_log_id!", last_hash as "last_hash!", sha256 as "sha256!", created_at as "created_at!: DateTime<Utc>" FROM audit_archives ORDER BY id DESC"#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn commit_audit_archive(
+        &self,
+        archive: NewAuditArchive,
+        cutoff: DateTime<Utc>,
+    ) -> Result<AuditArchive, AppError> {
+        let cutoff = cutoff.format(TIMESTAMP_FORMAT).to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let record = sqlx::query_as!(
+            AuditArchive,
+            r#"INSERT INTO audit_archives (kind, file_name, entries, first_log_id, last_log_id, last_hash, sha256) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id as "id!", kind as "kind!: ArchiveKind", file_name as "file_name!", entries as "entries!", first_log_id as "first_log_id!", last_log_id as "last_log_id!", last_hash as "last_hash!", sha256 as "sha256!", created_at as "created_at!: DateTime<Utc>""#,
+            archive.kind,
+            archive.file_name,
+            archive.entries,
+            archive.first_log_id,
+            archive.last_log_id,
+            archive.last_hash,
+            archive.sha256
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| conflict_on_unique(e, "audit.archive_exists"))?;
+
+        let action = match archive.kind {
+            ArchiveKind::AuditLogs => {
+                sqlx::query!(
+                    "DELETE FROM audit_logs WHERE id BETWEEN $1 AND $2 AND timestamp < $3",
+                    archive.first_log_id,
+                    archive.last_log_id,
+                    cutoff
+                )
+                .execute(&mut *tx)
+                .await?;
+                "ARCHIVE_AUDIT_LOGS"
+            }
+            ArchiveKind::SecurityEvents => {
+                sqlx::query!(
+                    "DELETE FROM security_events WHERE id BETWEEN $1 AND $2 AND timestamp < $3",
+                    archive.first_log_id,
+                    archive.last_log_id,
+                    cutoff
+                )
+                .execute(&mut *tx)
+                .await?;
+                "ARCHIVE_SECURITY_EVENTS"
+            }
+        };
+
+        sqlx::query!(
+            "INSERT INTO audit_logs (admin_username, action, target) VALUES ($1, $2, $3)",
+            SYSTEM_ACTOR,
+            action,
+            archive.file_name
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(record)
+    }
+
+    async fn get_pending_erasure(&self, user_id: i64) -> Result<Option<ErasureRequest>, AppError> {
+        sqlx::query_as!(
+            ErasureRequest,
+            r#"SELECT id as "id!", user_id as "user_id!", pseudonym as "pseudonym!", status as "status!: ErasureStatus", requested_at as "requested_at!: DateTime<Utc>", scheduled_for as "scheduled_for!: DateTime<Utc>", resolved_at as "resolved_at: DateTime<Utc>" FROM erasure_requests WHERE user_id = $1 AND status = 'pending'"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+
+    async fn get_due_erasures(&self, now: DateTime<Utc>) -> Result<Vec<ErasureRequest>, AppError> {
+        let now = now.format(TIMESTAMP_FORMAT).to_string();
+        sqlx::query_as!(
+            ErasureRequest,
+            r#"SELECT id as "id!", user_id as "user_id!", pseudonym as "pseudonym!", status as "status!: ErasureStatus", requested_at as "requested_at!: DateTime<Utc>", scheduled_for as "scheduled_for!: DateTime<Utc>", resolved_at as "resolved_at: DateTime<Utc>" FROM erasure_requests WHERE status = 'pending' AND scheduled_for <= $1 ORDER BY scheduled_for, id"#,
+            now
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::Database)
+    }
+}
+
+/// Parámetros de `UserSearch` tal como se enlazan en las consultas del listado.
+struct Filters {
+    role: Option<&'static str>,
+    status: Option<&'static str>,
+    // Mismo formato que CURRENT_TIMESTAMP para comparar como texto
+    created_from: Option<String>,
+    created_to: Option<String>,
+    sort: Option<&'static str>,
+    order: &'static str,
+    offset: i64,
+    email: bool,
+}
+
+impl Filters {
+    fn new(params: &UserSearch) -> Self {
+        let timestamp =
+            |at: Option<DateTime<Utc>>| at.map(|at| at.format(TIMESTAMP_FORMAT).to_string());
+        Self {
+            role: params.role.as_ref().map(Role::as_str),
+            status: params.status.map(|s| s.as_str()),
+            created_from: timestamp(params.created_from),
+            created_to: timestamp(params.created_to),
+            sort: params.sort.map(|s| s.as_str()),
+            order: params.order.as_str(),
+            offset: params.offset(),
+            email: params.search_email,
+        }
+    }
+}
+
+/// Traduce el texto libre a una consulta FTS5: cada palabra, entre comillas
+/// (sin operadores) y con `*` para buscar por prefijo; todas deben aparecer.
+/// Parte por lo mismo que el tokenizador `unicode61`: lo que no es alfanumérico.
+/// Sin `email`, un filtro de columnas deja fuera el email.
+fn fts_query(q: &str, email: bool) -> Option<String> {
+    let terms: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    let query = terms.join(" ");
+    if email {
+        Some(query)
+    } else {
+        Some(format!("{{username display_name}} : ({})", query))
+    }
 }
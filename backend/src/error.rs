@@ -1,18 +1,326 @@
+use crate::i18n::{self, Locale};
 use axum::{
-    http::StatusCode,
+    extract::rejection::JsonRejection,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, future::Future};
+use utoipa::ToSchema;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
+/// Los `String` de cada variante son claves del catálogo `i18n` (o texto libre,
+/// que se devuelve tal cual si no existe como clave).
 #[derive(Debug)]
 pub enum AppError {
     Database(sqlx::Error),
     NotFound(String),
     Validation(String),
+    /// Errores de `validator` con el detalle de cada campo
+    ValidationFields(ValidationErrors),
+    /// El cuerpo JSON no se pudo leer (sintaxis, tipos o content-type)
+    Rejection(JsonRejection),
     AuthError(String),
+    /// Rechazo de `auth_guard`/`admin_guard` con su motivo
+    Guard(AuthFailure),
     Conflict(String),
     Forbidden(String),
+    /// `If-Match` no coincide con la versión actual del recurso
+    PreconditionFailed(String),
+    /// Falta `If-Match` y la configuración lo exige (`require_if_match`)
+    PreconditionRequired(String),
+    /// Petición bien formada pero incompatible con el estado guardado (p. ej.
+    /// una `Idempotency-Key` reutilizada con otro cuerpo)
+    Unprocessable(String),
+    Internal(String),
+}
+
+/// Motivo por el que un guard rechaza la petición.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    MissingToken,
+    Expired,
+    InvalidSignature,
+    /// Token mal formado o con claims ilegibles
+    InvalidToken,
+    RevokedSession,
+    InsufficientRole,
+    /// Token válido de una cuenta suspendida
+    SuspendedAccount,
+}
+
+impl AuthFailure {
+    pub const ALL: [AuthFailure; 7] = [
+        AuthFailure::MissingToken,
+        AuthFailure::Expired,
+        AuthFailure::InvalidSignature,
+        AuthFailure::InvalidToken,
+        AuthFailure::RevokedSession,
+        AuthFailure::InsufficientRole,
+        AuthFailure::SuspendedAccount,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthFailure::MissingToken => "token_missing",
+            AuthFailure::Expired => "token_expired",
+            AuthFailure::InvalidSignature => "token_invalid_signature",
+            AuthFailure::InvalidToken => "token_invalid",
+            AuthFailure::RevokedSession => "session_revoked",
+            AuthFailure::InsufficientRole => "insufficient_role",
+            AuthFailure::SuspendedAccount => "account_suspended",
+        }
+    }
+
+    /// Valor de `WWW-Authenticate` (esquema Bearer, RFC 6750).
+    pub fn challenge(&self) -> String {
+        let realm = "Bearer realm=\"sintonia\"";
+        match self {
+            AuthFailure::MissingToken => realm.to_string(),
+            AuthFailure::InsufficientRole => format!("{}, error=\"insufficient_scope\"", realm),
+            other => format!(
+                "{}, error=\"invalid_token\", error_description=\"{}\"",
+                realm,
+                other.code()
+            ),
+        }
+    }
+}
+
+/// Formato del cuerpo de error. `Legacy` conserva el antiguo `{"error": "..."}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    #[default]
+    Problem,
+    Legacy,
+}
+
+/// Datos de la petición en curso que necesita el render de errores.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    pub request_id: Option<String>,
+    pub format: ErrorFormat,
+    pub locale: Locale,
+}
+
+tokio::task_local! {
+    static ERROR_CONTEXT: ErrorContext;
+}
+
+/// Ejecuta `fut` con el contexto de error disponible para `AppError::into_response`.
+pub async fn scope_error_context<F: Future>(ctx: ErrorContext, fut: F) -> F::Output {
+    ERROR_CONTEXT.scope(ctx, fut).await
+}
+
+/// Cuerpo `application/problem+json` (RFC 7807).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    /// URI que identifica el tipo de problema (`urn:sintonia:error:<code>`)
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Id de la petición (`x-request-id`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Código estable para que los clientes ramifiquen
+    pub code: String,
+    /// Reglas incumplidas por campo (solo en `validation_failed`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<BTreeMap<String, Vec<FieldError>>>,
+}
+
+/// Una regla de validación incumplida por un campo.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Regla incumplida (ej: `length`, `email`)
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Parámetros de la regla (ej: `min`)
+    #[schema(value_type = Object)]
+    pub params: Map<String, Value>,
+}
+
+/// Errores de validación por campo, traducidos: el `errors` de `ProblemDetails`
+/// (también lo usan los informes por fila de la importación masiva).
+pub fn field_errors(
+    errors: &ValidationErrors,
+    locale: Locale,
+) -> BTreeMap<String, Vec<FieldError>> {
+    let mut fields = BTreeMap::new();
+    collect_field_errors(errors, "", locale, &mut fields);
+    fields
+}
+
+/// Aplana los errores de `validator` a rutas de campo (`items[0].name`),
+/// traduciendo cada mensaje con los parámetros de su regla.
+fn collect_field_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    locale: Locale,
+    out: &mut BTreeMap<String, Vec<FieldError>>,
+) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                let entries = list.iter().map(|e| {
+                    // `value` repite lo que envió el cliente (podría ser una contraseña)
+                    let params: Map<String, Value> = e
+                        .params
+                        .iter()
+                        .filter(|(k, _)| *k != "value")
+                        .map(|(k, v)| (k.to_string(), v.clone()))
+                        .collect();
+                    let message = e.message.as_ref().map(|key| {
+                        let values: Vec<(&str, String)> = params
+                            .iter()
+                            .map(|(k, v)| match v {
+                                Value::String(text) => (k.as_str(), text.clone()),
+                                other => (k.as_str(), other.to_string()),
+                            })
+                            .collect();
+                        i18n::t_with(locale, key, &values)
+                    });
+                    FieldError {
+                        code: e.code.to_string(),
+                        message,
+                        params,
+                    }
+                });
+                out.entry(path).or_default().extend(entries);
+            }
+            ValidationErrorsKind::Struct(inner) => collect_field_errors(inner, &path, locale, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, inner) in items {
+                    collect_field_errors(inner, &format!("{}[{}]", path, index), locale, out);
+                }
+            }
+        }
+    }
+}
+
+impl AppError {
+    /// Código de máquina estable por variante. No cambiar: los clientes dependen de él.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "database_error",
+            AppError::NotFound(_) => "not_found",
+            AppError::Validation(_) | AppError::ValidationFields(_) => "validation_failed",
+            AppError::Rejection(JsonRejection::JsonSyntaxError(_)) => "malformed_json",
+            AppError::Rejection(JsonRejection::MissingJsonContentType(_)) => {
+                "unsupported_media_type"
+            }
+            AppError::Rejection(_) => "invalid_body",
+            AppError::AuthError(_) => "unauthorized",
+            AppError::Guard(failure) => failure.code(),
+            AppError::Conflict(_) => "conflict",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::Unprocessable(_) => "unprocessable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Validation(_) | AppError::ValidationFields(_) => StatusCode::BAD_REQUEST,
+            AppError::Rejection(rejection) => rejection.status(),
+            AppError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            AppError::Guard(AuthFailure::InsufficientRole | AuthFailure::SuspendedAccount) => {
+                StatusCode::FORBIDDEN
+            }
+            AppError::Guard(_) => StatusCode::UNAUTHORIZED,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Mensaje para el cliente. Los errores internos nunca exponen el detalle.
+    fn detail(&self, locale: Locale) -> String {
+        match self {
+            AppError::Database(e) => {
+                tracing::error!("❌ Error de Base de Datos: {:?}", e);
+                i18n::t(locale, "error.internal")
+            }
+            AppError::Internal(detail) => {
+                tracing::error!("❌ Error Interno: {}", detail);
+                i18n::t(locale, "error.internal")
+            }
+            AppError::ValidationFields(e) => {
+                let summary = field_errors(e, locale)
+                    .iter()
+                    .map(|(field, errors)| {
+                        let messages: Vec<&str> = errors
+                            .iter()
+                            .map(|e| e.message.as_deref().unwrap_or(&e.code))
+                            .collect();
+                        format!("{}: {}", field, messages.join(", "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                format!("{}: {}", i18n::t(locale, "error.invalid_data"), summary)
+            }
+            AppError::Rejection(rejection) => format!(
+                "{}: {}",
+                i18n::t(locale, "error.invalid_json"),
+                rejection.body_text()
+            ),
+            AppError::Guard(failure) => i18n::t(locale, &format!("auth.{}", failure.code())),
+            AppError::NotFound(msg)
+            | AppError::Validation(msg)
+            | AppError::AuthError(msg)
+            | AppError::Conflict(msg)
+            | AppError::Forbidden(msg)
+            | AppError::PreconditionFailed(msg)
+            | AppError::PreconditionRequired(msg)
+            | AppError::Unprocessable(msg) => i18n::t(locale, msg),
+        }
+    }
+
+    pub fn to_problem(&self, instance: Option<String>, locale: Locale) -> ProblemDetails {
+        let code = self.code();
+        let errors = match self {
+            AppError::ValidationFields(e) => Some(field_errors(e, locale)),
+            _ => None,
+        };
+        ProblemDetails {
+            problem_type: format!("urn:sintonia:error:{}", code),
+            title: i18n::t(locale, &format!("title.{}", code)),
+            status: self.status().as_u16(),
+            detail: self.detail(locale),
+            instance,
+            code: code.to_string(),
+            errors,
+        }
+    }
+}
+
+/// Mensaje para operadores (CLI, consola): a diferencia de la respuesta HTTP
+/// sí muestra el detalle de los errores internos.
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "{}", e),
+            AppError::Internal(detail) => write!(f, "{}", detail),
+            _ => write!(f, "{}", self.detail(Locale::default())),
+        }
+    }
 }
 
 // Permite usar `?` con errores de SQLx automáticamente
@@ -22,28 +330,101 @@ impl From<sqlx::Error> for AppError {
     }
 }
 
+// Rechazos del extractor `Json` (sintaxis, tipos, content-type)
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::Rejection(rejection)
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::ValidationFields(errors)
+    }
+}
+
 // Convierte nuestro error en una Respuesta HTTP real
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::Database(e) => {
-                tracing::error!("❌ Error de Base de Datos: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Error interno del servidor".to_string(),
-                )
-            }
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            AppError::Validation(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::AuthError(msg) => (StatusCode::UNAUTHORIZED, msg),
-            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
-            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
+        let ctx = ERROR_CONTEXT
+            .try_with(|ctx| ctx.clone())
+            .unwrap_or_default();
+        let status = self.status();
+        let challenge = match &self {
+            AppError::Guard(failure) => HeaderValue::from_str(&failure.challenge()).ok(),
+            _ => None,
         };
 
-        let body = Json(json!({
-            "error": message
-        }));
+        let mut response = match ctx.format {
+            ErrorFormat::Legacy => {
+                let body = Json(json!({
+                    "error": self.detail(ctx.locale)
+                }));
+                (status, body).into_response()
+            }
+            ErrorFormat::Problem => {
+                let mut response =
+                    (status, Json(self.to_problem(ctx.request_id, ctx.locale))).into_response();
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/problem+json"),
+                );
+                response
+            }
+        };
+
+        if let Some(challenge) = challenge {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_details_shape() {
+        let problem = AppError::Conflict("user.username_taken".to_string())
+            .to_problem(Some("req-123".to_string()), Locale::Es);
+        let value = serde_json::to_value(&problem).unwrap();
+
+        assert_eq!(value["type"], "urn:sintonia:error:conflict");
+        assert_eq!(value["status"], 409);
+        assert_eq!(value["code"], "conflict");
+        assert_eq!(value["detail"], "El nombre de usuario ya existe");
+        assert_eq!(value["instance"], "req-123");
+    }
+
+    #[test]
+    fn test_internal_errors_hide_detail() {
+        let problem = AppError::Internal("disco lleno".to_string()).to_problem(None, Locale::En);
+        assert_eq!(problem.detail, "Internal server error");
+        assert_eq!(problem.code, "internal_error");
+    }
+
+    #[test]
+    fn test_validation_fields_detail() {
+        use crate::core::models::user::CreateUserRequest;
+        use validator::Validate;
+
+        let req = CreateUserRequest {
+            username: "yo".to_string(),
+            password: "123".to_string(),
+        };
+        let problem = AppError::from(req.validate().unwrap_err()).to_problem(None, Locale::En);
+        let errors = problem.errors.unwrap();
 
-        (status, body).into_response()
+        assert_eq!(problem.code, "validation_failed");
+        assert_eq!(errors["username"][0].code, "length");
+        assert_eq!(errors["username"][0].params["min"], 3);
+        assert!(!errors["password"][0].params.contains_key("value"));
+        assert_eq!(
+            errors["username"][0].message.as_deref(),
+            Some("Username must be at least 3 characters long")
+        );
     }
 }
@@ -0,0 +1,109 @@
+//! English messages.
+
+pub fn message(key: &str) -> Option<&'static str> {
+    Some(match key {
+        // Problem Details titles (by error code)
+        "title.database_error" | "title.internal_error" => "Internal error",
+        "title.not_found" => "Resource not found",
+        "title.validation_failed" => "Invalid data",
+        "title.malformed_json" | "title.invalid_body" | "title.unsupported_media_type" => {
+            "Invalid request body"
+        }
+        "title.unauthorized" => "Not authenticated",
+        "title.conflict" => "Conflict",
+        "title.forbidden" => "Access denied",
+        "title.token_missing" => "Not authenticated",
+        "title.token_expired" => "Session expired",
+        "title.token_invalid_signature" => "Invalid token",
+        "title.token_invalid" => "Invalid token",
+        "title.session_revoked" => "Session revoked",
+        "title.insufficient_role" => "Access denied",
+        "title.account_suspended" => "Account suspended",
+        "title.precondition_failed" => "Outdated version",
+        "title.precondition_required" => "If-Match required",
+        "title.unprocessable" => "Unprocessable request",
+
+        // Errors
+        "error.internal" => "Internal server error",
+        "error.invalid_data" => "Invalid data",
+        "error.invalid_json" => "Invalid JSON body",
+        "auth.invalid_credentials" => "Invalid credentials",
+        "auth.credential_check_failed" => "Error verifying credentials",
+        "auth.hash_failed" => "Security error while processing the password",
+        "auth.token_failed" => "Error generating token",
+        "auth.session_invalid" => "Invalid or expired session",
+        "auth.token_missing" => "Session token missing",
+        "auth.token_expired" => "Session has expired",
+        "auth.token_invalid_signature" => "Token signature is not valid",
+        "auth.token_invalid" => "Invalid session token",
+        "auth.session_revoked" => "Session has been closed",
+        "auth.insufficient_role" => "Insufficient role",
+        "auth.account_suspended" => "The account is suspended",
+        "auth.password_token_invalid" => "The password code is invalid or has already been used",
+        "user.username_taken" => "Username already exists",
+        "user.not_found" => "User not found",
+        "backup.unavailable" => "Backups are only available with SQLite",
+        "backup.exists" => "A backup with that name already exists; try again",
+        "backup.not_found" => "That backup does not exist",
+        "backup.database_in_use" => "The database is still open: stop the server before restoring (after a crash, `backend-admin migrate status` checkpoints the WAL)",
+        "backup.incompatible_schema" => "The backup is not compatible with this binary (it has unknown or modified migrations)",
+        "backup.checksum_mismatch" => "The backup checksum does not match its manifest",
+
+        // Validation (`validator`)
+        "validation.username_length" => "Username must be at least {min} characters long",
+        "validation.username_chars" => "Username cannot contain ':'",
+        "validation.password_length" => "Password must be at least {min} characters long",
+        "validation.display_name_length" => "Display name must be at most {max} characters long",
+        "validation.query" => "A URL parameter is invalid (wrong type or unknown value)",
+        "validation.page" => "Page must be {min} or greater",
+        "validation.limit" => "Limit must be between {min} and {max}",
+        "validation.cursor" => "Invalid or expired pagination cursor",
+        "validation.unknown_field" => "Unknown field '{field}'; allowed: {allowed}",
+        "validation.cursor_sort" => "Cursor pagination only supports sorting by `created_at`",
+        "validation.email" => "Email is not valid",
+
+        // Handler responses
+        "login.success" => "Login successful",
+        "logout.success" => "Logged out successfully",
+        "password.updated" => "Password saved; you can now log in",
+        "user.deleted" => "User deleted and audited",
+        "locale.updated" => "Language updated",
+        "import.empty" => "The file contains no rows",
+        "import.too_many_rows" => "At most 5000 rows per import are allowed",
+        "import.unsupported_format" => "Unsupported format: use text/csv or application/x-ndjson",
+        "import.invalid_encoding" => "The file must be UTF-8 encoded",
+        "import.invalid_row" => "Unreadable row: {detail}",
+        "import.duplicate_username" => "The username appears more than once in the file",
+        "import.concurrent_change" => "Another change created or deleted one of the users during the import; try again",
+        "import.password_required" => "Password is missing (or use invite=true)",
+        "import.invite_without_mailer" => "No mail server is configured: invite=true cannot send the invitations",
+        "import.email_required" => "Without a password an email is required to send the invitation",
+        "invite.subject" => "Invitation to Sintonía 3026",
+        "invite.body" => {
+            "Your account '{username}' has been created. Choose your password within {hours} hours by sending this code to POST /api/v1/password: {token}"
+        }
+        "erasure.already_requested" => "An erasure request is already pending",
+        "erasure.not_found" => "There is no pending erasure request",
+        "erasure.cancelled" => "Erasure request cancelled",
+        "audit.archive_exists" => "An audit archive with that name already exists",
+        "precondition.failed" => "The resource changed since you read it; reload it",
+        "precondition.if_match_required" => {
+            "The If-Match header with the resource ETag is required"
+        }
+        "validation.batch_size" => "A batch must have between {min} and {max} operations",
+        "validation.empty_update" => "There are no fields to update",
+        "idempotency.invalid_key" => {
+            "Idempotency-Key must have between 1 and 255 visible characters"
+        }
+        "idempotency.body_too_large" => "The body is too large for an idempotent request",
+        "idempotency.in_progress" => {
+            "Another request with the same Idempotency-Key is still in progress"
+        }
+        "idempotency.key_reused" => "The Idempotency-Key was already used with a different request",
+        "shaping.admin_only" => "Some requested fields or expansions are only available to admins",
+        "profile.updated" => "Profile updated",
+        "dashboard.welcome" => "🔐 Control Panel | Agent: {user} | Rank: {role}",
+
+        _ => return None,
+    })
+}
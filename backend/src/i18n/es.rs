@@ -0,0 +1,105 @@
+//! Mensajes en español (idioma por defecto).
+
+pub fn message(key: &str) -> Option<&'static str> {
+    Some(match key {
+        // Títulos de Problem Details (por código de error)
+        "title.database_error" | "title.internal_error" => "Error interno",
+        "title.not_found" => "Recurso no encontrado",
+        "title.validation_failed" => "Datos inválidos",
+        "title.malformed_json" | "title.invalid_body" | "title.unsupported_media_type" => {
+            "Cuerpo de la petición inválido"
+        }
+        "title.unauthorized" => "No autenticado",
+        "title.conflict" => "Conflicto",
+        "title.forbidden" => "Acceso denegado",
+        "title.token_missing" => "No autenticado",
+        "title.token_expired" => "Sesión expirada",
+        "title.token_invalid_signature" => "Token inválido",
+        "title.token_invalid" => "Token inválido",
+        "title.session_revoked" => "Sesión revocada",
+        "title.insufficient_role" => "Acceso denegado",
+        "title.account_suspended" => "Cuenta suspendida",
+        "title.precondition_failed" => "Versión desactualizada",
+        "title.precondition_required" => "Falta If-Match",
+        "title.unprocessable" => "Petición no procesable",
+
+        // Errores
+        "error.internal" => "Error interno del servidor",
+        "error.invalid_data" => "Datos inválidos",
+        "error.invalid_json" => "Cuerpo JSON inválido",
+        "auth.invalid_credentials" => "Credenciales inválidas",
+        "auth.credential_check_failed" => "Error verificando credenciales",
+        "auth.hash_failed" => "Error de seguridad al procesar la contraseña",
+        "auth.token_failed" => "Error generando token",
+        "auth.session_invalid" => "Sesión inválida o expirada",
+        "auth.token_missing" => "Falta el token de sesión",
+        "auth.token_expired" => "La sesión expiró",
+        "auth.token_invalid_signature" => "La firma del token no es válida",
+        "auth.token_invalid" => "Token de sesión inválido",
+        "auth.session_revoked" => "La sesión fue cerrada",
+        "auth.insufficient_role" => "No tiene el rango necesario",
+        "auth.account_suspended" => "La cuenta está suspendida",
+        "auth.password_token_invalid" => "El código para elegir contraseña no es válido o ya se usó",
+        "user.username_taken" => "El nombre de usuario ya existe",
+        "user.not_found" => "El usuario no existe",
+        "backup.unavailable" => "Las copias de seguridad solo están disponibles con SQLite",
+        "backup.exists" => "Ya existe una copia con ese nombre; vuelva a intentarlo",
+        "backup.not_found" => "No existe esa copia de seguridad",
+        "backup.database_in_use" => "La base sigue abierta: detenga el servidor antes de restaurar (tras una caída, `backend-admin migrate status` consolida el WAL)",
+        "backup.incompatible_schema" => "La copia no es compatible con este binario (tiene migraciones que no conoce o modificadas)",
+        "backup.checksum_mismatch" => "El checksum de la copia no coincide con su manifiesto",
+
+        // Validación (`validator`)
+        "validation.username_length" => "El usuario debe tener al menos {min} caracteres",
+        "validation.username_chars" => "El usuario no puede contener «:»",
+        "validation.password_length" => "La contraseña debe tener al menos {min} caracteres",
+        "validation.display_name_length" => "El nombre visible admite como máximo {max} caracteres",
+        "validation.query" => "Algún parámetro de la URL no es válido (tipo o valor desconocido)",
+        "validation.page" => "La página debe ser {min} o mayor",
+        "validation.limit" => "El límite debe estar entre {min} y {max}",
+        "validation.cursor" => "Cursor de paginación inválido o caducado",
+        "validation.unknown_field" => "Campo desconocido «{field}»; permitidos: {allowed}",
+        "validation.cursor_sort" => {
+            "La paginación por cursor solo admite el orden por `created_at`"
+        }
+        "validation.email" => "El email no es válido",
+
+        // Respuestas de handlers
+        "login.success" => "Login exitoso",
+        "logout.success" => "Sesión cerrada correctamente",
+        "password.updated" => "Contraseña guardada; ya puede iniciar sesión",
+        "user.deleted" => "Usuario eliminado y auditado",
+        "locale.updated" => "Idioma actualizado",
+        "import.empty" => "El archivo no contiene filas",
+        "import.too_many_rows" => "Se admiten como máximo 5000 filas por importación",
+        "import.unsupported_format" => "Formato no soportado: use text/csv o application/x-ndjson",
+        "import.invalid_encoding" => "El archivo debe estar en UTF-8",
+        "import.invalid_row" => "Fila ilegible: {detail}",
+        "import.duplicate_username" => "El usuario aparece más de una vez en el archivo",
+        "import.concurrent_change" => "Otro cambio dio de alta o borró alguno de los usuarios durante la importación; vuelva a intentarlo",
+        "import.password_required" => "Falta la contraseña (o use invite=true)",
+        "import.invite_without_mailer" => "No hay un servidor de correo configurado: invite=true no puede enviar las invitaciones",
+        "import.email_required" => "Sin contraseña hace falta un email para enviar la invitación",
+        "invite.subject" => "Invitación a Sintonía 3026",
+        "invite.body" => "Se ha creado tu cuenta «{username}». Elige tu contraseña en las próximas {hours} horas enviando este código a POST /api/v1/password: {token}",
+        "erasure.already_requested" => "Ya hay una solicitud de borrado pendiente",
+        "erasure.not_found" => "No hay ninguna solicitud de borrado pendiente",
+        "erasure.cancelled" => "Solicitud de borrado cancelada",
+        "audit.archive_exists" => "Ya existe un archivo de auditoría con ese nombre",
+        "precondition.failed" => "El recurso cambió desde que lo leíste; vuelve a cargarlo",
+        "precondition.if_match_required" => "Falta la cabecera If-Match con el ETag del recurso",
+        "validation.batch_size" => "El lote debe tener entre {min} y {max} operaciones",
+        "validation.empty_update" => "No hay ningún campo que actualizar",
+        "idempotency.invalid_key" => "Idempotency-Key debe tener entre 1 y 255 caracteres visibles",
+        "idempotency.body_too_large" => {
+            "El cuerpo es demasiado grande para una petición idempotente"
+        }
+        "idempotency.in_progress" => "Otra petición con la misma Idempotency-Key sigue en curso",
+        "idempotency.key_reused" => "La Idempotency-Key ya se usó con otra petición",
+        "shaping.admin_only" => "Hay campos o expansiones pedidos que solo pueden ver los admins",
+        "profile.updated" => "Perfil actualizado",
+        "dashboard.welcome" => "🔐 Panel de Control | Agente: {user} | Rango: {role}",
+
+        _ => return None,
+    })
+}
@@ -0,0 +1,131 @@
+//! Catálogo de mensajes de la API. Cada texto visible para el cliente se
+//! identifica por una clave y se traduce según el idioma de la petición.
+pub mod en;
+pub mod es;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use sqlx::Type;
+use std::convert::Infallible;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Es,
+    En,
+}
+
+impl Locale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::Es => "es",
+            Locale::En => "en",
+        }
+    }
+
+    /// Interpreta una etiqueta BCP 47 (`en-US`, `es`) por su subetiqueta primaria.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "es" => Some(Locale::Es),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    /// Elige el idioma soportado con mayor `q` de un header `Accept-Language`.
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut candidates: Vec<(f32, Locale)> = accept_language
+            .split(',')
+            .filter_map(|part| {
+                let mut pieces = part.split(';');
+                let locale = Locale::from_tag(pieces.next()?)?;
+                let q = pieces
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (q > 0.0).then_some((q, locale))
+            })
+            .collect();
+        // Orden estable: a igual `q` gana el que aparece primero
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.first().map(|(_, locale)| *locale)
+    }
+}
+
+/// El middleware de contexto deja el idioma resuelto en las extensiones.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Locale>()
+            .copied()
+            .unwrap_or_default())
+    }
+}
+
+fn lookup(locale: Locale, key: &str) -> Option<&'static str> {
+    match locale {
+        Locale::Es => es::message(key),
+        Locale::En => en::message(key),
+    }
+}
+
+/// Traduce `key`. Si falta en el idioma pedido usa el idioma por defecto y,
+/// en última instancia, devuelve la propia clave (texto libre pasa intacto).
+pub fn t(locale: Locale, key: &str) -> String {
+    lookup(locale, key)
+        .or_else(|| lookup(Locale::default(), key))
+        .unwrap_or(key)
+        .to_string()
+}
+
+/// Igual que [`t`] sustituyendo `{param}` por su valor.
+pub fn t_with(locale: Locale, key: &str, params: &[(&str, String)]) -> String {
+    params.iter().fold(t(locale, key), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_accept_language() {
+        assert_eq!(Locale::negotiate("en-US,en;q=0.9"), Some(Locale::En));
+        assert_eq!(
+            Locale::negotiate("fr-FR, es;q=0.5, en;q=0.8"),
+            Some(Locale::En)
+        );
+        assert_eq!(Locale::negotiate("de, fr"), None);
+        assert_eq!(Locale::negotiate("en;q=0, es;q=0.1"), Some(Locale::Es));
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        assert_eq!(
+            t(Locale::En, "auth.invalid_credentials"),
+            "Invalid credentials"
+        );
+        assert_eq!(
+            t(Locale::Es, "auth.invalid_credentials"),
+            "Credenciales inválidas"
+        );
+        assert_eq!(t(Locale::En, "texto libre"), "texto libre");
+        assert_eq!(
+            t_with(
+                Locale::En,
+                "validation.password_length",
+                &[("min", "8".to_string())]
+            ),
+            "Password must be at least 8 characters long"
+        );
+    }
+}
@@ -2,17 +2,19 @@ pub mod api;
 pub mod core;
 pub mod data;
 pub mod error;
+pub mod i18n;
 pub mod settings;
+pub mod state;
 
 use axum::{
     extract::State,
-    http::{header, Method, Request, StatusCode},
+    http::{header, HeaderName, Method, Request, StatusCode},
     middleware,
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use sqlx::SqlitePool;
+use state::AppState;
 use std::sync::Arc;
 use tower_cookies::CookieManagerLayer;
 use tower_governor::{
@@ -20,7 +22,7 @@ use tower_governor::{
 };
 use tower_http::cors::CorsLayer;
 use tower_http::{
-    request_id::{MakeRequestUuid, SetRequestIdLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     trace::TraceLayer,
 };
 use utoipa::OpenApi;
@@ -38,30 +40,99 @@ use utoipa_swagger_ui::SwaggerUi;
         api::handlers::user::get_users,
         api::handlers::user::login,
         api::handlers::user::logout,
+        api::handlers::user::set_password,
+        api::handlers::user::get_user,
+        api::handlers::user::update_user,
         api::handlers::user::delete_user,
         api::handlers::user::get_audit_logs,
+        api::handlers::user::get_audit_archives,
+        api::handlers::user::get_security_events,
         api::handlers::user::dashboard,
+        api::handlers::user::update_locale,
+        api::handlers::user::update_profile,
+        api::handlers::privacy::export_me,
+        api::handlers::privacy::request_erasure,
+        api::handlers::privacy::get_erasure,
+        api::handlers::privacy::cancel_erasure,
+        api::handlers::bulk::import_users,
+        api::handlers::bulk::export_users,
+        api::handlers::bulk::batch_users,
+        api::handlers::backup::create_backup,
+        api::handlers::backup::list_backups,
     ),
     components(schemas(
         core::models::user::User,
+        core::models::user::UserHit,
         core::models::user::CreateUserRequest,
         core::models::user::LoginRequest,
+        core::models::user::SetPasswordRequest,
         core::models::user::Role,
         core::models::user::AuditLog,
+        core::models::user::AuditArchive,
+        core::models::user::ArchiveKind,
         core::models::user::UserSearch,
+        core::models::user::UserSort,
+        core::models::user::SortOrder,
+        core::models::user::UserStatus,
+        api::pagination::UserCursorPage,
+        api::pagination::AuditLogCursorPage,
+        api::pagination::SecurityEventCursorPage,
+        core::models::user::SecurityEvent,
+        core::models::user::UpdateLocaleRequest,
+        core::models::user::UpdateProfileRequest,
+        core::models::user::UpdateUserRequest,
+        core::models::privacy::DataExport,
+        core::models::privacy::ProfileExport,
+        core::models::privacy::SessionsExport,
+        core::models::privacy::ErasureRequest,
+        core::models::privacy::ErasureStatus,
+        core::models::bulk::ImportRow,
+        core::models::bulk::ImportMode,
+        core::models::bulk::ImportReport,
+        core::models::bulk::ImportRowError,
+        core::models::bulk::ExportFormat,
+        core::models::bulk::BatchOperation,
+        core::models::bulk::BatchRequest,
+        core::models::bulk::BatchItemResult,
+        core::models::bulk::BatchReport,
+        data::backup::BackupInfo,
+        i18n::Locale,
+        error::ProblemDetails,
+        error::FieldError,
     ))
 )]
 pub struct ApiDoc;
 
-pub fn create_app(pool: SqlitePool) -> Router {
+pub fn create_app(state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(
             "http://localhost:4321"
                 .parse::<axum::http::HeaderValue>()
                 .unwrap(),
         )
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers([header::CONTENT_TYPE])
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::ACCEPT_LANGUAGE,
+            header::AUTHORIZATION,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static(api::middleware::IDEMPOTENCY_KEY),
+        ])
+        .expose_headers([
+            header::ETAG,
+            HeaderName::from_static(api::middleware::IDEMPOTENT_REPLAYED),
+            // Total y navegación del listado paginado (`UserList.astro`)
+            HeaderName::from_static("x-total-count"),
+            header::LINK,
+        ])
         .allow_credentials(true);
 
     // Configuración de Rate Limiting: 10 peticiones por segundo, ráfaga de 20
@@ -74,34 +145,153 @@ pub fn create_app(pool: SqlitePool) -> Router {
             .unwrap(),
     );
 
+    // Reintentos seguros con `Idempotency-Key`: en cada POST que crea o cambia
+    // datos, por dentro de su guard (login y logout manejan cookies y no se repiten)
+    let idempotency = middleware::from_fn_with_state(state.clone(), api::middleware::idempotency);
+
     let api_v1 = Router::new()
         .route(
             "/users",
-            post(api::handlers::user::create_user).get(api::handlers::user::get_users),
+            post(api::handlers::user::create_user)
+                .layer(idempotency.clone())
+                .merge(get(api::handlers::user::get_users).route_layer(
+                    middleware::from_fn_with_state(state.clone(), api::middleware::optional_auth),
+                )),
         )
         .route("/login", post(api::handlers::user::login))
         .route("/logout", post(api::handlers::user::logout))
+        .route("/password", post(api::handlers::user::set_password))
         .route(
             "/users/:id",
-            delete(api::handlers::user::delete_user)
-                .route_layer(middleware::from_fn(api::middleware::admin_guard)),
+            get(api::handlers::user::get_user)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::auth_guard,
+                ))
+                .merge(
+                    delete(api::handlers::user::delete_user)
+                        .patch(api::handlers::user::update_user)
+                        .route_layer(middleware::from_fn_with_state(
+                            state.clone(),
+                            api::middleware::admin_guard,
+                        )),
+                ),
+        )
+        .route(
+            "/users:batch",
+            post(api::handlers::bulk::batch_users)
+                .layer(idempotency.clone())
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::admin_guard,
+                )),
         )
         .route(
             "/dashboard",
-            get(api::handlers::user::dashboard)
-                .route_layer(middleware::from_fn(api::middleware::auth_guard)),
+            get(api::handlers::user::dashboard).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::auth_guard,
+            )),
+        )
+        .route(
+            "/me/locale",
+            put(api::handlers::user::update_locale).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::auth_guard,
+            )),
+        )
+        .route(
+            "/me/profile",
+            put(api::handlers::user::update_profile).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::auth_guard,
+            )),
+        )
+        .route(
+            "/me",
+            delete(api::handlers::privacy::request_erasure).route_layer(
+                middleware::from_fn_with_state(state.clone(), api::middleware::auth_guard),
+            ),
+        )
+        .route(
+            "/me/export",
+            get(api::handlers::privacy::export_me).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::auth_guard,
+            )),
+        )
+        .route(
+            "/me/erasure",
+            get(api::handlers::privacy::get_erasure)
+                .delete(api::handlers::privacy::cancel_erasure)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::auth_guard,
+                )),
         )
         .route(
             "/audit-logs",
-            get(api::handlers::user::get_audit_logs)
-                .route_layer(middleware::from_fn(api::middleware::admin_guard)),
+            get(api::handlers::user::get_audit_logs).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::admin_guard,
+            )),
+        )
+        .route(
+            "/audit-logs/archives",
+            get(api::handlers::user::get_audit_archives).route_layer(
+                middleware::from_fn_with_state(state.clone(), api::middleware::admin_guard),
+            ),
+        )
+        .route(
+            "/security-events",
+            get(api::handlers::user::get_security_events).route_layer(
+                middleware::from_fn_with_state(state.clone(), api::middleware::admin_guard),
+            ),
+        )
+        .route(
+            "/admin/users/import",
+            post(api::handlers::bulk::import_users)
+                .layer(idempotency.clone())
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::admin_guard,
+                )),
+        )
+        .route(
+            "/admin/users/export",
+            get(api::handlers::bulk::export_users).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::admin_guard,
+            )),
+        )
+        .route(
+            "/admin/backups",
+            post(api::handlers::backup::create_backup)
+                .layer(idempotency)
+                .get(api::handlers::backup::list_backups)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    api::middleware::admin_guard,
+                )),
         );
 
     Router::new()
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .route("/", get(root))
         .route("/health", get(health_check))
+        // Los contadores delatan intentos de acceso y volumen de uso: solo admins
+        .route(
+            "/metrics",
+            get(api::metrics::metrics).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::middleware::admin_guard,
+            )),
+        )
         .nest("/api/v1", api_v1)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::middleware::error_context,
+        ))
         .layer(CookieManagerLayer::new())
         .layer(GovernorLayer { config: governor_conf })
         .layer(cors) // CORS debe ser el último (externo) para manejar errores del Governor
@@ -115,15 +305,17 @@ pub fn create_app(pool: SqlitePool) -> Router {
                 })
                 .on_response(tower_http::trace::DefaultOnResponse::new().level(tracing::Level::INFO))
         )
+        // Devuelve el x-request-id al cliente (es el `instance` de los errores)
+        .layer(PropagateRequestIdLayer::x_request_id())
         .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
-        .with_state(pool)
+        .with_state(state)
 }
 
 async fn root() -> &'static str {
     "Sistema Semilla 3026: Online"
 }
-async fn health_check(State(pool): State<SqlitePool>) -> impl IntoResponse {
-    match sqlx::query("SELECT 1").execute(&pool).await {
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    match state.users.ping().await {
         Ok(_) => (StatusCode::OK, "Sintonía 3026: Operativo (DB Conectada)"),
         Err(e) => {
             tracing::error!("Health Check Fallido: {:?}", e);
@@ -1,6 +1,15 @@
-use backend::{create_app, settings::Settings}; // Importamos Settings
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use std::{net::SocketAddr, str::FromStr};
+use backend::{
+    core::clock::SystemClock,
+    create_app,
+    data::{
+        audit_archive::AuditArchiver,
+        backup::BackupManager,
+        db::{DbPool, MigrationState},
+    },
+    settings::{self, Settings},
+    state::AppState,
+}; // Importamos Settings
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -17,28 +26,113 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer().json())
         .init();
 
-    // 3. Conexión a Base de Datos (Crear archivo si no existe)
-    let db_url = settings.database_url;
-
-    let connection_options = SqliteConnectOptions::from_str(&db_url)
-        .unwrap()
-        .create_if_missing(true);
-
-    let pool = SqlitePoolOptions::new()
-        .connect_with(connection_options)
+    // 3. Conexión a Base de Datos (el esquema de la URL elige el backend)
+    let db = DbPool::connect_with(&settings.database_url, &settings.db_options())
         .await
         .expect("❌ Fallo al conectar a la Base de Datos");
 
-    // 3.1 Ejecutar Migraciones (Evolución de la DB)
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .expect("❌ Fallo al ejecutar migraciones");
+    // 3.1 Migraciones (Evolución de la DB): nunca contra un esquema más nuevo
+    if let Err(e) = db.ensure_not_ahead().await {
+        panic!("❌ {}", e);
+    }
+    if settings.auto_migrate {
+        db.migrate()
+            .await
+            .expect("❌ Fallo al ejecutar migraciones");
+    } else {
+        let pending = db
+            .migration_status()
+            .await
+            .expect("❌ Fallo al leer migraciones")
+            .iter()
+            .filter(|m| m.state == MigrationState::Pending)
+            .count();
+        if pending > 0 {
+            tracing::warn!(
+                "⚠️ {} migraciones pendientes y auto_migrate desactivado: ejecute backend-admin migrate up",
+                pending
+            );
+        }
+    }
 
-    tracing::info!("💾 Memoria conectada: {}", db_url);
+    tracing::info!("💾 Memoria conectada: {}", db.backend_name());
 
-    // 4. Construir la aplicación e inyectar el pool
-    let app = create_app(pool);
+    if settings.jwt_secret == settings::default_jwt_secret() {
+        tracing::warn!("⚠️ jwt_secret usa el valor por defecto: configure APP_JWT_SECRET");
+    }
+
+    // 4. Construir el estado compartido e inyectar el repositorio
+    let backups = BackupManager::for_db(
+        &db,
+        Arc::new(SystemClock),
+        &settings.backup_dir,
+        settings.backup_retention,
+        settings.backup_compress,
+    )
+    .map(Arc::new);
+    let mut builder = AppState::builder_shared(db.repository()).settings(settings);
+    if let Some(backups) = &backups {
+        builder = builder.backups(backups.clone());
+    }
+    let state = builder.build();
+    let settings = state.settings.clone();
+
+    // 4.1 Primer administrador (solo mientras no exista ninguno)
+    if let Some(bootstrap) = &settings.bootstrap_admin {
+        let credentials = bootstrap
+            .credentials()
+            .unwrap_or_else(|e| panic!("❌ bootstrap_admin inválido: {}", e));
+        match state
+            .user_service()
+            .bootstrap_admin(&credentials.username, &credentials.password)
+            .await
+        {
+            Ok(Some(admin)) => tracing::info!("👑 Admin inicial creado: {}", admin.username),
+            Ok(None) => tracing::debug!("👑 Ya existe un admin: bootstrap_admin se ignora"),
+            Err(e) => panic!("❌ Fallo al crear el admin inicial: {}", e),
+        }
+    }
+
+    // 4.2 Retención de auditoría (archivado periódico en segundo plano)
+    if let Some(days) = settings.audit_retention_days {
+        AuditArchiver::new(
+            state.users.clone(),
+            state.clock.clone(),
+            &settings.audit_archive_dir,
+            days,
+        )
+        .spawn(Duration::from_secs(settings.audit_archive_interval_secs));
+        tracing::info!(
+            "🗄️ Retención de auditoría activa: {} días -> {}",
+            days,
+            settings.audit_archive_dir
+        );
+    }
+
+    // 4.3 Borrado de las cuentas cuyo periodo de gracia terminó
+    state
+        .privacy_service()
+        .spawn(Duration::from_secs(settings.erasure_interval_secs));
+
+    // 4.4 Copias de seguridad periódicas (solo SQLite)
+    match (&backups, settings.backup_interval_secs) {
+        (Some(backups), Some(secs)) => {
+            backups.clone().spawn(Duration::from_secs(secs));
+            tracing::info!(
+                "💾 Copias de seguridad cada {} s -> {}",
+                secs,
+                settings.backup_dir
+            );
+        }
+        (None, Some(_)) => {
+            tracing::warn!(
+                "⚠️ backup_interval_secs se ignora: las copias solo funcionan con SQLite"
+            )
+        }
+        _ => {}
+    }
+
+    let app = create_app(state);
 
     // 5. Definir dirección y arrancar
     let addr = format!("{}:{}", settings.host, settings.port)
@@ -54,6 +148,9 @@ async fn main() {
     .with_graceful_shutdown(shutdown_signal())
     .await
     .unwrap();
+
+    // 6. Cerrar el pool: SQLite consolida el WAL y borra `-wal`/`-shm`
+    db.close().await;
 }
 
 /// Escucha señales de apagado (Ctrl+C o SIGTERM) para cerrar conexiones limpiamente
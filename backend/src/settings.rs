@@ -1,13 +1,210 @@
+use crate::core::models::user::CreateUserRequest;
+use crate::data::db::DbOptions;
+use crate::error::{AppError, ErrorFormat};
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
-use std::env;
+use std::{env, fmt, fs, time::Duration};
+use validator::Validate;
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub host: String,
     pub port: u16,
     pub database_url: String,
+    /// Aplica las migraciones pendientes al arrancar. Desactivado, el
+    /// esquema se gestiona con `backend-admin migrate`
+    #[serde(default = "default_auto_migrate")]
+    pub auto_migrate: bool,
+    /// Pool de conexiones (SQLite y PostgreSQL)
+    #[serde(default = "default_db_max_connections")]
+    pub db_max_connections: u32,
+    #[serde(default)]
+    pub db_min_connections: u32,
+    #[serde(default = "default_db_acquire_timeout_secs")]
+    pub db_acquire_timeout_secs: u64,
+    #[serde(default = "default_db_statement_cache_capacity")]
+    pub db_statement_cache_capacity: usize,
+    /// PRAGMAs de cada conexión SQLite (ver `DbOptions`)
+    #[serde(default = "default_sqlite_journal_mode")]
+    pub sqlite_journal_mode: String,
+    #[serde(default = "default_sqlite_synchronous")]
+    pub sqlite_synchronous: String,
+    #[serde(default = "default_sqlite_busy_timeout_ms")]
+    pub sqlite_busy_timeout_ms: u64,
+    #[serde(default = "default_sqlite_foreign_keys")]
+    pub sqlite_foreign_keys: bool,
     pub log_level: String,
+    /// Días que se conservan en la DB los registros de auditoría y los eventos
+    /// de seguridad (None = sin retención)
+    pub audit_retention_days: Option<i64>,
+    #[serde(default = "default_audit_archive_dir")]
+    pub audit_archive_dir: String,
+    #[serde(default = "default_audit_archive_interval_secs")]
+    pub audit_archive_interval_secs: u64,
+    /// Copias de seguridad de SQLite (`backup create` / POST /admin/backups)
+    #[serde(default = "default_backup_dir")]
+    pub backup_dir: String,
+    /// Cada cuánto se crea una copia automática (None = solo bajo demanda)
+    pub backup_interval_secs: Option<u64>,
+    /// Copias que se conservan (0 = todas)
+    #[serde(default = "default_backup_retention")]
+    pub backup_retention: usize,
+    #[serde(default = "default_backup_compress")]
+    pub backup_compress: bool,
+    /// Días entre `DELETE /api/v1/me` y el borrado efectivo (se puede cancelar)
+    #[serde(default = "default_erasure_grace_days")]
+    pub erasure_grace_days: i64,
+    /// Cada cuánto se ejecutan los borrados cuyo plazo venció
+    #[serde(default = "default_erasure_interval_secs")]
+    pub erasure_interval_secs: u64,
+    /// Exigir `If-Match` en PATCH/DELETE de usuarios (428 si falta). Sin
+    /// exigirlo, si viene se comprueba igual (412 si no coincide)
+    #[serde(default)]
+    pub require_if_match: bool,
+    /// Cuánto se guarda la respuesta de un POST con `Idempotency-Key`
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+    /// `problem` (RFC 7807) o `legacy` ({"error": "..."}) para clientes antiguos
+    #[serde(default)]
+    pub error_format: ErrorFormat,
+    /// Secreto HMAC de los tokens. Sobrescribir en producción (APP_JWT_SECRET)
+    #[serde(default = "default_jwt_secret")]
+    pub jwt_secret: String,
+    /// Primer admin a crear al arrancar si la base no tiene ninguno
+    #[serde(default)]
+    pub bootstrap_admin: Option<BootstrapAdmin>,
+}
+
+/// Credenciales del primer administrador (`[bootstrap_admin]` o
+/// `APP_BOOTSTRAP_ADMIN__USERNAME` / `__PASSWORD` / `__PASSWORD_FILE`).
+#[derive(Clone, Deserialize)]
+pub struct BootstrapAdmin {
+    pub username: String,
+    pub password: Option<String>,
+    /// Alternativa a `password`, p. ej. un secreto montado por Docker
+    pub password_file: Option<String>,
+}
+
+// Sin la contraseña: `Settings` se puede volcar en logs
+impl fmt::Debug for BootstrapAdmin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BootstrapAdmin")
+            .field("username", &self.username)
+            .field("password_file", &self.password_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BootstrapAdmin {
+    /// Resuelve la contraseña (`password` tiene prioridad sobre el archivo, del
+    /// que se descarta el salto de línea final) y la valida como un alta normal.
+    pub fn credentials(&self) -> Result<CreateUserRequest, AppError> {
+        let password = match (&self.password, &self.password_file) {
+            (Some(password), _) => password.clone(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| AppError::Internal(format!("No se pudo leer {}: {}", path, e)))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None) => {
+                return Err(AppError::Internal(
+                    "bootstrap_admin necesita password o password_file".to_string(),
+                ))
+            }
+        };
+        let request = CreateUserRequest {
+            username: self.username.clone(),
+            password,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+}
+
+fn default_auto_migrate() -> bool {
+    true
+}
+fn default_db_max_connections() -> u32 {
+    10
+}
+fn default_db_acquire_timeout_secs() -> u64 {
+    30
+}
+fn default_db_statement_cache_capacity() -> usize {
+    100
+}
+fn default_sqlite_journal_mode() -> String {
+    "wal".into()
+}
+fn default_sqlite_synchronous() -> String {
+    "normal".into()
+}
+fn default_sqlite_busy_timeout_ms() -> u64 {
+    5000
+}
+fn default_sqlite_foreign_keys() -> bool {
+    true
+}
+fn default_backup_dir() -> String {
+    "backups".into()
+}
+fn default_backup_retention() -> usize {
+    7
+}
+fn default_backup_compress() -> bool {
+    true
+}
+fn default_audit_archive_dir() -> String {
+    "archives/audit".into()
+}
+fn default_audit_archive_interval_secs() -> u64 {
+    3600
+}
+fn default_erasure_grace_days() -> i64 {
+    30
+}
+fn default_erasure_interval_secs() -> u64 {
+    3600
+}
+fn default_idempotency_ttl_secs() -> u64 {
+    86400
+}
+pub fn default_jwt_secret() -> String {
+    "secret".into()
+}
+
+impl Default for Settings {
+    /// Mismos valores que `config/default.toml` (útil en tests)
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".into(),
+            port: 3000,
+            database_url: "sqlite://backend.db".into(),
+            auto_migrate: default_auto_migrate(),
+            db_max_connections: default_db_max_connections(),
+            db_min_connections: 0,
+            db_acquire_timeout_secs: default_db_acquire_timeout_secs(),
+            db_statement_cache_capacity: default_db_statement_cache_capacity(),
+            sqlite_journal_mode: default_sqlite_journal_mode(),
+            sqlite_synchronous: default_sqlite_synchronous(),
+            sqlite_busy_timeout_ms: default_sqlite_busy_timeout_ms(),
+            sqlite_foreign_keys: default_sqlite_foreign_keys(),
+            log_level: "info".into(),
+            audit_retention_days: None,
+            audit_archive_dir: default_audit_archive_dir(),
+            audit_archive_interval_secs: default_audit_archive_interval_secs(),
+            backup_dir: default_backup_dir(),
+            backup_interval_secs: None,
+            backup_retention: default_backup_retention(),
+            backup_compress: default_backup_compress(),
+            erasure_grace_days: default_erasure_grace_days(),
+            erasure_interval_secs: default_erasure_interval_secs(),
+            require_if_match: false,
+            idempotency_ttl_secs: default_idempotency_ttl_secs(),
+            error_format: ErrorFormat::default(),
+            jwt_secret: default_jwt_secret(),
+            bootstrap_admin: None,
+        }
+    }
 }
 
 impl Settings {
@@ -20,9 +217,60 @@ impl Settings {
             // 2. Configuración por entorno (opcional, ej: config/production.toml)
             .add_source(File::with_name(&format!("config/{}", run_mode)).required(false))
             // 3. Variables de entorno (ej: APP_PORT=8080 sobrescribe port)
-            .add_source(Environment::with_prefix("APP"))
+            // (`__` separa secciones: APP_BOOTSTRAP_ADMIN__USERNAME)
+            .add_source(
+                Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator("__"),
+            )
             .build()?;
 
         s.try_deserialize()
     }
+
+    /// Opciones de conexión para `DbPool::connect_with`
+    pub fn db_options(&self) -> DbOptions {
+        DbOptions {
+            max_connections: self.db_max_connections,
+            min_connections: self.db_min_connections,
+            acquire_timeout: Duration::from_secs(self.db_acquire_timeout_secs),
+            statement_cache_capacity: self.db_statement_cache_capacity,
+            sqlite_journal_mode: self.sqlite_journal_mode.clone(),
+            sqlite_synchronous: self.sqlite_synchronous.clone(),
+            sqlite_busy_timeout: Duration::from_millis(self.sqlite_busy_timeout_ms),
+            sqlite_foreign_keys: self.sqlite_foreign_keys,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bootstrap(password: Option<&str>, password_file: Option<String>) -> BootstrapAdmin {
+        BootstrapAdmin {
+            username: "root".into(),
+            password: password.map(Into::into),
+            password_file,
+        }
+    }
+
+    #[test]
+    fn bootstrap_credentials_from_value_or_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), "desde-archivo\n").unwrap();
+        let path = Some(file.path().display().to_string());
+
+        let from_file = bootstrap(None, path.clone()).credentials().unwrap();
+        assert_eq!(from_file.password, "desde-archivo");
+        let explicit = bootstrap(Some("explicita1"), path).credentials().unwrap();
+        assert_eq!(explicit.password, "explicita1");
+
+        assert!(bootstrap(None, None).credentials().is_err());
+        assert!(matches!(
+            bootstrap(Some("corta"), None).credentials(),
+            Err(AppError::ValidationFields(_))
+        ));
+        assert!(!format!("{:?}", bootstrap(Some("explicita1"), None)).contains("explicita1"));
+    }
 }
@@ -0,0 +1,130 @@
+use crate::core::{
+    clock::{Clock, SystemClock},
+    cursor::CursorKey,
+    mailer::{LogMailer, Mailer},
+    repository::{DynUserRepository, UserRepository},
+    services::{auth::AuthService, bulk::BulkService, privacy::PrivacyService, user::UserService},
+};
+use crate::data::backup::BackupManager;
+use crate::settings::Settings;
+use std::sync::Arc;
+
+pub use crate::core::services::auth::JwtKeys;
+
+/// Estado compartido por handlers y middlewares. Todo lo que tiene
+/// implementaciones alternativas se inyecta como trait object.
+#[derive(Clone)]
+pub struct AppState {
+    pub users: DynUserRepository,
+    pub settings: Arc<Settings>,
+    pub keys: JwtKeys,
+    /// Firma los cursores de paginación; se deriva de `settings.jwt_secret`
+    pub cursors: CursorKey,
+    pub mailer: Arc<dyn Mailer>,
+    pub clock: Arc<dyn Clock>,
+    /// Solo con SQLite; sin él los endpoints de backups responden 409
+    pub backups: Option<Arc<BackupManager>>,
+}
+
+impl AppState {
+    /// Único obligatorio: el repositorio. El resto toma valores por defecto.
+    pub fn builder<R>(users: R) -> AppStateBuilder
+    where
+        R: UserRepository + Send + Sync + 'static,
+    {
+        Self::builder_shared(Arc::new(users))
+    }
+
+    /// Como `builder`, con un repositorio ya compartido (p. ej. elegido en
+    /// tiempo de ejecución según `database_url`).
+    pub fn builder_shared(users: DynUserRepository) -> AppStateBuilder {
+        AppStateBuilder {
+            users,
+            settings: None,
+            keys: None,
+            mailer: None,
+            clock: None,
+            backups: None,
+        }
+    }
+
+    /// Servicios de dominio sobre las dependencias inyectadas (solo clonan `Arc`s).
+    pub fn user_service(&self) -> UserService {
+        UserService::new(self.users.clone())
+    }
+
+    pub fn bulk_service(&self) -> BulkService {
+        BulkService::new(
+            self.users.clone(),
+            self.mailer.clone(),
+            self.auth_service(),
+            self.settings.require_if_match,
+        )
+    }
+
+    pub fn privacy_service(&self) -> PrivacyService {
+        PrivacyService::new(
+            self.users.clone(),
+            self.clock.clone(),
+            self.settings.erasure_grace_days,
+        )
+    }
+
+    pub fn auth_service(&self) -> AuthService {
+        AuthService::new(self.users.clone(), self.keys.clone(), self.clock.clone())
+    }
+}
+
+pub struct AppStateBuilder {
+    users: DynUserRepository,
+    settings: Option<Settings>,
+    keys: Option<JwtKeys>,
+    mailer: Option<Arc<dyn Mailer>>,
+    clock: Option<Arc<dyn Clock>>,
+    backups: Option<Arc<BackupManager>>,
+}
+
+impl AppStateBuilder {
+    pub fn settings(mut self, settings: Settings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Por defecto se derivan de `settings.jwt_secret`.
+    pub fn keys(mut self, keys: JwtKeys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    pub fn mailer(mut self, mailer: impl Mailer + 'static) -> Self {
+        self.mailer = Some(Arc::new(mailer));
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    pub fn backups(mut self, backups: Arc<BackupManager>) -> Self {
+        self.backups = Some(backups);
+        self
+    }
+
+    pub fn build(self) -> AppState {
+        let settings = self.settings.unwrap_or_default();
+        let keys = self
+            .keys
+            .unwrap_or_else(|| JwtKeys::from_secret(settings.jwt_secret.as_bytes()));
+
+        AppState {
+            users: self.users,
+            cursors: CursorKey::from_secret(settings.jwt_secret.as_bytes()),
+            settings: Arc::new(settings),
+            keys,
+            mailer: self.mailer.unwrap_or_else(|| Arc::new(LogMailer)),
+            clock: self.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            backups: self.backups,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use axum::{body::Body, http::StatusCode, Router};
+use backend::{
+    core::{clock::SystemClock, models::user::Role, repository::DynUserRepository},
+    create_app,
+    data::{
+        backup::{self, BackupManager},
+        db::DbPool,
+        memory_repository::InMemoryRepository,
+    },
+    error::AppError,
+    state::AppState,
+};
+use common::{cookie, create_admin, request};
+use serde_json::Value;
+use std::sync::Arc;
+
+async fn call(app: &Router, method: &str, username: &str, role: Role) -> (StatusCode, Value) {
+    let req = request(method, "/api/v1/admin/backups")
+        .header("cookie", cookie(username, role))
+        .body(Body::empty())
+        .unwrap();
+    common::call(app, req).await
+}
+
+#[tokio::test]
+async fn test_backup_endpoints_and_restore() {
+    // 1. Setup: SQLite en archivo (restaurar sustituye el archivo)
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("app.db").display());
+    let backups_dir = dir.path().join("backups");
+    let db = DbPool::connect(&url).await.unwrap();
+    db.migrate().await.unwrap();
+    let users = db.repository();
+    create_admin(&users, "root").await;
+    users.create_user("ana", "hash").await.unwrap();
+
+    let manager = BackupManager::for_db(&db, Arc::new(SystemClock), &backups_dir, 2, true)
+        .expect("SQLite admite copias");
+    let app = create_app(
+        AppState::builder_shared(users.clone())
+            .backups(Arc::new(manager))
+            .build(),
+    );
+
+    // 2. Solo admins
+    let (status, _) = call(&app, "POST", "ana", Role::User).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // 3. Tres copias con retención 2: se conservan las dos últimas
+    let mut created = Vec::new();
+    for _ in 0..3 {
+        let (status, body) = call(&app, "POST", "root", Role::Admin).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["compressed"], true);
+        created.push(body["file_name"].as_str().unwrap().to_string());
+    }
+    let (status, body) = call(&app, "GET", "root", Role::Admin).await;
+    assert_eq!(status, StatusCode::OK);
+    let listed: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["file_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(listed, [created[2].as_str(), created[1].as_str()]);
+    assert!(!backups_dir.join(&created[0]).exists());
+
+    // 4. Cada copia queda auditada
+    let logs = users.get_audit_logs().await.unwrap();
+    assert_eq!(logs.len(), 3);
+    assert!(logs.iter().all(|l| l.action == "CREATE_BACKUP"));
+
+    // 5. Restaurar devuelve la base al estado de la copia
+    users.create_user("bea", "hash").await.unwrap();
+    db.close().await;
+    let report = backup::restore(&url, &backups_dir, &created[2], &SystemClock)
+        .await
+        .unwrap();
+    assert_eq!(report.pending_migrations, 0);
+    assert!(report.previous.is_some());
+
+    let db = DbPool::connect(&url).await.unwrap();
+    let users = db.repository();
+    assert!(users.get_by_username("ana").await.unwrap().is_some());
+    assert!(users.get_by_username("bea").await.unwrap().is_none());
+    db.close().await;
+
+    // 6. Un checksum que no cuadra impide restaurar
+    let file = backups_dir.join(&created[1]);
+    let mut bytes = std::fs::read(&file).unwrap();
+    bytes.push(0);
+    std::fs::write(&file, bytes).unwrap();
+    let err = backup::restore(&url, &backups_dir, &created[1], &SystemClock)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AppError::Conflict(key) if key == "backup.checksum_mismatch"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_restore_rejects_newer_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("app.db").display());
+    let backups_dir = dir.path().join("backups");
+    let db = DbPool::connect(&url).await.unwrap();
+    db.migrate().await.unwrap();
+
+    // Copia de una base migrada por un binario más nuevo
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    sqlx::query("INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (9999, 'futura', TRUE, x'00', 0)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    let manager = BackupManager::for_db(&db, Arc::new(SystemClock), &backups_dir, 0, false)
+        .expect("SQLite admite copias");
+    let info = manager.create().await.unwrap();
+    assert_eq!(info.schema_version, Some(9999));
+    db.close().await;
+
+    let before = std::fs::read(dir.path().join("app.db")).unwrap();
+    let err = backup::restore(&url, &backups_dir, &info.file_name, &SystemClock)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AppError::Conflict(key) if key == "backup.incompatible_schema"),
+        "{}",
+        err
+    );
+    // La base actual no se tocó
+    assert_eq!(std::fs::read(dir.path().join("app.db")).unwrap(), before);
+}
+
+#[tokio::test]
+async fn test_backups_unavailable_without_sqlite() {
+    let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+    create_admin(&users, "root").await;
+    let app = create_app(AppState::builder_shared(users).build());
+    let (status, body) = call(&app, "GET", "root", Role::Admin).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "conflict");
+}
@@ -0,0 +1,494 @@
+mod common;
+
+use async_trait::async_trait;
+use axum::{body::Body, http::StatusCode, Router};
+use backend::{
+    core::{
+        mailer::{Email, Mailer},
+        models::user::Role,
+        repository::DynUserRepository,
+    },
+    create_app,
+    data::user_repository::SqliteRepository,
+    error::AppError,
+    settings::Settings,
+    state::AppState,
+};
+use common::{call_text, cookie, create_admin, memory_pool};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Guarda los correos en vez de enviarlos.
+#[derive(Clone, Default)]
+struct Outbox(Arc<Mutex<Vec<Email>>>);
+
+#[async_trait]
+impl Mailer for Outbox {
+    async fn send(&self, email: Email) -> Result<(), AppError> {
+        self.0.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+fn admin_cookie() -> String {
+    cookie("root", Role::Admin)
+}
+
+async fn import(app: &Router, query: &str, content_type: &str, body: &str) -> (StatusCode, Value) {
+    let req = common::request("POST", &format!("/api/v1/admin/users/import?{}", query))
+        .header("cookie", admin_cookie())
+        .header("content-type", content_type)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, body) = call_text(app, req).await;
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+/// App con el admin `root` (id 1) y el usuario `pepe` (id 2).
+async fn setup() -> (Router, Outbox, sqlx::SqlitePool) {
+    setup_with(Settings::default()).await
+}
+
+async fn setup_with(settings: Settings) -> (Router, Outbox, sqlx::SqlitePool) {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    create_admin(&users, "root").await;
+    users.create_user("pepe", "hash").await.unwrap();
+    let outbox = Outbox::default();
+    let state = AppState::builder_shared(users)
+        .settings(settings)
+        .mailer(outbox.clone())
+        .build();
+    (create_app(state), outbox, pool)
+}
+
+async fn audit_actions(pool: &sqlx::SqlitePool) -> Vec<(String, String)> {
+    sqlx::query_as("SELECT action, target FROM audit_logs ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_csv_import_dry_run_invitations_and_upsert() {
+    let (app, outbox, pool) = setup().await;
+    let csv = "username,password,role,display_name,email\n\
+               ana,password123,admin,Ana Pérez,\n\
+               bea,,,,bea@example.com\n";
+
+    // 1. Dry-run: informe completo, nada escrito
+    let bad = format!("{}yo,corta,,,\nana,password123,,,\n", csv);
+    let (status, report) = import(&app, "dry_run=true&invite=true", "text/csv", &bad).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["rows"], 4);
+    assert_eq!(report["created"], 2);
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["row"], 3);
+    assert!(errors[0]["errors"]["username"].is_array());
+    assert!(errors[0]["errors"]["password"].is_array());
+    assert_eq!(errors[1]["errors"]["username"][0]["code"], "duplicate");
+    assert!(report["batch_id"].is_null());
+
+    // 2. Sin dry-run y con errores: 422 y tampoco se escribe
+    let (status, _) = import(&app, "invite=true", "text/csv", &bad).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(audit_actions(&pool).await.is_empty());
+
+    // 3. Sin invitaciones, una fila sin contraseña no es válida
+    let (status, report) = import(&app, "", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        report["errors"][0]["errors"]["password"][0]["code"],
+        "required"
+    );
+
+    // 4. Alta real: rol y perfil aplicados, invitación con código para elegir contraseña
+    let (status, report) = import(&app, "invite=true", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", report);
+    assert_eq!(
+        (report["created"].clone(), report["invited"].clone()),
+        (json!(2), json!(1))
+    );
+    let batch = report["batch_id"].as_str().unwrap().to_string();
+    let (role, display_name): (String, String) =
+        sqlx::query_as("SELECT role, display_name FROM users WHERE username = 'ana'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        (role.as_str(), display_name.as_str()),
+        ("admin", "Ana Pérez")
+    );
+
+    let emails = outbox.0.lock().unwrap().clone();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].to, "bea@example.com");
+    let token = emails[0].body.rsplit(' ').next().unwrap().to_string();
+    let post = |uri: &str, body: Value| {
+        common::request("POST", uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let choose = json!({ "token": token, "password": "elegida-123" });
+    let (status, body) = call_text(&app, post("/api/v1/password", choose.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let login = json!({ "username": "bea", "password": "elegida-123" });
+    assert_eq!(
+        call_text(&app, post("/api/v1/login", login)).await.0,
+        StatusCode::OK
+    );
+    // De un solo uso
+    let (status, body) = call_text(&app, post("/api/v1/password", choose)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body.contains("ya se usó"), "{}", body);
+
+    // 5. Repetir en modo create choca; en upsert (NDJSON) actualiza
+    let (status, _) = import(&app, "invite=true", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let ndjson = "{\"username\":\"bea\",\"display_name\":\"Bea\"}\n\n{\"username\":\"cris\",\"password\":\"password123\"}\n";
+    let (status, report) = import(&app, "mode=upsert", "application/x-ndjson", ndjson).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", report);
+    assert_eq!(
+        (report["created"].clone(), report["updated"].clone()),
+        (json!(1), json!(1))
+    );
+    let email: Option<String> =
+        sqlx::query_scalar("SELECT email FROM users WHERE username = 'bea'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(email.as_deref(), Some("bea@example.com"));
+
+    // 6. Una entrada de auditoría por lote (y la contraseña elegida)
+    let audit = audit_actions(&pool).await;
+    assert_eq!(audit.len(), 3);
+    assert_eq!(audit[0].0, "IMPORT_USERS");
+    assert_eq!(audit[0].1, format!("batch:{} created=2 updated=0", batch));
+    assert_eq!(audit[1], ("RESET_PASSWORD".to_string(), "bea".to_string()));
+
+    // 7. Formato desconocido
+    let (status, _) = import(&app, "", "application/json", "[]").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_upsert_writes_each_row_once_and_audits_role_changes() {
+    let (app, _, pool) = setup().await;
+    let ndjson = "{\"username\":\"pepe\",\"password\":\"password123\",\"role\":\"admin\",\"display_name\":\"Pepe\",\"email\":\"pepe@example.com\"}\n";
+    let (status, report) = import(&app, "mode=upsert", "application/x-ndjson", ndjson).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", report);
+
+    // Contraseña, rol y perfil: una sola versión más
+    let (role, version): (String, i64) =
+        sqlx::query_as("SELECT role, version FROM users WHERE username = 'pepe'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((role.as_str(), version), ("admin", 2));
+
+    // El ascenso tiene su propia entrada, enlazada con el lote
+    let batch = report["batch_id"].as_str().unwrap();
+    let audit = audit_actions(&pool).await;
+    assert_eq!(
+        audit,
+        [
+            (
+                "CHANGE_ROLE".to_string(),
+                format!("pepe:admin:batch:{}", batch)
+            ),
+            (
+                "IMPORT_USERS".to_string(),
+                format!("batch:{} created=0 updated=1", batch)
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_invitations_need_a_mailer_that_delivers() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    create_admin(&users, "root").await;
+    // Sin `.mailer(...)`: `LogMailer`, que solo deja el correo en los logs
+    let app = create_app(AppState::builder_shared(users).build());
+    let csv = "username,password,role,display_name,email
+bea,,,,bea@example.com
+";
+
+    let (status, problem) = import(&app, "dry_run=true&invite=true", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", problem);
+    assert!(problem["detail"].as_str().unwrap().contains("invite=true"));
+    let (status, _) = import(&app, "invite=true", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(audit_actions(&pool).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_empty_csv_export_keeps_the_header() {
+    let (app, _, _) = setup().await;
+    let (status, body) = call_text(
+        &app,
+        common::request("GET", "/api/v1/admin/users/export?status=suspended")
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        "id,username,role,status,display_name,email,created_at,last_login_at\n"
+    );
+}
+
+#[tokio::test]
+async fn test_streamed_export_requires_admin_and_is_audited() {
+    let (app, _, pool) = setup().await;
+    // 1200 usuarios directamente en SQL: hashear sus contraseñas no aporta nada aquí
+    sqlx::query(
+        "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 1199)
+         INSERT INTO users (username, password_hash) SELECT printf('user%04d', i), 'x' FROM n",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // 1. CSV: cabecera y una línea por usuario, a través de varios bloques
+    let (status, body) = call_text(
+        &app,
+        common::request("GET", "/api/v1/admin/users/export")
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), 1203);
+    assert!(lines[0].starts_with("id,username,role,status"));
+    assert!(lines[3].contains(",user0000,user,active,"));
+    assert!(!body.contains("argon2"));
+
+    // 2. NDJSON filtrado
+    let (status, body) = call_text(
+        &app,
+        common::request("GET", "/api/v1/admin/users/export?format=ndjson&role=admin")
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.lines().count(), 1);
+    assert!(body.contains("\"username\":\"root\""), "{}", body);
+
+    let audit = audit_actions(&pool).await;
+    let exports: Vec<_> = audit
+        .iter()
+        .filter(|(action, _)| action == "EXPORT_USERS")
+        .collect();
+    assert_eq!(exports.len(), 2);
+    assert!(exports[1].1.ends_with("format=ndjson"));
+
+    // 3. Solo administradores
+    let req = common::request("GET", "/api/v1/admin/users/export")
+        .header("cookie", cookie("pepe", Role::User))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(call_text(&app, req).await.0, StatusCode::FORBIDDEN);
+}
+
+async fn batch(app: &Router, session: String, body: Value) -> (StatusCode, Value) {
+    let req = common::request("POST", "/api/v1/users:batch")
+        .header("cookie", session)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, body) = call_text(app, req).await;
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_batch_operations_with_per_item_results_and_atomic_rollback() {
+    let (app, _, pool) = setup().await;
+    sqlx::query("INSERT INTO users (username, password_hash) VALUES ('ana', 'x'), ('bea', 'x'), ('carla', 'x'), ('dani', 'x')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // 1. Por separado: el id inexistente falla sin detener las demás
+    let (status, report) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "operations": [
+            { "op": "suspend", "id": 3 },
+            { "op": "delete", "id": 99 },
+            { "op": "change_role", "id": 4, "role": "Admin" },
+            { "op": "delete", "id": 5 },
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["committed"], true);
+    assert_eq!(report["succeeded"], 3);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["results"][1]["status"], 404);
+    assert_eq!(report["results"][1]["error"]["code"], "not_found");
+    assert!(report["results"][0].get("error").is_none());
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT username, status || ':' || role FROM users WHERE id > 2 ORDER BY id",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    let rows: Vec<(&str, &str)> = rows.iter().map(|(u, s)| (u.as_str(), s.as_str())).collect();
+    assert_eq!(
+        rows,
+        [
+            ("ana", "suspended:user"),
+            ("bea", "active:admin"),
+            ("dani", "active:user")
+        ]
+    );
+
+    // Una entrada de auditoría por operación aplicada, enlazadas por el lote
+    let batch_id = report["batch_id"].as_str().unwrap();
+    let audit = audit_actions(&pool).await;
+    assert_eq!(
+        audit,
+        [
+            (
+                "SUSPEND_USER".to_string(),
+                format!("ana:batch:{}", batch_id)
+            ),
+            (
+                "CHANGE_ROLE".to_string(),
+                format!("bea:admin:batch:{}", batch_id)
+            ),
+            (
+                "DELETE_USER".to_string(),
+                format!("carla:batch:{}", batch_id)
+            ),
+        ]
+    );
+
+    // 2. Atómico: el fallo deshace todo (auditoría incluida) y corta el lote
+    let (status, report) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "atomic": true, "operations": [
+            { "op": "delete", "id": 6 },
+            { "op": "suspend", "id": 5 },
+            { "op": "suspend", "id": 4 },
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", report);
+    assert_eq!(report["committed"], false);
+    assert_eq!(report["results"].as_array().unwrap().len(), 2);
+    assert_eq!(report["succeeded"], 0);
+    assert_eq!(report["rolled_back"], 1);
+    assert_eq!(report["results"][0]["status"], 424);
+    assert!(report["results"][0].get("error").is_none());
+    assert_eq!(report["results"][1]["status"], 404);
+    let dani: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE username = 'dani'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(dani, 1);
+    assert_eq!(audit_actions(&pool).await.len(), 3);
+
+    let (status, report) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "atomic": true, "operations": [
+            { "op": "delete", "id": 6 },
+            { "op": "suspend", "id": 4 },
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["succeeded"], 2);
+    assert_eq!(audit_actions(&pool).await.len(), 5);
+
+    // 3. Límites: vacío, más de 100 u operación desconocida; solo admins
+    let (status, problem) = batch(&app, admin_cookie(), json!({ "operations": [] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(problem["errors"]["operations"].is_array());
+    let many: Vec<Value> = (0..101)
+        .map(|id| json!({ "op": "delete", "id": id }))
+        .collect();
+    let (status, _) = batch(&app, admin_cookie(), json!({ "operations": many })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "operations": [{ "op": "explode", "id": 1 }] }),
+    )
+    .await;
+    assert!(status.is_client_error());
+    let (status, _) = batch(
+        &app,
+        cookie("pepe", Role::User),
+        json!({ "operations": [{ "op": "delete", "id": 2 }] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_batch_checks_versions_like_single_user_routes() {
+    let (app, _, pool) = setup_with(Settings {
+        require_if_match: true,
+        ..Settings::default()
+    })
+    .await;
+    sqlx::query("INSERT INTO users (username, password_hash) VALUES ('ana', 'x'), ('bea', 'x')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // Sin versión: 428, como un DELETE sin If-Match
+    let (status, report) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "operations": [
+            { "op": "delete", "id": 3 },
+            { "op": "change_role", "id": 4, "role": "Admin" },
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["failed"], 2);
+    assert_eq!(report["results"][0]["status"], 428);
+    assert_eq!(report["results"][1]["status"], 428);
+
+    // Versión vieja: 412; la actual se aplica
+    let (status, report) = batch(
+        &app,
+        admin_cookie(),
+        json!({ "operations": [
+            { "op": "delete", "id": 3, "version": 7 },
+            { "op": "change_role", "id": 4, "role": "Admin", "version": 1 },
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["results"][0]["status"], 412);
+    assert_eq!(report["results"][1]["status"], 200);
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT username, role FROM users WHERE id > 2 ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        rows,
+        [
+            ("ana".to_string(), "user".to_string()),
+            ("bea".to_string(), "admin".to_string())
+        ]
+    );
+    assert_eq!(audit_actions(&pool).await.len(), 1);
+}
@@ -0,0 +1,115 @@
+//! Utilidades compartidas por los tests de integración. Cada archivo de
+//! `tests/` es su propio crate y usa solo una parte.
+#![allow(dead_code)]
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{request::Builder, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use backend::core::{
+    models::user::{Claims, Role, User},
+    repository::DynUserRepository,
+};
+use http_body_util::BodyExt;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tower::ServiceExt;
+
+/// SQLite en memoria con todas las migraciones. Una sola conexión: cada
+/// conexión a `sqlite::memory:` sería otra base.
+pub async fn memory_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+/// Alta directa en el repositorio con rol admin; los guards comprueban que el
+/// usuario de la sesión exista.
+pub async fn create_admin(users: &DynUserRepository, username: &str) -> User {
+    let user = users.create_user(username, "hash").await.unwrap();
+    let mut uow = users.begin().await.unwrap();
+    uow.set_role(user.id, Role::Admin).await.unwrap();
+    uow.commit().await.unwrap();
+    users.get_by_id(user.id).await.unwrap().unwrap()
+}
+
+/// Petición desde 127.0.0.1 (el rate limiter necesita la IP del cliente).
+pub fn request(method: &str, uri: &str) -> Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .extension(ConnectInfo(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            8080,
+        )))
+}
+
+/// Cookie de sesión firmada con el secreto por defecto, sin pasar por el login.
+pub fn cookie(username: &str, role: Role) -> String {
+    let token = encode(
+        &Header::default(),
+        &Claims {
+            sub: username.to_string(),
+            role,
+            exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+            locale: None,
+            jti: None,
+            uid: None,
+        },
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+    format!("auth_token={}", token)
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone().oneshot(request).await.unwrap()
+}
+
+pub async fn text(response: Response) -> String {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// Cuerpo JSON; `Null` si está vacío o no es JSON.
+pub async fn json(response: Response) -> Value {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap_or(Value::Null)
+}
+
+pub async fn call(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = send(app, request).await;
+    (response.status(), json(response).await)
+}
+
+/// Como `call`, con el cuerpo como texto (CSV, NDJSON...).
+pub async fn call_text(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = send(app, request).await;
+    (response.status(), text(response).await)
+}
+
+/// Alta y login: devuelve la cookie de sesión.
+pub async fn sign_up(app: &Router, username: &str) -> String {
+    let credentials = json!({ "username": username, "password": "password123" }).to_string();
+    let req = request("POST", "/api/v1/users")
+        .header("content-type", "application/json")
+        .body(Body::from(credentials.clone()))
+        .unwrap();
+    assert_eq!(call(app, req).await.0, StatusCode::CREATED);
+    let req = request("POST", "/api/v1/login")
+        .header("content-type", "application/json")
+        .body(Body::from(credentials))
+        .unwrap();
+    let response = send(app, req).await;
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_string()
+}
@@ -0,0 +1,293 @@
+mod common;
+
+use axum::{body::Body, http::Request, http::StatusCode, response::Response, Router};
+use backend::{
+    api::metrics,
+    core::{models::user::Role, repository::DynUserRepository},
+    create_app,
+    data::memory_repository::InMemoryRepository,
+    error::AuthFailure,
+    i18n::Locale,
+    settings::Settings,
+    state::AppState,
+};
+use common::{cookie, create_admin, json, send};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+fn admin_cookie() -> String {
+    cookie("root", Role::Admin)
+}
+
+fn patch(uri: &str, if_match: Option<&str>, body: Value) -> Request<Body> {
+    let mut builder = common::request("PATCH", uri)
+        .header("cookie", admin_cookie())
+        .header("content-type", "application/json");
+    if let Some(tag) = if_match {
+        builder = builder.header("if-match", tag);
+    }
+    builder.body(Body::from(body.to_string())).unwrap()
+}
+
+fn etag(response: &Response) -> String {
+    response.headers()["etag"].to_str().unwrap().to_string()
+}
+
+async fn setup(settings: Settings) -> (Router, DynUserRepository, i64) {
+    let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+    create_admin(&users, "root").await;
+    let ana = users.create_user("ana", "hash").await.unwrap();
+    let app = create_app(
+        AppState::builder_shared(users.clone())
+            .settings(settings)
+            .build(),
+    );
+    (app, users, ana.id)
+}
+
+#[tokio::test]
+async fn test_etag_and_conditional_requests() {
+    let (app, users, id) = setup(Settings::default()).await;
+    let uri = format!("/api/v1/users/{}", id);
+
+    // 1. GET con ETag; If-None-Match con la versión actual da 304 sin cuerpo
+    let response = send(
+        &app,
+        common::request("GET", &uri)
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let tag = etag(&response);
+    assert!(tag.starts_with("\"1-"), "{}", tag);
+    assert_eq!(json(response).await["version"], 1);
+    let response = send(
+        &app,
+        common::request("GET", &uri)
+            .header("cookie", admin_cookie())
+            .header("if-none-match", format!("W/{}", tag))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(etag(&response), tag);
+    assert!(response
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .is_empty());
+
+    // 2. PATCH con el ETag leído: aplica los cambios y devuelve la nueva versión
+    let response = send(
+        &app,
+        patch(
+            &uri,
+            Some(&tag),
+            json!({ "role": "admin", "display_name": "Ana" }),
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_tag = etag(&response);
+    assert!(new_tag.starts_with("\"2-"), "{}", new_tag);
+    let user = json(response).await;
+    assert_eq!(user["role"], "Admin");
+    assert_eq!(user["display_name"], "Ana");
+    let logs = users.get_audit_logs().await.unwrap();
+    assert_eq!(logs[0].action, "UPDATE_USER");
+    assert_eq!(logs[0].target, "ana:role,display_name");
+
+    // 3. Con el ETag viejo: 412 y nada cambia; el GET condicional ya no es 304
+    //    (el ETag del GET es de otra representación, con la misma versión)
+    let response = send(&app, patch(&uri, Some(&tag), json!({ "email": "" }))).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(json(response).await["code"], "precondition_failed");
+    let response = send(
+        &app,
+        common::request("GET", &uri)
+            .header("cookie", admin_cookie())
+            .header("if-none-match", &tag)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(etag(&response).starts_with("\"2-"));
+
+    // 4. Sin exigir If-Match, una petición sin él sigue funcionando
+    let response = send(
+        &app,
+        patch(&uri, None, json!({ "email": "ana@example.com" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(etag(&response).starts_with("\"3-"));
+
+    // 5. Validación: nada que cambiar
+    let response = send(&app, patch(&uri, Some("*"), json!({}))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // 6. DELETE con versión vieja falla; con la actual (basta el número) borra
+    let delete = |tag: &str| {
+        common::request("DELETE", &uri)
+            .header("cookie", admin_cookie())
+            .header("if-match", tag)
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(
+        send(&app, delete(&new_tag)).await.status(),
+        StatusCode::PRECONDITION_FAILED
+    );
+    assert_eq!(send(&app, delete("\"3\"")).await.status(), StatusCode::OK);
+    assert!(users.get_by_id(id).await.unwrap().is_none());
+    // Ya no existe: If-Match no puede cumplirse
+    assert_eq!(
+        send(&app, delete("*")).await.status(),
+        StatusCode::PRECONDITION_FAILED
+    );
+    let response = send(
+        &app,
+        common::request("GET", &uri)
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_if_match_required_when_enabled() {
+    let settings = Settings {
+        require_if_match: true,
+        ..Settings::default()
+    };
+    let (app, users, id) = setup(settings).await;
+    let uri = format!("/api/v1/users/{}", id);
+
+    let response = send(&app, patch(&uri, None, json!({ "status": "suspended" }))).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(json(response).await["code"], "precondition_required");
+    let response = send(
+        &app,
+        common::request("DELETE", &uri)
+            .header("cookie", admin_cookie())
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    assert!(users.get_by_id(id).await.unwrap().is_some());
+    assert!(users.get_audit_logs().await.unwrap().is_empty());
+
+    let response = send(
+        &app,
+        patch(&uri, Some("\"1\""), json!({ "status": "suspended" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["status"], "Suspended");
+}
+
+#[tokio::test]
+async fn test_get_user_requires_admin_or_self() {
+    let (app, users, id) = setup(Settings::default()).await;
+    users.create_user("bob", "hash").await.unwrap();
+    users
+        .set_profile("ana", None, Some("ana@example.com"))
+        .await
+        .unwrap();
+    let uri = format!("/api/v1/users/{}", id);
+    let get = |uri: &str, cookie: Option<String>| {
+        let mut builder = common::request("GET", uri);
+        if let Some(cookie) = cookie {
+            builder = builder.header("cookie", cookie);
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    // 1. Sin sesión: 401
+    let response = send(&app, get(&uri, None)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(json(response).await["code"], "token_missing");
+
+    // 2. Otro usuario normal: 403, exista o no el id, contado como los del guard
+    let denied = metrics::auth_failures(AuthFailure::InsufficientRole);
+    let bob = || Some(cookie("bob", Role::User));
+    assert_eq!(
+        send(&app, get(&uri, bob())).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(&app, get("/api/v1/users/9999", bob())).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    assert!(metrics::auth_failures(AuthFailure::InsufficientRole) >= denied + 2);
+
+    // 3. El propio usuario se ve, pero sin los campos de admin
+    let response = send(&app, get(&uri, Some(cookie("ana", Role::User)))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let user = json(response).await;
+    assert_eq!(user["username"], "ana");
+    assert!(user.get("email").is_none());
+
+    // 4. Un admin ve a cualquiera, con email
+    let response = send(&app, get(&uri, Some(admin_cookie()))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["email"], "ana@example.com");
+}
+
+#[tokio::test]
+async fn test_etag_follows_the_representation() {
+    let (app, users, id) = setup(Settings::default()).await;
+    let get = |uri: String, cookie: String, if_none_match: Option<String>| {
+        let mut builder = common::request("GET", &uri).header("cookie", cookie);
+        if let Some(tag) = if_none_match {
+            builder = builder.header("if-none-match", tag);
+        }
+        send(&app, builder.body(Body::empty()).unwrap())
+    };
+    let uri = format!("/api/v1/users/{}", id);
+
+    // 1. La vista de admin y la del propio usuario tienen ETags distintos
+    let response = get(uri.clone(), admin_cookie(), None).await;
+    assert_eq!(response.headers()["vary"], "cookie, authorization");
+    let admin_tag = etag(&response);
+    let response = get(
+        uri.clone(),
+        cookie("ana", Role::User),
+        Some(admin_tag.clone()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let own_tag = etag(&response);
+    assert_ne!(own_tag, admin_tag);
+
+    // 2. Igual con otros `fields`
+    let response = get(
+        format!("{}?fields=id,username", uri),
+        admin_cookie(),
+        Some(admin_tag.clone()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await, json!({ "id": id, "username": "ana" }));
+
+    // 3. El idioma no sube la versión, pero sí cambia el cuerpo: nada de 304
+    users.set_locale("ana", Some(Locale::En)).await.unwrap();
+    let response = get(uri.clone(), admin_cookie(), Some(admin_tag.clone())).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_tag = etag(&response);
+    assert_ne!(new_tag, admin_tag);
+    assert!(new_tag.starts_with("\"1-"), "{}", new_tag);
+    assert_eq!(json(response).await["locale"], "en");
+    let response = get(uri, admin_cookie(), Some(new_tag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
@@ -0,0 +1,55 @@
+use backend::data::db::{DbOptions, DbPool};
+use std::time::Duration;
+
+async fn pragma(pool: &sqlx::SqlitePool, name: &str) -> i64 {
+    sqlx::query_scalar(&format!("PRAGMA {}", name))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_sqlite_options_and_clean_close() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("opciones.db");
+    let url = format!("sqlite://{}", path.display());
+
+    // 1. Los PRAGMAs de DbOptions se aplican a cada conexión
+    let options = DbOptions {
+        sqlite_busy_timeout: Duration::from_millis(1234),
+        ..DbOptions::default()
+    };
+    let db = DbPool::connect_with(&url, &options).await.unwrap();
+    db.migrate().await.unwrap();
+    // Sin la feature `postgres` el patrón es irrefutable
+    #[allow(clippy::infallible_destructuring_match)]
+    let pool = match &db {
+        DbPool::Sqlite(pool) => pool,
+        #[cfg(feature = "postgres")]
+        _ => unreachable!(),
+    };
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(journal_mode, "wal");
+    assert_eq!(pragma(pool, "synchronous").await, 1); // NORMAL
+    assert_eq!(pragma(pool, "foreign_keys").await, 1);
+    assert_eq!(pragma(pool, "busy_timeout").await, 1234);
+
+    // 2. Al cerrar, el WAL queda consolidado (vacío o borrado)
+    db.repository().create_user("ana", "hash").await.unwrap();
+    db.close().await;
+    let wal = dir.path().join("opciones.db-wal");
+    assert!(std::fs::metadata(&wal)
+        .map(|m| m.len() == 0)
+        .unwrap_or(true));
+
+    // 3. Un valor desconocido se rechaza al conectar
+    let bad = DbOptions {
+        sqlite_journal_mode: "turbo".into(),
+        ..DbOptions::default()
+    };
+    let err = DbPool::connect_with(&url, &bad).await.err().unwrap();
+    assert!(err.to_string().contains("sqlite_journal_mode"), "{}", err);
+}
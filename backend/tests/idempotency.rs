@@ -0,0 +1,269 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use backend::{
+    api::middleware::idempotency, core::repository::DynUserRepository, create_app,
+    data::user_repository::SqliteRepository, state::AppState,
+};
+use common::{json, memory_pool, request, send};
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tower_cookies::CookieManagerLayer;
+
+fn sign_up(key: Option<&str>, username: &str) -> Request<Body> {
+    let mut builder = request("POST", "/api/v1/users").header("content-type", "application/json");
+    if let Some(key) = key {
+        builder = builder.header("idempotency-key", key);
+    }
+    let body = json!({ "username": username, "password": "password123" });
+    builder.body(Body::from(body.to_string())).unwrap()
+}
+
+#[tokio::test]
+async fn test_idempotency_key_replays_post_responses() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+
+    // 1. El primer alta se ejecuta
+    let response = send(&app, sign_up(Some("alta-ana-1"), "ana")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    let created = json(response).await;
+
+    // 2. El reintento recibe la misma respuesta sin crear nada ni dar 409
+    let response = send(&app, sign_up(Some("alta-ana-1"), "ana")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/json"));
+    assert_eq!(json(response).await, created);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    // 3. La misma clave con otro cuerpo es 422
+    let response = send(&app, sign_up(Some("alta-ana-1"), "beto")).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json(response).await["code"], "unprocessable");
+    assert!(users.get_by_username("beto").await.unwrap().is_none());
+
+    // 4. Sin clave (o con otra) se comporta como siempre: el duplicado es 409
+    assert_eq!(
+        send(&app, sign_up(None, "ana")).await.status(),
+        StatusCode::CONFLICT
+    );
+    let response = send(&app, sign_up(Some("alta-ana-2"), "ana")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    // Los errores 4xx también se guardan: el reintento repite el 409
+    let response = send(&app, sign_up(Some("alta-ana-2"), "ana")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+
+    // 5. Una clave vacía se rechaza
+    assert_eq!(
+        send(&app, sign_up(Some(" "), "carla")).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // 6. Mientras la primera petición sigue en curso, los reintentos son 409
+    sqlx::query("UPDATE idempotency_keys SET status = NULL, body = NULL WHERE key = 'alta-ana-1'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = send(&app, sign_up(Some("alta-ana-1"), "ana")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+}
+
+#[tokio::test]
+async fn test_login_with_idempotency_key_is_not_stored() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    let app = create_app(AppState::builder_shared(users).build());
+    assert_eq!(
+        send(&app, sign_up(None, "ana")).await.status(),
+        StatusCode::CREATED
+    );
+
+    // Las respuestas que abren sesión llevan su cookie: se ejecutan siempre
+    for _ in 0..2 {
+        let request = request("POST", "/api/v1/login")
+            .header("content-type", "application/json")
+            .header("idempotency-key", "login-1")
+            .body(Body::from(
+                json!({ "username": "ana", "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        let response = send(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("set-cookie").is_some());
+        assert!(response.headers().get("idempotent-replayed").is_none());
+    }
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM idempotency_keys")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+}
+
+/// Ruta de prueba tras el middleware: cada ejecución crea un "recurso" nuevo
+/// y lo anuncia en `Location` y `ETag`.
+async fn resource_app() -> (Router, SqlitePool, Arc<AtomicUsize>) {
+    let pool = memory_pool().await;
+    let state = AppState::builder(SqliteRepository::new(pool.clone())).build();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    let app = Router::new()
+        .route(
+            "/recursos",
+            post(move || async move {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                (
+                    StatusCode::CREATED,
+                    [
+                        (header::LOCATION, format!("/recursos/{}", n)),
+                        (header::ETAG, format!("\"{}\"", n)),
+                    ],
+                    Json(json!({ "id": n })),
+                )
+                    .into_response()
+            }),
+        )
+        .layer(middleware::from_fn_with_state(state, idempotency))
+        .layer(CookieManagerLayer::new());
+    (app, pool, runs)
+}
+
+fn create_resource(key: &str) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/recursos")
+        .header("idempotency-key", key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_replay_keeps_response_headers() {
+    let (app, _pool, runs) = resource_app().await;
+
+    let first = send(&app, create_resource("rec-1")).await;
+    assert_eq!(first.status(), StatusCode::CREATED);
+    let replay = send(&app, create_resource("rec-1")).await;
+    assert_eq!(replay.status(), StatusCode::CREATED);
+    assert_eq!(replay.headers()["idempotent-replayed"], "true");
+    for name in [header::LOCATION, header::ETAG, header::CONTENT_TYPE] {
+        assert_eq!(replay.headers()[&name], first.headers()[&name], "{}", name);
+    }
+    assert_eq!(json(replay).await, json(first).await);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_key_is_released_when_the_response_cannot_be_stored() {
+    let (app, pool, runs) = resource_app().await;
+    // Simula un fallo de la base al guardar la respuesta
+    sqlx::query(
+        "CREATE TRIGGER fail_idempotency BEFORE UPDATE ON idempotency_keys
+        BEGIN SELECT RAISE(ABORT, 'disco lleno'); END",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // La respuesta llega igual y la clave no queda reservada: el reintento se
+    // ejecuta de nuevo en lugar de dar 409 hasta que venza la reserva
+    let response = send(&app, create_resource("rec-2")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM idempotency_keys")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+    let response = send(&app, create_resource("rec-2")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_keys_belong_to_the_user_and_rejections_are_not_stored() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    let app = create_app(AppState::builder_shared(users).build());
+    let root = common::sign_up(&app, "root").await;
+    let luis = common::sign_up(&app, "luis").await;
+    sqlx::query("UPDATE users SET role = 'admin'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let stored = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM idempotency_keys")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+    let batch = |cookie: Option<&str>| {
+        let mut builder = request("POST", "/api/v1/users:batch")
+            .header("content-type", "application/json")
+            .header("idempotency-key", "lote-1");
+        if let Some(cookie) = cookie {
+            builder = builder.header("cookie", cookie);
+        }
+        let body = json!({ "operations": [{ "op": "suspend", "id": 99 }] });
+        builder.body(Body::from(body.to_string())).unwrap()
+    };
+
+    // 1. Rechazos del guard y rutas inexistentes no reservan la clave
+    let response = send(&app, batch(None)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = send(
+        &app,
+        request("POST", "/api/v1/no-existe")
+            .header("idempotency-key", "lote-1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(stored().await, 0);
+
+    // 2. Con sesión se guarda; tras volver a iniciar sesión el reintento se repite
+    let response = send(&app, batch(Some(&root))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let relogin = {
+        let req = request("POST", "/api/v1/login")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": "root", "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        let response = send(&app, req).await;
+        let cookie = response.headers()["set-cookie"].to_str().unwrap();
+        cookie.split(';').next().unwrap().to_string()
+    };
+    assert_ne!(relogin, root);
+    let response = send(&app, batch(Some(&relogin))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+
+    // 3. La misma clave de otro usuario es otra clave
+    let response = send(&app, batch(Some(&luis))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert_eq!(stored().await, 2);
+}
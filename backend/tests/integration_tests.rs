@@ -1,14 +1,37 @@
+mod common;
+
 use axum::{
     body::Body,
     extract::ConnectInfo,
     http::{Request, StatusCode},
 };
-use backend::create_app;
-use http_body_util::BodyExt; // Para leer el cuerpo de la respuesta
+use backend::{
+    core::{
+        clock::{FixedClock, SystemClock},
+        models::user::{ArchiveKind, AuditLog, Claims, Role, UpdateUserRequest, UserStatus},
+        repository::DynUserRepository,
+        services::user::UserService,
+    },
+    create_app,
+    data::{
+        audit_archive::{chain_hash, verify_archives, AuditArchiver, GENESIS_HASH},
+        memory_repository::InMemoryRepository,
+        user_repository::SqliteRepository,
+    },
+    error::ErrorFormat,
+    settings::Settings,
+    state::AppState,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use http_body_util::BodyExt;
+use jsonwebtoken::{encode, EncodingKey, Header}; // Para leer el cuerpo de la respuesta
 use serde_json::json;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use sqlx::sqlite::SqlitePoolOptions;
+use std::io::{Read, Write};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
 use tower::ServiceExt; // Para llamar a app.oneshot()
 
 #[tokio::test]
@@ -26,7 +49,7 @@ async fn test_create_user_flow() {
         .expect("Fallo al migrar DB de test");
 
     // 3. Crear la App con el pool de prueba
-    let app = create_app(pool);
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
 
     // 4. Simular Petición HTTP (POST /users)
     let response = app
@@ -68,7 +91,7 @@ async fn test_login_flow() {
         .await
         .expect("Fallo Migrations");
 
-    let app = create_app(pool);
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
 
     // 2. Crear Usuario (Usamos app.clone() porque oneshot consume la instancia)
     let _ = app
@@ -76,7 +99,7 @@ async fn test_login_flow() {
         .oneshot(
             Request::builder()
                 .method("POST")
-                .uri("/users")
+                .uri("/api/v1/users")
                 .header("content-type", "application/json")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -98,7 +121,7 @@ async fn test_login_flow() {
         .oneshot(
             Request::builder()
                 .method("POST")
-                .uri("/login")
+                .uri("/api/v1/login")
                 .header("content-type", "application/json")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -134,7 +157,7 @@ async fn test_delete_user_rbac_protection() {
         .await
         .expect("Fallo Migrations");
 
-    let app = create_app(pool);
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
 
     // 2. Crear Víctima (User ID 1)
     let _ = app
@@ -142,7 +165,7 @@ async fn test_delete_user_rbac_protection() {
         .oneshot(
             Request::builder()
                 .method("POST")
-                .uri("/users")
+                .uri("/api/v1/users")
                 .header("content-type", "application/json")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -165,7 +188,7 @@ async fn test_delete_user_rbac_protection() {
         .oneshot(
             Request::builder()
                 .method("POST")
-                .uri("/users")
+                .uri("/api/v1/users")
                 .header("content-type", "application/json")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -188,7 +211,7 @@ async fn test_delete_user_rbac_protection() {
         .oneshot(
             Request::builder()
                 .method("POST")
-                .uri("/login")
+                .uri("/api/v1/login")
                 .header("content-type", "application/json")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -219,7 +242,7 @@ async fn test_delete_user_rbac_protection() {
         .oneshot(
             Request::builder()
                 .method("DELETE")
-                .uri("/users/1")
+                .uri("/api/v1/users/1")
                 .header("cookie", cookie)
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -244,7 +267,7 @@ async fn test_health_check() {
         .expect("Fallo DB Memoria");
 
     // No necesitamos migraciones para el health check básico, pero sí para que la pool sea válida
-    let app = create_app(pool);
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
 
     // 2. Request
     let response = app
@@ -277,7 +300,7 @@ async fn test_pagination() {
         .run(&pool)
         .await
         .expect("Fallo Migrations");
-    let app = create_app(pool);
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
 
     // 2. Crear 2 Usuarios
     for i in 1..=2 {
@@ -286,7 +309,7 @@ async fn test_pagination() {
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/users")
+                    .uri("/api/v1/users")
                     .header("content-type", "application/json")
                     .extension(ConnectInfo(SocketAddr::new(
                         IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -309,7 +332,7 @@ async fn test_pagination() {
         .oneshot(
             Request::builder()
                 .method("GET")
-                .uri("/users?page=1&limit=1")
+                .uri("/api/v1/users?page=1&limit=1")
                 .extension(ConnectInfo(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
                     8080,
@@ -327,3 +350,878 @@ async fn test_pagination() {
     let users: Vec<Value> = serde_json::from_slice(&body_bytes).unwrap();
     assert_eq!(users.len(), 1);
 }
+
+#[tokio::test]
+async fn test_audit_retention_archives_and_purges() {
+    // 1. Setup (una sola conexión: la DB en memoria vive en ella)
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Fallo DB Memoria");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Fallo Migrations");
+
+    // 2. Tres registros vencidos y uno reciente
+    for target in ["old_1", "old_2", "old_3"] {
+        sqlx::query("INSERT INTO audit_logs (admin_username, action, target, timestamp) VALUES ('admin', 'DELETE_USER', $1, datetime('now', '-100 days'))")
+            .bind(target)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query("INSERT INTO audit_logs (admin_username, action, target) VALUES ('admin', 'DELETE_USER', 'recent')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    // ... y dos rechazos de los guards vencidos
+    for reason in ["token_missing", "insufficient_role"] {
+        sqlx::query("INSERT INTO security_events (reason, method, path, timestamp) VALUES ($1, 'GET', '/api/v1/dashboard', datetime('now', '-100 days'))")
+            .bind(reason)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    // 3. Archivar con retención de 90 días
+    let dir = tempfile::tempdir().unwrap();
+    let archiver = AuditArchiver::new(
+        Arc::new(SqliteRepository::new(pool.clone())),
+        Arc::new(SystemClock),
+        dir.path(),
+        90,
+    );
+    let archives = archiver.run().await.unwrap();
+    assert_eq!(archives.len(), 2);
+    assert_eq!(archives[0].entries, 3);
+    assert_eq!(archives[1].kind, ArchiveKind::SecurityEvents);
+    assert_eq!(archives[1].entries, 2);
+    assert!(archives[1].file_name.starts_with("security-"));
+
+    // 4. El archivo contiene la cadena de hashes completa
+    let file = std::fs::File::open(dir.path().join(&archives[0].file_name)).unwrap();
+    let mut ndjson = String::new();
+    GzDecoder::new(file).read_to_string(&mut ndjson).unwrap();
+    let mut prev = GENESIS_HASH.to_string();
+    for line in ndjson.lines() {
+        let entry: Value = serde_json::from_str(line).unwrap();
+        let log = AuditLog {
+            id: entry["id"].as_i64().unwrap(),
+            admin_username: entry["admin_username"].as_str().unwrap().to_string(),
+            action: entry["action"].as_str().unwrap().to_string(),
+            target: entry["target"].as_str().unwrap().to_string(),
+            timestamp: entry["timestamp"].as_str().unwrap().parse().unwrap(),
+        };
+        assert_eq!(entry["prev_hash"], prev.as_str());
+        prev = chain_hash(&prev, &log);
+        assert_eq!(entry["hash"], prev.as_str());
+    }
+    assert_eq!(archives[0].last_hash, prev);
+
+    // 5. Los eventos de seguridad van en su propia cadena, y ambas verifican
+    let checks = verify_archives(dir.path(), &archives);
+    assert!(checks.iter().all(|c| c.ok), "{:?}", checks);
+    let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM security_events")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(events, 0);
+
+    // 6. Quedan el reciente y las propias acciones de archivado
+    let remaining: Vec<(String, String)> =
+        sqlx::query_as("SELECT admin_username, target FROM audit_logs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        remaining,
+        vec![
+            ("admin".to_string(), "recent".to_string()),
+            ("SYSTEM".to_string(), archives[0].file_name.clone()),
+            ("SYSTEM".to_string(), archives[1].file_name.clone()),
+        ]
+    );
+
+    // 7. Una segunda pasada no genera nada
+    assert!(archiver.run().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_verify_archives_detects_tampering() {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Fallo DB Memoria");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Fallo Migrations");
+    for target in ["old_1", "old_2"] {
+        sqlx::query("INSERT INTO audit_logs (admin_username, action, target, timestamp) VALUES ('admin', 'DELETE_USER', $1, datetime('now', '-100 days'))")
+            .bind(target)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Arc::new(SqliteRepository::new(pool.clone()));
+    let archives = AuditArchiver::new(repo, Arc::new(SystemClock), dir.path(), 90)
+        .run()
+        .await
+        .unwrap();
+
+    // 1. Recién generado, todo cuadra
+    let checks = verify_archives(dir.path(), &archives);
+    assert_eq!(checks.len(), 1);
+    assert!(checks[0].ok, "{:?}", checks[0].error);
+
+    // 2. Se altera un registro y se recomprime
+    let path = dir.path().join(&archives[0].file_name);
+    let mut ndjson = String::new();
+    GzDecoder::new(std::fs::File::open(&path).unwrap())
+        .read_to_string(&mut ndjson)
+        .unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(ndjson.replace("old_1", "otro").as_bytes())
+        .unwrap();
+    let tampered = encoder.finish().unwrap();
+    std::fs::write(&path, &tampered).unwrap();
+
+    let checks = verify_archives(dir.path(), &archives);
+    assert!(!checks[0].ok);
+    assert!(checks[0].error.as_deref().unwrap().contains("sha256"));
+
+    // 3. Aunque también se falsee el sha256 registrado, la cadena lo delata
+    let mut forged = archives[0].clone();
+    forged.sha256 = hex::encode(Sha256::digest(&tampered));
+    let checks = verify_archives(dir.path(), &[forged]);
+    assert!(!checks[0].ok);
+    assert!(checks[0].error.as_deref().unwrap().contains("alterado"));
+}
+
+#[tokio::test]
+async fn test_audit_archives_endpoint_requires_admin() {
+    // 1. Setup
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Fallo DB Memoria");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Fallo Migrations");
+    let app = create_app(AppState::builder(SqliteRepository::new(pool.clone())).build());
+
+    // 2. Sin sesión: 401
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/v1/audit-logs/archives")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // 3. Crear Admin y hacer Login
+    let _ = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/users")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "root_admin", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    sqlx::query("UPDATE users SET role = 'admin' WHERE username = 'root_admin'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let login_response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "root_admin", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let cookie = login_response
+        .headers()
+        .get("set-cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // 4. Listado vacío
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/v1/audit-logs/archives")
+                .header("cookie", cookie)
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let archives: Vec<Value> = serde_json::from_slice(&body_bytes).unwrap();
+    assert!(archives.is_empty());
+}
+
+#[tokio::test]
+async fn test_errors_are_problem_json() {
+    // 1. Setup
+    let app = create_app(AppState::builder(InMemoryRepository::new()).build());
+
+    // 2. Login con un usuario inexistente
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "nadie", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // 3. Problem Details con código estable e instance = x-request-id
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/problem+json"
+    );
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(problem["code"], "unauthorized");
+    assert_eq!(problem["status"], 401);
+    assert_eq!(problem["type"], "urn:sintonia:error:unauthorized");
+    assert_eq!(problem["instance"], request_id.as_str());
+}
+
+#[tokio::test]
+async fn test_legacy_error_format() {
+    // 1. Setup con formato de compatibilidad
+    let settings = Settings {
+        error_format: ErrorFormat::Legacy,
+        ..Settings::default()
+    };
+    let app = create_app(
+        AppState::builder(InMemoryRepository::new())
+            .settings(settings)
+            .build(),
+    );
+
+    // 2. Request
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "nadie", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // 3. Forma antigua {"error": "..."}
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(body, json!({ "error": "Credenciales inválidas" }));
+}
+
+#[tokio::test]
+async fn test_create_user_validation_and_body_rejections() {
+    // 1. Setup
+    let app = create_app(AppState::builder(InMemoryRepository::new()).build());
+
+    // 2. (content-type, cuerpo) -> (status, code)
+    let cases = [
+        (
+            "application/json",
+            json!({ "username": "yo", "password": "123" }).to_string(),
+            StatusCode::BAD_REQUEST,
+            "validation_failed",
+        ),
+        (
+            "application/json",
+            "{\"username\": ".to_string(),
+            StatusCode::BAD_REQUEST,
+            "malformed_json",
+        ),
+        (
+            "application/json",
+            json!({ "username": "sin_password" }).to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_body",
+        ),
+        (
+            "text/plain",
+            json!({ "username": "usuario", "password": "password123" }).to_string(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+        ),
+    ];
+
+    for (content_type, body, status, code) in cases {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/users")
+                    .header("content-type", content_type)
+                    .extension(ConnectInfo(SocketAddr::new(
+                        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                        8080,
+                    )))
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // 3. Todos usan el mismo formato problem+json
+        assert_eq!(response.status(), status);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(problem["code"], code);
+
+        if code == "validation_failed" {
+            assert_eq!(problem["errors"]["username"][0]["code"], "length");
+            assert_eq!(problem["errors"]["username"][0]["params"]["min"], 3);
+            assert_eq!(problem["errors"]["password"][0]["params"]["min"], 8);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_localized_messages() {
+    // 1. Setup
+    let app = create_app(AppState::builder(InMemoryRepository::new()).build());
+
+    // 2. Accept-Language en -> errores en inglés
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .header("accept-language", "en-US,en;q=0.9,es;q=0.5")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "nadie", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("content-language").unwrap(), "en");
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(problem["detail"], "Invalid credentials");
+    assert_eq!(problem["title"], "Not authenticated");
+
+    // 3. Crear usuario, Login y guardar preferencia "en"
+    let _ = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/users")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "english_user", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    let login_response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "english_user", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let cookie = login_response
+        .headers()
+        .get("set-cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/api/v1/me/locale")
+                .header("content-type", "application/json")
+                .header("cookie", cookie)
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(json!({ "locale": "en" }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = response
+        .headers()
+        .get("set-cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // 4. La preferencia manda sobre Accept-Language
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/users")
+                .header("content-type", "application/json")
+                .header("accept-language", "es")
+                .header("cookie", cookie)
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "english_user", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(problem["detail"], "Username already exists");
+}
+
+#[tokio::test]
+async fn test_guard_failure_reasons() {
+    // 1. Setup
+    let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+    common::create_admin(&users, "root").await;
+    users.create_user("pepe", "hash").await.unwrap();
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+
+    let token = |secret: &str, exp: i64| {
+        encode(
+            &Header::default(),
+            &Claims {
+                sub: "alguien".to_string(),
+                role: Role::User,
+                exp: exp as usize,
+                locale: None,
+                jti: None,
+                uid: None,
+            },
+            &EncodingKey::from_secret(secret.as_ref()),
+        )
+        .unwrap()
+    };
+    let now = chrono::Utc::now().timestamp();
+
+    // 2. (cookie, code esperado)
+    let cases = [
+        (None, "token_missing"),
+        (Some("basura".to_string()), "token_invalid"),
+        (
+            Some(token("otro_secreto", now + 3600)),
+            "token_invalid_signature",
+        ),
+        (Some(token("secret", now - 3600)), "token_expired"),
+    ];
+
+    for (cookie, code) in cases {
+        let mut request = Request::builder()
+            .method("GET")
+            .uri("/api/v1/dashboard")
+            .extension(ConnectInfo(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                8080,
+            )));
+        if let Some(value) = cookie {
+            request = request.header("cookie", format!("auth_token={}", value));
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        // 3. 401 con motivo, challenge y cuerpo problem+json
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response
+            .headers()
+            .get("www-authenticate")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(challenge.starts_with("Bearer realm=\"sintonia\""));
+        let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(problem["code"], code);
+    }
+
+    // 4. Las métricas cuentan cada motivo, y solo las ve un admin
+    let metrics = |cookie: Option<String>| {
+        let mut request = common::request("GET", "/metrics");
+        if let Some(cookie) = cookie {
+            request = request.header("cookie", cookie);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+    let (status, _) = common::call_text(&app, metrics(None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let user = common::cookie("pepe", Role::Admin);
+    let (status, _) = common::call_text(&app, metrics(Some(user))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let admin = common::cookie("root", Role::Admin);
+    let (status, metrics) = common::call_text(&app, metrics(Some(admin))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(metrics.contains("auth_guard_failures_total{reason=\"token_expired\"}"));
+
+    // 5. Cada rechazo queda guardado en `security_events`, sin la query string
+    let events = users
+        .get_security_events_before(chrono::Utc::now() + chrono::Duration::days(1), 100)
+        .await
+        .unwrap();
+    let reasons: Vec<&str> = events.iter().map(|e| e.reason.as_str()).collect();
+    assert_eq!(
+        reasons,
+        [
+            "token_missing",
+            "token_invalid",
+            "token_invalid_signature",
+            "token_expired",
+            "token_missing",
+            "insufficient_role",
+        ]
+    );
+    assert_eq!(
+        (events[0].method.as_str(), events[0].path.as_str()),
+        ("GET", "/api/v1/dashboard")
+    );
+    assert_eq!(events[5].path, "/metrics");
+}
+
+#[tokio::test]
+async fn test_suspended_users_cannot_log_in_or_keep_their_session() {
+    // 1. Setup: ana con una sesión abierta
+    let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+    let session = common::sign_up(&app, "ana").await;
+    let dashboard = || {
+        common::request("GET", "/api/v1/dashboard")
+            .header("cookie", &session)
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(common::call_text(&app, dashboard()).await.0, StatusCode::OK);
+
+    // 2. Un admin la suspende
+    let ana = users.get_by_username("ana").await.unwrap().unwrap();
+    let suspend = UpdateUserRequest {
+        status: Some(UserStatus::Suspended),
+        ..Default::default()
+    };
+    UserService::new(users)
+        .update(ana.id, &suspend, None, "root")
+        .await
+        .unwrap();
+
+    // 3. Ni nuevas sesiones ni las ya abiertas
+    let login = common::request("POST", "/api/v1/login")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "username": "ana", "password": "password123" }).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = common::call_text(&app, login).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert!(body.contains("account_suspended"), "{}", body);
+    let (status, body) = common::call_text(&app, dashboard()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert!(body.contains("account_suspended"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_demoted_admin_loses_access_with_an_open_session() {
+    // 1. Setup: luis entra como admin
+    let users: DynUserRepository = Arc::new(InMemoryRepository::new());
+    let luis = common::create_admin(&users, "luis").await;
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+    let export = || {
+        common::request("GET", "/api/v1/admin/users/export")
+            .header("cookie", common::cookie("luis", Role::Admin))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(common::call_text(&app, export()).await.0, StatusCode::OK);
+
+    // 2. Otro admin le quita el rol
+    UserService::new(users)
+        .change_role(luis.id, Role::User, "root")
+        .await
+        .unwrap();
+
+    // 3. El token aún dice admin, pero vale el rol guardado
+    let (status, body) = common::call_text(&app, export()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert!(body.contains("insufficient_role"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_logout_revokes_session() {
+    // 1. Setup
+    let app = create_app(AppState::builder(InMemoryRepository::new()).build());
+
+    // 2. Crear usuario y Login
+    let _ = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/users")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "sesion_user", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    let login_response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "sesion_user", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let cookie = login_response
+        .headers()
+        .get("set-cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // 3. Logout
+    let _ = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/logout")
+                .header("cookie", cookie.clone())
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // 4. Reusar la cookie anterior: sesión revocada
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/v1/dashboard")
+                .header("cookie", cookie)
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(problem["code"], "session_revoked");
+}
+
+#[tokio::test]
+async fn test_injected_clock_drives_token_expiry() {
+    // 1. Setup con un reloj detenido hace dos días (el token de 24h nace vencido)
+    let state = AppState::builder(InMemoryRepository::new())
+        .clock(FixedClock(chrono::Utc::now() - chrono::Duration::days(2)))
+        .build();
+    let app = create_app(state);
+
+    // 2. Crear usuario y Login
+    let _ = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/users")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "viajero", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+    let login_response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/login")
+                .header("content-type", "application/json")
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::from(
+                    json!({ "username": "viajero", "password": "password123" }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(login_response.status(), StatusCode::OK);
+    let cookie = login_response
+        .headers()
+        .get("set-cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // 3. El guard valida contra la hora real: token vencido
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/v1/dashboard")
+                .header("cookie", cookie)
+                .extension(ConnectInfo(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    8080,
+                )))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let problem: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(problem["code"], "token_expired");
+}
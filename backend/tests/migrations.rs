@@ -0,0 +1,97 @@
+use backend::data::db::{DbPool, MigrationState, MigrationStatus};
+
+fn states(status: &[MigrationStatus]) -> Vec<(i64, MigrationState)> {
+    status.iter().map(|m| (m.version, m.state)).collect()
+}
+
+/// El `DbPool` bajo prueba y una conexión aparte al mismo archivo para inspeccionarlo.
+async fn sqlite_db(dir: &tempfile::TempDir) -> (DbPool, sqlx::SqlitePool) {
+    let url = format!("sqlite://{}", dir.path().join("migraciones.db").display());
+    let db = DbPool::connect(&url).await.expect("Fallo DB");
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    (db, pool)
+}
+
+async fn table_exists(pool: &sqlx::SqlitePool, name: &str) -> bool {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = $1)",
+    )
+    .bind(name)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_status_up_and_down() {
+    let dir = tempfile::tempdir().unwrap();
+    let (db, pool) = sqlite_db(&dir).await;
+
+    // 1. Base vacía: todo pendiente y todo reversible
+    let status = db.migration_status().await.unwrap();
+    assert!(!status.is_empty());
+    assert!(status.iter().all(|m| m.state == MigrationState::Pending));
+    assert!(status.iter().all(|m| m.reversible));
+
+    // 2. Tras migrar, todo aplicado
+    db.migrate().await.unwrap();
+    let status = db.migration_status().await.unwrap();
+    assert!(status.iter().all(|m| m.state == MigrationState::Applied));
+    let latest = status.last().unwrap().version;
+
+    // 3. El plan de reversión va de la más nueva a la más antigua y no toca nada
+    let plan = db.revert_plan(3).await.unwrap();
+    let versions: Vec<i64> = plan.iter().map(|m| m.version).collect();
+    assert_eq!(versions, (4..=latest).rev().collect::<Vec<_>>());
+    assert!(table_exists(&pool, "audit_logs").await);
+
+    // 4. Revertir deshace el esquema y deja las migraciones pendientes
+    db.revert(3).await.unwrap();
+    assert!(!table_exists(&pool, "audit_logs").await);
+    assert!(table_exists(&pool, "users").await);
+    let status = db.migration_status().await.unwrap();
+    let expected: Vec<_> = status
+        .iter()
+        .map(|m| match m.version {
+            v if v <= 3 => (v, MigrationState::Applied),
+            v => (v, MigrationState::Pending),
+        })
+        .collect();
+    assert_eq!(states(&status), expected);
+
+    // 5. Y se pueden volver a aplicar
+    db.migrate().await.unwrap();
+    assert!(table_exists(&pool, "audit_logs").await);
+    db.revert(0).await.unwrap();
+    assert!(!table_exists(&pool, "users").await);
+}
+
+#[tokio::test]
+async fn test_checksum_mismatch_and_database_ahead() {
+    let dir = tempfile::tempdir().unwrap();
+    let (db, pool) = sqlite_db(&dir).await;
+    db.migrate().await.unwrap();
+    db.ensure_not_ahead().await.unwrap();
+
+    // 1. Una migración aplicada cuyo contenido ya no coincide
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = x'00' WHERE version = 2")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let status = db.migration_status().await.unwrap();
+    assert_eq!(status[1].state, MigrationState::ChecksumMismatch);
+    assert!(db.migrate().await.is_err());
+
+    // 2. Una migración que este binario no conoce: la base va por delante
+    sqlx::query("INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (9999, 'futura', TRUE, x'00', 0)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let status = db.migration_status().await.unwrap();
+    let last = status.last().unwrap();
+    assert_eq!((last.version, last.state), (9999, MigrationState::Unknown));
+
+    let err = db.ensure_not_ahead().await.unwrap_err();
+    assert!(err.to_string().contains("9999"), "{}", err);
+    assert!(db.revert_plan(0).await.is_err());
+}
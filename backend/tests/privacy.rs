@@ -0,0 +1,97 @@
+mod common;
+
+use axum::{body::Body, http::StatusCode};
+use backend::{
+    core::{clock::FixedClock, repository::DynUserRepository, services::privacy::PrivacyService},
+    create_app,
+    data::user_repository::SqliteRepository,
+    state::AppState,
+};
+use common::{call, memory_pool, request, send, sign_up};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_export_and_erasure_with_grace_period() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool.clone()));
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+    let cookie = sign_up(&app, "ana").await;
+    let me = |method: &str, uri: &str| {
+        request(method, uri)
+            .header("cookie", &cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // 1. Exportación: perfil sin hash, último login y nada pendiente
+    let response = send(&app, me("GET", "/api/v1/me/export")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment; filename=\"datos-"));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let export: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(export["profile"]["username"], "ana");
+    assert!(!String::from_utf8_lossy(&body).contains("argon2"));
+    assert!(export["sessions"]["last_login_at"].is_string());
+    assert!(export["erasure"].is_null());
+
+    // 2. Solicitud de borrado: una sola pendiente, visible en la exportación
+    let (status, pending) = call(&app, me("DELETE", "/api/v1/me")).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", pending);
+    assert_eq!(pending["status"], "pending");
+    assert!(pending.get("pseudonym").is_none());
+    assert_eq!(
+        call(&app, me("DELETE", "/api/v1/me")).await.0,
+        StatusCode::CONFLICT
+    );
+    let (_, export) = call(&app, me("GET", "/api/v1/me/export")).await;
+    assert_eq!(export["erasure"]["id"], pending["id"]);
+    assert_eq!(export["audit_logs"][0]["action"], "REQUEST_ERASURE");
+
+    // 3. Cancelar durante el periodo de gracia
+    let (status, _) = call(&app, me("GET", "/api/v1/me/erasure")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        call(&app, me("DELETE", "/api/v1/me/erasure")).await.0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&app, me("DELETE", "/api/v1/me/erasure")).await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        call(&app, me("GET", "/api/v1/me/erasure")).await.0,
+        StatusCode::NOT_FOUND
+    );
+
+    // 4. Vencido el plazo se borra la cuenta y la auditoría queda seudonimizada
+    assert_eq!(
+        call(&app, me("DELETE", "/api/v1/me")).await.0,
+        StatusCode::ACCEPTED
+    );
+    let later = chrono::Utc::now() + chrono::Duration::days(31);
+    let privacy = PrivacyService::new(users.clone(), Arc::new(FixedClock(later)), 30);
+    assert_eq!(privacy.run_due().await.unwrap().len(), 1);
+    assert!(users.get_by_username("ana").await.unwrap().is_none());
+    let rows: Vec<(String, String, String)> =
+        sqlx::query_as("SELECT admin_username, action, target FROM audit_logs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(rows.len(), 4);
+    assert!(rows
+        .iter()
+        .all(|(actor, _, target)| actor != "ana" && target != "ana"));
+    assert_eq!(rows[3].1, "ERASE_USER");
+    assert_eq!(rows[0].0, rows[3].2, "el seudónimo enlaza las entradas");
+
+    // 5. Sin sesión no hay nada que exportar
+    let req = request("GET", "/api/v1/me/export")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,1030 @@
+//! Batería de conformidad de `UserRepository`.
+//! Cada caso se ejecuta contra SQLite, la implementación en memoria y, con la
+//! feature `postgres`, contra PostgreSQL; esos casos están marcados `#[ignore]`
+//! y se activan con `--include-ignored` y `TEST_DATABASE_URL` definida.
+//! Así cualquier divergencia de semántica rompe un test.
+use backend::{
+    core::{
+        models::privacy::ErasureStatus,
+        models::user::{
+            ArchiveKind, Keyset, NewAuditArchive, Role, SortOrder, UpdateUserRequest, UserSearch,
+            UserSort, UserStatus, SYSTEM_ACTOR,
+        },
+        repository::DynUserRepository,
+    },
+    data::{memory_repository::InMemoryRepository, user_repository::SqliteRepository},
+    error::AppError,
+    i18n::Locale,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+
+// Fecha posterior a cualquier timestamp real: todo el historial es "antiguo"
+fn far_future() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(9999, 12, 31, 23, 59, 59).unwrap()
+}
+
+/// Las fechas se guardan en UTC: una zona horaria mal aplicada las desplaza horas.
+fn assert_recent(at: DateTime<Utc>) {
+    let drift = (Utc::now() - at).num_seconds().abs();
+    assert!(drift < 60, "fecha fuera de rango: {} ({}s)", at, drift);
+}
+
+/// Parámetros de listado con texto y página; el resto por defecto.
+fn query(q: Option<&str>, page: i64, limit: i64) -> UserSearch {
+    UserSearch {
+        q: q.map(Into::into),
+        page,
+        limit,
+        ..Default::default()
+    }
+}
+
+/// Búsqueda de un admin: `q` también en el email.
+fn admin_query(q: &str) -> UserSearch {
+    UserSearch {
+        search_email: true,
+        ..query(Some(q), 1, 10)
+    }
+}
+
+async fn sqlite_repo() -> DynUserRepository {
+    // Una sola conexión: cada conexión a sqlite::memory: es una base distinta
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Fallo al crear DB en memoria");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Fallo al migrar DB de test");
+    Arc::new(SqliteRepository::new(pool))
+}
+
+async fn memory_repo() -> DynUserRepository {
+    Arc::new(InMemoryRepository::new())
+}
+
+/// Cada test migra su propio esquema para no compartir ids ni filas.
+/// Ejecutar la batería sin `TEST_DATABASE_URL` es un error, no un test superado.
+#[cfg(feature = "postgres")]
+async fn postgres_repo() -> DynUserRepository {
+    use backend::data::pg_repository::PgRepository;
+    use sqlx::{postgres::PgPoolOptions, Executor};
+
+    let url = std::env::var("TEST_DATABASE_URL")
+        .expect("La batería PostgreSQL requiere TEST_DATABASE_URL");
+
+    let schema = format!("conformance_{:016x}", rand::random::<u64>());
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&url)
+        .await
+        .expect("Fallo al conectar a TEST_DATABASE_URL");
+    admin
+        .execute(format!("CREATE SCHEMA {}", schema).as_str())
+        .await
+        .expect("Fallo al crear el esquema de test");
+
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .after_connect(move |conn, _meta| {
+            let search_path = format!("SET search_path TO {}", schema);
+            Box::pin(async move {
+                conn.execute(search_path.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect(&url)
+        .await
+        .expect("Fallo al conectar a TEST_DATABASE_URL");
+    sqlx::migrate!("./migrations_pg")
+        .run(&pool)
+        .await
+        .expect("Fallo al migrar DB de test");
+    Arc::new(PgRepository::new(pool))
+}
+
+async fn create_and_fetch(repo: DynUserRepository) {
+    repo.ping().await.unwrap();
+
+    let user = repo.create_user("ana", "hash-ana").await.unwrap();
+    assert_eq!(user.username, "ana");
+    assert_eq!(user.password_hash, "hash-ana");
+    assert_eq!(user.role, Role::User);
+    assert_eq!(user.locale, None);
+
+    let fetched = repo.get_by_username("ana").await.unwrap().unwrap();
+    assert_eq!(fetched.id, user.id);
+    assert_eq!(fetched.created_at, user.created_at);
+    assert_recent(user.created_at);
+    assert_eq!(fetched.last_login_at, None);
+
+    // El último login se guarda con la precisión de las demás fechas (segundos)
+    let login = Utc.with_ymd_and_hms(2030, 5, 1, 12, 30, 15).unwrap();
+    repo.record_login(user.id, login).await.unwrap();
+    let fetched = repo.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(fetched.last_login_at, Some(login));
+
+    assert!(repo.get_by_username("nadie").await.unwrap().is_none());
+}
+
+async fn duplicate_username_conflicts(repo: DynUserRepository) {
+    repo.create_user("ana", "h1").await.unwrap();
+    let err = repo.create_user("ana", "h2").await.unwrap_err();
+    assert!(
+        matches!(&err, AppError::Conflict(key) if key == "user.username_taken"),
+        "se esperaba Conflict, llegó {:?}",
+        err
+    );
+}
+
+async fn search_and_paging(repo: DynUserRepository) {
+    for name in ["alice", "Bob", "ALBERTO", "carla", "alfredo"] {
+        repo.create_user(name, "hash").await.unwrap();
+    }
+
+    let names = |users: Vec<backend::core::models::user::User>| {
+        users.into_iter().map(|u| u.username).collect::<Vec<_>>()
+    };
+
+    // Sin filtro (o filtro vacío): orden de inserción
+    let all = names(repo.get_all(&query(None, 1, 10)).await.unwrap().items);
+    assert_eq!(all, ["alice", "Bob", "ALBERTO", "carla", "alfredo"]);
+    let empty_q = names(repo.get_all(&query(Some(""), 1, 10)).await.unwrap().items);
+    assert_eq!(empty_q, all);
+
+    // Búsqueda sin distinción de mayúsculas
+    let al = names(repo.get_all(&query(Some("AL"), 1, 10)).await.unwrap().items);
+    assert_eq!(al, ["alice", "ALBERTO", "alfredo"]);
+
+    // Los comodines de LIKE se buscan literalmente (también en la búsqueda)
+    repo.create_user("a_b%c", "hash").await.unwrap();
+    for q in ["_", "%", "\\"] {
+        let literal = names(repo.get_all(&query(Some(q), 1, 10)).await.unwrap().items);
+        let expected: &[&str] = if q == "\\" { &[] } else { &["a_b%c"] };
+        assert_eq!(literal, expected, "q={:?}", q);
+        let hits = repo.search(&query(Some(q), 1, 10)).await.unwrap();
+        assert_eq!(hits.total, expected.len() as i64, "q={:?}", q);
+    }
+    let b_c = names(
+        repo.get_all(&query(Some("b%c"), 1, 10))
+            .await
+            .unwrap()
+            .items,
+    );
+    assert_eq!(b_c, ["a_b%c"]);
+
+    // Paginación
+    let page2 = names(repo.get_all(&query(None, 2, 2)).await.unwrap().items);
+    assert_eq!(page2, ["ALBERTO", "carla"]);
+    let page3 = names(repo.get_all(&query(None, 3, 2)).await.unwrap().items);
+    assert_eq!(page3, ["alfredo", "a_b%c"]);
+    assert!(repo
+        .get_all(&query(None, 4, 2))
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+}
+
+async fn lookup_and_delete_by_id(repo: DynUserRepository) {
+    let user = repo.create_user("victima", "hash").await.unwrap();
+
+    let fetched = repo.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(fetched.username, "victima");
+    assert!(repo.get_by_id(9999).await.unwrap().is_none());
+
+    assert!(repo.delete_user(user.id).await.unwrap());
+    assert!(repo.get_by_username("victima").await.unwrap().is_none());
+    // Borrar un id inexistente no es un error
+    assert!(!repo.delete_user(user.id).await.unwrap());
+
+    // Los ids no se reutilizan tras un borrado
+    let next = repo.create_user("otra", "hash").await.unwrap();
+    assert!(next.id > user.id);
+}
+
+async fn audit_log_order(repo: DynUserRepository) {
+    repo.record_audit("admin", "DELETE_USER", "primero")
+        .await
+        .unwrap();
+    repo.record_audit("root", "PROMOTE_USER", "segundo")
+        .await
+        .unwrap();
+
+    let logs = repo.get_audit_logs().await.unwrap();
+    assert_eq!(logs.len(), 2);
+    // Más reciente primero
+    assert_eq!(logs[0].target, "segundo");
+    assert_eq!(logs[0].admin_username, "root");
+    assert_eq!(logs[0].action, "PROMOTE_USER");
+    assert_eq!(logs[1].target, "primero");
+    assert!(logs[0].id > logs[1].id);
+    assert_recent(logs[0].timestamp);
+}
+
+async fn unit_of_work_commits_together(repo: DynUserRepository) {
+    let user = repo.create_user("ana", "hash").await.unwrap();
+
+    let mut uow = repo.begin().await.unwrap();
+    assert!(!uow.has_admin().await.unwrap());
+    assert!(uow.set_role(user.id, Role::Admin).await.unwrap());
+    assert!(uow.has_admin().await.unwrap());
+    assert!(!uow.set_role(9999, Role::Admin).await.unwrap());
+    assert!(uow.set_password_hash(user.id, "nuevo").await.unwrap());
+    assert!(!uow.set_password_hash(9999, "nuevo").await.unwrap());
+    let staged = uow.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(staged.role, Role::Admin);
+    uow.record_audit("root", "CHANGE_ROLE", "ana:admin")
+        .await
+        .unwrap();
+    uow.commit().await.unwrap();
+
+    let stored = repo.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(stored.role, Role::Admin);
+    assert_eq!(stored.password_hash, "nuevo");
+    assert_eq!(repo.get_audit_logs().await.unwrap().len(), 1);
+}
+
+async fn unit_of_work_rolls_back_when_dropped(repo: DynUserRepository) {
+    let user = repo.create_user("ana", "hash").await.unwrap();
+
+    {
+        let mut uow = repo.begin().await.unwrap();
+        assert!(uow.delete_user(user.id).await.unwrap());
+        assert!(uow.get_by_id(user.id).await.unwrap().is_none());
+        let staged = uow.create_user("bea", "hash").await.unwrap();
+        assert!(uow.get_by_id(staged.id).await.unwrap().is_some());
+        uow.record_audit("root", "DELETE_USER", "ana")
+            .await
+            .unwrap();
+        // Lo último: en Postgres un error invalida el resto de la transacción
+        match uow.create_user("bea", "hash").await {
+            Err(AppError::Conflict(_)) => {}
+            _ => panic!("el nombre duplicado debe dar Conflict también en la transacción"),
+        }
+        // Sin commit: como si el siguiente paso hubiera fallado
+    }
+
+    assert!(repo.get_by_id(user.id).await.unwrap().is_some());
+    assert!(repo.get_by_username("bea").await.unwrap().is_none());
+    assert!(repo.get_audit_logs().await.unwrap().is_empty());
+}
+
+async fn locale_roundtrip(repo: DynUserRepository) {
+    repo.create_user("ana", "hash").await.unwrap();
+
+    repo.set_locale("ana", Some(Locale::En)).await.unwrap();
+    let user = repo.get_by_username("ana").await.unwrap().unwrap();
+    assert_eq!(user.locale, Some(Locale::En));
+
+    repo.set_locale("ana", None).await.unwrap();
+    let user = repo.get_by_username("ana").await.unwrap().unwrap();
+    assert_eq!(user.locale, None);
+
+    // Usuario inexistente: no es un error
+    repo.set_locale("nadie", Some(Locale::Es)).await.unwrap();
+}
+
+/// Lo que FTS5 (SQLite) y LIKE (resto) deben tener en común.
+async fn profile_search(repo: DynUserRepository) {
+    for name in ["ana", "bob", "carla"] {
+        repo.create_user(name, "hash").await.unwrap();
+    }
+    repo.set_profile("bob", Some("Roberto Gómez"), Some("bob@example.com"))
+        .await
+        .unwrap();
+    repo.set_profile("carla", None, Some("carla@corp.io"))
+        .await
+        .unwrap();
+
+    let hits = |hits: Vec<backend::core::models::user::UserHit>| {
+        hits.into_iter()
+            .map(|h| (h.user.username, h.highlight.unwrap_or_default()))
+            .collect::<Vec<_>>()
+    };
+
+    // El email solo se busca a petición (admins)
+    assert!(repo
+        .search(&query(Some("example"), 1, 10))
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+    assert_eq!(
+        repo.get_all(&query(Some("example"), 1, 10))
+            .await
+            .unwrap()
+            .total,
+        0
+    );
+
+    // Nombre visible y email, sin distinción de mayúsculas y con resaltado
+    let found = hits(repo.search(&admin_query("EXAMPLE")).await.unwrap().items);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "bob");
+    assert!(found[0].1.contains("<mark>example</mark>"), "{:?}", found);
+    let found = hits(
+        repo.search(&query(Some("rober"), 1, 10))
+            .await
+            .unwrap()
+            .items,
+    );
+    assert_eq!(found[0].0, "bob");
+    assert!(found[0].1.starts_with("<mark>Rober"), "{:?}", found);
+
+    let user = repo.get_by_username("bob").await.unwrap().unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("Roberto Gómez"));
+    assert_eq!(user.email.as_deref(), Some("bob@example.com"));
+
+    // Borrar el perfil o el usuario lo saca de los resultados
+    repo.set_profile("bob", None, None).await.unwrap();
+    assert!(repo
+        .search(&admin_query("example"))
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+    let carla = repo.get_by_username("carla").await.unwrap().unwrap();
+    assert_eq!(
+        repo.search(&admin_query("corp")).await.unwrap().items.len(),
+        1
+    );
+    repo.delete_user(carla.id).await.unwrap();
+    assert!(repo
+        .search(&admin_query("corp"))
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+}
+
+async fn sort_filter_and_total(repo: DynUserRepository) {
+    for name in ["carla", "Bob", "alice", "dora"] {
+        repo.create_user(name, "hash").await.unwrap();
+        repo.set_profile(name, Some("Equipo"), None).await.unwrap();
+    }
+    let bob = repo.get_by_username("Bob").await.unwrap().unwrap();
+    let mut uow = repo.begin().await.unwrap();
+    uow.set_role(bob.id, Role::Admin).await.unwrap();
+    uow.commit().await.unwrap();
+
+    let list = |params: UserSearch| {
+        let repo = repo.clone();
+        async move {
+            let page = repo.get_all(&params).await.unwrap();
+            let names = page
+                .items
+                .into_iter()
+                .map(|u| u.username)
+                .collect::<Vec<_>>();
+            (names, page.total)
+        }
+    };
+
+    // Por nombre sin distinción de mayúsculas, en ambos sentidos
+    let by_name = UserSearch {
+        sort: Some(UserSort::Username),
+        ..query(None, 1, 10)
+    };
+    assert_eq!(
+        list(by_name.clone()).await.0,
+        ["alice", "Bob", "carla", "dora"]
+    );
+    let desc = UserSearch {
+        order: SortOrder::Desc,
+        ..by_name.clone()
+    };
+    assert_eq!(list(desc).await.0, ["dora", "carla", "Bob", "alice"]);
+
+    // Por rol: admin antes que user, y a igualdad por id
+    let by_role = UserSearch {
+        sort: Some(UserSort::Role),
+        ..query(None, 1, 10)
+    };
+    assert_eq!(list(by_role).await.0, ["Bob", "carla", "alice", "dora"]);
+
+    // El total cuenta todas las coincidencias, no solo la página
+    let page = UserSearch {
+        sort: Some(UserSort::Username),
+        ..query(None, 2, 3)
+    };
+    assert_eq!(list(page).await, (vec!["dora".to_string()], 4));
+    let filtered = UserSearch {
+        role: Some(Role::User),
+        ..query(Some("a"), 1, 1)
+    };
+    assert_eq!(list(filtered).await, (vec!["carla".to_string()], 3));
+
+    // Estado y rango de fechas de alta
+    let suspended = UserSearch {
+        status: Some(UserStatus::Suspended),
+        ..query(None, 1, 10)
+    };
+    assert_eq!(list(suspended).await.1, 0);
+    let active = UserSearch {
+        status: Some(UserStatus::Active),
+        created_to: Some(far_future()),
+        ..query(None, 1, 10)
+    };
+    assert_eq!(list(active).await.1, 4);
+    let future = UserSearch {
+        created_from: Some(far_future()),
+        ..query(None, 1, 10)
+    };
+    assert_eq!(list(future).await, (vec![], 0));
+
+    // La búsqueda de texto aplica los mismos filtros y orden
+    let search = UserSearch {
+        sort: Some(UserSort::Username),
+        order: SortOrder::Desc,
+        role: Some(Role::User),
+        ..query(Some("equipo"), 1, 10)
+    };
+    let found = repo.search(&search).await.unwrap();
+    let names = found
+        .items
+        .iter()
+        .map(|h| h.user.username.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["dora", "carla", "alice"]);
+    assert_eq!(found.total, 3);
+}
+
+async fn keyset_paging(repo: DynUserRepository) {
+    for name in ["u1", "u2", "u3"] {
+        repo.create_user(name, "hash").await.unwrap();
+    }
+    let key = |u: &backend::core::models::user::User| Keyset {
+        at: u.created_at,
+        id: u.id,
+    };
+    let names = |users: &[backend::core::models::user::User]| {
+        users.iter().map(|u| u.username.clone()).collect::<Vec<_>>()
+    };
+
+    // Una inserción entre páginas no duplica ni salta filas
+    let first = repo.get_all_after(&query(None, 1, 2), None).await.unwrap();
+    assert_eq!(names(&first), ["u1", "u2"]);
+    repo.create_user("u4", "hash").await.unwrap();
+    let rest = repo
+        .get_all_after(&query(None, 1, 10), Some(&key(&first[1])))
+        .await
+        .unwrap();
+    assert_eq!(names(&rest), ["u3", "u4"]);
+
+    // Descendente, con filtros; `page` no cuenta
+    let desc = UserSearch {
+        order: SortOrder::Desc,
+        ..query(Some("u"), 7, 2)
+    };
+    let first = repo.get_all_after(&desc, None).await.unwrap();
+    assert_eq!(names(&first), ["u4", "u3"]);
+    let rest = repo
+        .get_all_after(&desc, Some(&key(&first[1])))
+        .await
+        .unwrap();
+    assert_eq!(names(&rest), ["u2", "u1"]);
+    assert!(repo
+        .get_all_after(&desc, Some(&key(&rest[1])))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Bitácora: de la más reciente a la más antigua
+    for i in 0..3 {
+        repo.record_audit("admin", "DELETE_USER", &format!("t{}", i))
+            .await
+            .unwrap();
+    }
+    let logs = repo.get_audit_logs_after(None, 2).await.unwrap();
+    let targets = |logs: &[backend::core::models::user::AuditLog]| {
+        logs.iter().map(|l| l.target.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(targets(&logs), ["t2", "t1"]);
+    let after = Keyset {
+        at: logs[1].timestamp,
+        id: logs[1].id,
+    };
+    let logs = repo.get_audit_logs_after(Some(&after), 2).await.unwrap();
+    assert_eq!(targets(&logs), ["t0"]);
+
+    // Eventos de seguridad: igual que la bitácora
+    for path in ["/p0", "/p1", "/p2"] {
+        repo.record_security_event("token_missing", "GET", path)
+            .await
+            .unwrap();
+    }
+    let events = repo.get_security_events_after(None, 2).await.unwrap();
+    let paths = |events: &[backend::core::models::user::SecurityEvent]| {
+        events.iter().map(|e| e.path.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(paths(&events), ["/p2", "/p1"]);
+    let after = Keyset {
+        at: events[1].timestamp,
+        id: events[1].id,
+    };
+    let events = repo
+        .get_security_events_after(Some(&after), 2)
+        .await
+        .unwrap();
+    assert_eq!(paths(&events), ["/p0"]);
+}
+
+async fn token_revocation(repo: DynUserRepository) {
+    let future = chrono::Utc::now().timestamp() + 3600;
+
+    assert!(!repo.is_token_revoked("jti-1").await.unwrap());
+    repo.revoke_token("jti-1", future).await.unwrap();
+    assert!(repo.is_token_revoked("jti-1").await.unwrap());
+
+    // Revocar dos veces es idempotente
+    repo.revoke_token("jti-1", future).await.unwrap();
+    assert!(repo.is_token_revoked("jti-1").await.unwrap());
+
+    // Los tokens ya caducados se purgan en la siguiente revocación
+    repo.revoke_token("jti-viejo", 1).await.unwrap();
+    repo.revoke_token("jti-2", future).await.unwrap();
+    assert!(!repo.is_token_revoked("jti-viejo").await.unwrap());
+    assert!(repo.is_token_revoked("jti-2").await.unwrap());
+}
+
+async fn audit_archive_flow(repo: DynUserRepository) {
+    for i in 0..3 {
+        repo.record_audit("admin", "DELETE_USER", &format!("u{}", i))
+            .await
+            .unwrap();
+    }
+
+    // Ascendente por id y respetando el límite
+    let batch = repo.get_audit_logs_before(far_future(), 2).await.unwrap();
+    assert_eq!(batch.len(), 2);
+    assert!(batch[0].id < batch[1].id);
+    assert_eq!(batch[0].target, "u0");
+
+    // Nada es anterior al inicio de los tiempos
+    assert!(repo
+        .get_audit_logs_before(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let archive = NewAuditArchive {
+        kind: ArchiveKind::AuditLogs,
+        file_name: "audit-test.ndjson.gz".to_string(),
+        entries: 2,
+        first_log_id: batch[0].id,
+        last_log_id: batch[1].id,
+        last_hash: "abc".to_string(),
+        sha256: "def".to_string(),
+    };
+    let stored = repo
+        .commit_audit_archive(archive, far_future())
+        .await
+        .unwrap();
+    assert_eq!(stored.kind, ArchiveKind::AuditLogs);
+    assert_eq!(stored.file_name, "audit-test.ndjson.gz");
+    assert_eq!(stored.entries, 2);
+    assert_eq!(stored.last_hash, "abc");
+
+    // Quedan el log no archivado y la entrada del propio archivado
+    let logs = repo.get_audit_logs().await.unwrap();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].admin_username, SYSTEM_ACTOR);
+    assert_eq!(logs[0].action, "ARCHIVE_AUDIT_LOGS");
+    assert_eq!(logs[0].target, "audit-test.ndjson.gz");
+    assert_eq!(logs[1].target, "u2");
+
+    let archives = repo.get_audit_archives().await.unwrap();
+    assert_eq!(archives.len(), 1);
+    assert_eq!(archives[0].id, stored.id);
+
+    // El mismo fichero no puede registrarse dos veces
+    let duplicate = NewAuditArchive {
+        kind: ArchiveKind::SecurityEvents,
+        file_name: "audit-test.ndjson.gz".to_string(),
+        entries: 1,
+        first_log_id: logs[1].id,
+        last_log_id: logs[1].id,
+        last_hash: "x".to_string(),
+        sha256: "y".to_string(),
+    };
+    let err = repo
+        .commit_audit_archive(duplicate, far_future())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AppError::Conflict(key) if key == "audit.archive_exists"),
+        "llegó {:?}",
+        err
+    );
+    assert_eq!(repo.get_audit_logs().await.unwrap().len(), 2);
+}
+
+async fn security_event_archive_flow(repo: DynUserRepository) {
+    for (reason, path) in [
+        ("token_missing", "/api/v1/dashboard"),
+        ("token_expired", "/api/v1/me"),
+        ("insufficient_role", "/api/v1/users/7"),
+    ] {
+        repo.record_security_event(reason, "GET", path)
+            .await
+            .unwrap();
+    }
+
+    // Ascendente por id y respetando el límite
+    let events = repo
+        .get_security_events_before(far_future(), 2)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events[0].id < events[1].id);
+    assert_eq!(
+        (events[0].reason.as_str(), events[0].method.as_str()),
+        ("token_missing", "GET")
+    );
+    assert_eq!(events[1].path, "/api/v1/me");
+    assert_recent(events[0].timestamp);
+    assert!(repo
+        .get_security_events_before(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let archive = NewAuditArchive {
+        kind: ArchiveKind::SecurityEvents,
+        file_name: "security-test.ndjson.gz".to_string(),
+        entries: 2,
+        first_log_id: events[0].id,
+        last_log_id: events[1].id,
+        last_hash: "abc".to_string(),
+        sha256: "def".to_string(),
+    };
+    let stored = repo
+        .commit_audit_archive(archive, far_future())
+        .await
+        .unwrap();
+    assert_eq!(stored.kind, ArchiveKind::SecurityEvents);
+
+    // Solo se purgan los eventos archivados; la auditoría registra la operación
+    let remaining = repo
+        .get_security_events_before(far_future(), 10)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].reason, "insufficient_role");
+    let logs = repo.get_audit_logs().await.unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].admin_username, SYSTEM_ACTOR);
+    assert_eq!(logs[0].action, "ARCHIVE_SECURITY_EVENTS");
+    assert_eq!(logs[0].target, "security-test.ndjson.gz");
+    assert_eq!(
+        repo.get_audit_archives().await.unwrap()[0].kind,
+        ArchiveKind::SecurityEvents
+    );
+}
+
+async fn erasure_flow(repo: DynUserRepository) {
+    let ana = repo.create_user("ana", "hash").await.unwrap();
+    repo.create_user("anabel", "hash").await.unwrap();
+    for (actor, target) in [
+        ("root", "ana"),
+        ("root", "ana:admin"),
+        ("ana", "anabel"),
+        ("root", "anabel:admin"),
+    ] {
+        repo.record_audit(actor, "ACCION", target).await.unwrap();
+    }
+
+    // 1. Solo las entradas cuyo objetivo es `ana` (no `anabel`), recientes primero
+    let targets: Vec<_> = repo
+        .get_audit_logs_for("ana")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|log| log.target)
+        .collect();
+    assert_eq!(targets, ["ana:admin", "ana"]);
+
+    // 2. Una pendiente por usuario; vence según `scheduled_for`
+    let scheduled_for = Utc::now() + chrono::Duration::days(7);
+    let mut uow = repo.begin().await.unwrap();
+    let request = uow
+        .request_erasure(ana.id, "anon-1", scheduled_for)
+        .await
+        .unwrap();
+    uow.commit().await.unwrap();
+    assert_eq!(request.status, ErasureStatus::Pending);
+    assert_recent(request.requested_at);
+    assert_eq!(request.scheduled_for.timestamp(), scheduled_for.timestamp());
+    let mut uow = repo.begin().await.unwrap();
+    assert!(matches!(
+        uow.request_erasure(ana.id, "anon-2", scheduled_for).await,
+        Err(AppError::Conflict(_))
+    ));
+    drop(uow);
+    let pending = repo.get_pending_erasure(ana.id).await.unwrap().unwrap();
+    assert_eq!(pending.pseudonym, "anon-1");
+    assert!(repo.get_due_erasures(Utc::now()).await.unwrap().is_empty());
+    let due = repo.get_due_erasures(far_future()).await.unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].user_id, ana.id);
+
+    // 3. Seudonimizar: actor y objetivo (también `ana:...`), `anabel` intacta
+    let mut uow = repo.begin().await.unwrap();
+    assert_eq!(uow.pseudonymize_audit("ana", "anon-1").await.unwrap(), 3);
+    let resolved = uow
+        .resolve_erasure(ana.id, ErasureStatus::Completed, Utc::now())
+        .await
+        .unwrap()
+        .unwrap();
+    uow.commit().await.unwrap();
+    assert_eq!(resolved.status, ErasureStatus::Completed);
+    assert_recent(resolved.resolved_at.unwrap());
+    let logs: Vec<_> = repo
+        .get_audit_logs()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|log| (log.admin_username, log.target))
+        .collect();
+    assert_eq!(
+        logs,
+        [
+            ("root".to_string(), "anabel:admin".to_string()),
+            ("anon-1".to_string(), "anabel".to_string()),
+            ("root".to_string(), "anon-1:admin".to_string()),
+            ("root".to_string(), "anon-1".to_string()),
+        ]
+    );
+
+    // 4. Cerrada, ya no está pendiente ni se vuelve a resolver
+    assert!(repo.get_pending_erasure(ana.id).await.unwrap().is_none());
+    assert!(repo
+        .get_due_erasures(far_future())
+        .await
+        .unwrap()
+        .is_empty());
+    let mut uow = repo.begin().await.unwrap();
+    assert!(uow
+        .resolve_erasure(ana.id, ErasureStatus::Cancelled, Utc::now())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn versioned_updates(repo: DynUserRepository) {
+    let ana = repo.create_user("ana", "hash").await.unwrap();
+    assert_eq!(ana.version, 1);
+
+    // 1. Las ediciones del perfil incrementan la versión; el idioma y el
+    //    registro de logins no (no invalidan el ETag que tenga un admin)
+    repo.set_locale("ana", Some(Locale::En)).await.unwrap();
+    repo.record_login(ana.id, Utc::now()).await.unwrap();
+    let fetched = repo.get_by_id(ana.id).await.unwrap().unwrap();
+    assert_eq!(fetched.version, 1);
+    repo.set_profile("ana", Some("Ana"), Some("ana@example.com"))
+        .await
+        .unwrap();
+    let fetched = repo.get_by_id(ana.id).await.unwrap().unwrap();
+    assert_eq!(fetched.version, 2);
+
+    // 2. Solo se bloquea en la versión actual
+    let mut uow = repo.begin().await.unwrap();
+    assert!(!uow.lock_version(ana.id, 1).await.unwrap());
+    assert!(uow.lock_version(ana.id, 2).await.unwrap());
+    assert!(!uow.lock_version(9999, 1).await.unwrap());
+
+    // 3. Una sola escritura: aplica lo presente, "" borra y la versión sube una vez
+    let changes = UpdateUserRequest {
+        role: Some(Role::Admin),
+        display_name: Some("  Ana María ".into()),
+        email: Some(String::new()),
+        ..Default::default()
+    };
+    let updated = uow.update_user(ana.id, &changes).await.unwrap().unwrap();
+    uow.commit().await.unwrap();
+    assert_eq!(updated.role, Role::Admin);
+    assert_eq!(updated.status, UserStatus::Active);
+    assert_eq!(updated.display_name.as_deref(), Some("Ana María"));
+    assert_eq!(updated.email, None);
+    assert_eq!(updated.locale, Some(Locale::En));
+    assert_eq!(updated.version, 3);
+    let fetched = repo.get_by_id(ana.id).await.unwrap().unwrap();
+    assert_eq!(fetched.version, 3);
+    assert_eq!(fetched.display_name.as_deref(), Some("Ana María"));
+
+    let mut uow = repo.begin().await.unwrap();
+    let suspend = UpdateUserRequest {
+        status: Some(UserStatus::Suspended),
+        ..Default::default()
+    };
+    assert!(uow.update_user(9999, &suspend).await.unwrap().is_none());
+    let updated = uow.update_user(ana.id, &suspend).await.unwrap().unwrap();
+    assert_eq!(updated.status, UserStatus::Suspended);
+    assert_eq!(updated.role, Role::Admin);
+    assert_eq!(updated.display_name.as_deref(), Some("Ana María"));
+
+    // 4. La contraseña va en la misma escritura; la unidad de trabajo ve lo suyo
+    let demote = UpdateUserRequest {
+        role: Some(Role::User),
+        ..Default::default()
+    };
+    let updated = uow
+        .update_user_with_password(ana.id, &demote, Some("otro-hash"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.password_hash, "otro-hash");
+    assert_eq!(updated.version, 5);
+    let staged = uow.get_by_username("ana").await.unwrap().unwrap();
+    assert_eq!((staged.role, staged.version), (Role::User, 5));
+    assert!(uow.get_by_username("nadie").await.unwrap().is_none());
+}
+
+async fn idempotency_keys(repo: DynUserRepository) {
+    let now = Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap();
+    let lock = now + chrono::Duration::minutes(5);
+    let scope = "POST /api/v1/users";
+
+    // 1. La primera reserva gana; la segunda ve la petición en curso
+    assert!(repo
+        .reserve_idempotency_key("k1", scope, "huella", now, lock)
+        .await
+        .unwrap()
+        .is_none());
+    let pending = repo
+        .reserve_idempotency_key("k1", scope, "otra", now, lock)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(pending.fingerprint, "huella");
+    assert!(!pending.is_complete());
+    // La misma clave en otra ruta es independiente
+    assert!(repo
+        .reserve_idempotency_key("k1", "POST /api/v1/login", "huella", now, lock)
+        .await
+        .unwrap()
+        .is_none());
+
+    // 2. Con la respuesta guardada se devuelve tal cual
+    let ttl = now + chrono::Duration::days(1);
+    let headers = "content-type: application/json\netag: \"1\"";
+    repo.complete_idempotency_key("k1", scope, 201, headers, b"{}", ttl)
+        .await
+        .unwrap();
+    let stored = repo
+        .reserve_idempotency_key("k1", scope, "huella", now, lock)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, Some(201));
+    assert_eq!(stored.headers.as_deref(), Some(headers));
+    assert_eq!(stored.body.as_deref(), Some(&b"{}"[..]));
+    assert_eq!(stored.expires_at, ttl);
+
+    // 3. Liberada, o vencida, se puede volver a reservar
+    repo.release_idempotency_key("k1", "POST /api/v1/login")
+        .await
+        .unwrap();
+    assert!(repo
+        .reserve_idempotency_key("k1", "POST /api/v1/login", "huella", now, lock)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(repo
+        .reserve_idempotency_key(
+            "k1",
+            scope,
+            "nueva",
+            ttl,
+            ttl + chrono::Duration::minutes(5)
+        )
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// Genera un módulo de tests por implementación con la batería completa.
+/// Si la fábrica devuelve `None` (backend no disponible) el test no hace nada.
+macro_rules! conformance_suite {
+    ($name:ident, $factory:ident $(, #[$attr:meta])*) => {
+        mod $name {
+            use super::*;
+
+            async fn repo() -> DynUserRepository {
+                $factory().await
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn create_and_fetch() {
+                super::create_and_fetch(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn duplicate_username_conflicts() {
+                super::duplicate_username_conflicts(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn search_and_paging() {
+                super::search_and_paging(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn sort_filter_and_total() {
+                super::sort_filter_and_total(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn keyset_paging() {
+                super::keyset_paging(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn lookup_and_delete_by_id() {
+                super::lookup_and_delete_by_id(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn audit_log_order() {
+                super::audit_log_order(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn unit_of_work_commits_together() {
+                super::unit_of_work_commits_together(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn unit_of_work_rolls_back_when_dropped() {
+                super::unit_of_work_rolls_back_when_dropped(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn locale_roundtrip() {
+                super::locale_roundtrip(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn profile_search() {
+                super::profile_search(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn token_revocation() {
+                super::token_revocation(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn audit_archive_flow() {
+                super::audit_archive_flow(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn security_event_archive_flow() {
+                super::security_event_archive_flow(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn erasure_flow() {
+                super::erasure_flow(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn versioned_updates() {
+                super::versioned_updates(repo().await).await;
+            }
+
+            #[tokio::test]
+            $(#[$attr])*
+            async fn idempotency_keys() {
+                super::idempotency_keys(repo().await).await;
+            }
+        }
+    };
+}
+
+conformance_suite!(sqlite, sqlite_repo);
+conformance_suite!(memory, memory_repo);
+#[cfg(feature = "postgres")]
+conformance_suite!(postgres, postgres_repo, #[ignore = "requiere TEST_DATABASE_URL"]);
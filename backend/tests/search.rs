@@ -0,0 +1,414 @@
+mod common;
+
+use axum::{body::Body, http::StatusCode, Router};
+use backend::{
+    core::{models::user::Role, repository::DynUserRepository},
+    create_app,
+    data::user_repository::SqliteRepository,
+    state::AppState,
+};
+use common::{call, cookie, create_admin, json, memory_pool, request, send};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+async fn set_profile(app: &Router, username: &str, profile: Value) -> StatusCode {
+    let req = request("PUT", "/api/v1/me/profile")
+        .header("content-type", "application/json")
+        .header("cookie", cookie(username, Role::User))
+        .body(Body::from(profile.to_string()))
+        .unwrap();
+    call(app, req).await.0
+}
+
+async fn search(app: &Router, q: &str) -> Vec<(String, String)> {
+    let req = request("GET", &format!("/api/v1/users?q={}", q))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = call(app, req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| {
+            (
+                hit["username"].as_str().unwrap().to_string(),
+                hit["highlight"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_fts_ranking_prefix_and_highlight() {
+    // 1. Setup: SQLite con el índice FTS5
+    let pool = memory_pool().await;
+    let users = SqliteRepository::new(pool);
+    let app = create_app(AppState::builder(users).build());
+    for name in ["marta", "anabel", "juana", "ana"] {
+        let req = request("POST", "/api/v1/users")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": name, "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        assert_eq!(call(&app, req).await.0, StatusCode::CREATED);
+    }
+
+    // 2. El perfil se edita y valida
+    let status = set_profile(&app, "marta", json!({ "display_name": "Ana <Pérez>" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = set_profile(&app, "juana", json!({ "email": "no-es-un-email" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // 3. Prefijo por palabra (no subcadena: "juana" no entra), el username pesa
+    //    más que el nombre visible, y el resaltado llega escapado
+    let hits = search(&app, "ana").await;
+    assert_eq!(
+        hits,
+        [
+            ("ana".to_string(), "<mark>ana</mark>".to_string()),
+            ("anabel".to_string(), "<mark>anabel</mark>".to_string()),
+            (
+                "marta".to_string(),
+                "<mark>Ana</mark> &lt;Pérez&gt;".to_string()
+            ),
+        ]
+    );
+
+    // 4. Sin acentos ni mayúsculas, y los triggers siguen los cambios del perfil
+    assert_eq!(search(&app, "PEREZ").await.len(), 1);
+    let status = set_profile(&app, "marta", json!({ "display_name": null })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(search(&app, "perez").await.is_empty());
+
+    // 5. Sin palabras indexables se recurre a LIKE
+    let req = request("GET", "/api/v1/users?q=%40%40")
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_listing_sort_filters_and_pagination_headers() {
+    let pool = memory_pool().await;
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
+    for name in ["carla", "bea", "ana"] {
+        let req = request("POST", "/api/v1/users")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": name, "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        assert_eq!(call(&app, req).await.0, StatusCode::CREATED);
+    }
+
+    // 1. Orden descendente, segunda página: total y enlaces en cabeceras
+    let uri = "/api/v1/users?sort=username&order=desc&status=active&limit=2&page=2";
+    let response = send(&app, request("GET", uri).body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-total-count"], "3");
+    let link = response.headers()["link"].to_str().unwrap().to_string();
+    assert!(
+        link.contains("limit=2&page=1>; rel=\"prev\"") && !link.contains("rel=\"next\""),
+        "{}",
+        link
+    );
+    let body = json(response).await;
+    assert_eq!(body[0]["username"], "ana");
+    assert_eq!(body[0]["status"], "Active");
+
+    // El frontend (otro origen) tiene que poder leer esas cabeceras
+    let req = request("GET", uri)
+        .header("origin", "http://localhost:4321")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&app, req).await;
+    let exposed = response.headers()["access-control-expose-headers"]
+        .to_str()
+        .unwrap()
+        .to_lowercase();
+    assert!(
+        exposed.contains("x-total-count") && exposed.contains("link"),
+        "{}",
+        exposed
+    );
+
+    // 2. Límites de paginación y parámetros desconocidos
+    for uri in [
+        "/api/v1/users?page=0",
+        "/api/v1/users?limit=101",
+        "/api/v1/users?limit=0",
+        "/api/v1/users?sort=password",
+        "/api/v1/users?created_from=ayer",
+    ] {
+        let (status, body) = call(&app, request("GET", uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", uri, body);
+    }
+
+    // Un parámetro ilegible se explica en el idioma pedido, no con el texto de serde
+    for uri in ["/api/v1/users?page=abc", "/api/v1/users?sort=bogus"] {
+        let req = request("GET", uri)
+            .header("accept-language", "es")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["detail"],
+            "Algún parámetro de la URL no es válido (tipo o valor desconocido)"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_cursor_pagination_is_stable_under_inserts() {
+    let pool = memory_pool().await;
+    let app = create_app(AppState::builder(SqliteRepository::new(pool)).build());
+    let create = |name: &'static str| {
+        let app = app.clone();
+        async move {
+            let req = request("POST", "/api/v1/users")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({ "username": name, "password": "password123" }).to_string(),
+                ))
+                .unwrap();
+            assert_eq!(call(&app, req).await.0, StatusCode::CREATED);
+        }
+    };
+    let get = |uri: String| {
+        let app = app.clone();
+        async move { call(&app, request("GET", &uri).body(Body::empty()).unwrap()).await }
+    };
+    for name in ["uno", "dos", "tres"] {
+        create(name).await;
+    }
+
+    // 1. `after` vacío abre el modo cursor con el sobre { items, next_cursor }
+    let (status, body) = get("/api/v1/users?after=&limit=2".to_string()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["items"][0]["username"], "uno");
+    assert_eq!(body["items"][1]["username"], "dos");
+    let cursor = body["next_cursor"].as_str().unwrap().to_string();
+
+    // 2. Un alta entre páginas no desplaza la siguiente
+    create("cuatro").await;
+    let (status, body) = get(format!("/api/v1/users?after={}&limit=2", cursor)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let names: Vec<_> = body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["username"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["tres", "cuatro"]);
+    assert!(body["next_cursor"].is_null());
+
+    // 3. Cursores alterados o de otro listado, y órdenes incompatibles
+    let mut forged = cursor.clone();
+    forged.insert(0, 'A');
+    for uri in [
+        format!("/api/v1/users?after={}", forged),
+        "/api/v1/users?after=basura".to_string(),
+        "/api/v1/users?after=&sort=username".to_string(),
+    ] {
+        let (status, body) = get(uri.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", uri, body);
+    }
+}
+
+#[tokio::test]
+async fn test_security_events_are_listed_by_cursor() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool));
+    create_admin(&users, "root").await;
+    users.create_user("pepe", "hash").await.unwrap();
+    let app = create_app(AppState::builder_shared(users).build());
+    let get = |uri: String, session: Option<String>| {
+        let app = app.clone();
+        async move {
+            let mut req = request("GET", &uri);
+            if let Some(session) = session {
+                req = req.header("cookie", session);
+            }
+            call(&app, req.body(Body::empty()).unwrap()).await
+        }
+    };
+    let admin = || Some(cookie("root", Role::Admin));
+
+    // 1. Tres accesos rechazados por el guard
+    for path in [
+        "/api/v1/dashboard",
+        "/api/v1/audit-logs",
+        "/api/v1/dashboard?x=1",
+    ] {
+        let (status, _) = get(path.to_string(), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // 2. Del más reciente al más antiguo, con cursor (sin `after` es la primera página)
+    let (status, body) = get("/api/v1/security-events?limit=2".to_string(), admin()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["items"][0]["reason"], "token_missing");
+    assert_eq!(body["items"][0]["path"], "/api/v1/dashboard");
+    assert_eq!(body["items"][1]["path"], "/api/v1/audit-logs");
+    let cursor = body["next_cursor"].as_str().unwrap().to_string();
+    let (_, body) = get(
+        format!("/api/v1/security-events?after={}&limit=2", cursor),
+        admin(),
+    )
+    .await;
+    assert_eq!(body["items"].as_array().unwrap().len(), 1);
+    assert!(body["next_cursor"].is_null());
+
+    // 3. Un cursor de otro listado no vale
+    let (_, body) = get("/api/v1/users?after=&limit=1".to_string(), None).await;
+    let users_cursor = body["next_cursor"].as_str().unwrap().to_string();
+    let (status, _) = get(
+        format!("/api/v1/security-events?after={}", users_cursor),
+        admin(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // 4. Solo para admins (y el rechazo también queda registrado)
+    let (status, _) = get(
+        "/api/v1/security-events".to_string(),
+        Some(cookie("pepe", Role::User)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, body) = get("/api/v1/security-events?limit=1".to_string(), admin()).await;
+    assert_eq!(body["items"][0]["reason"], "insufficient_role");
+    assert_eq!(body["items"][0]["path"], "/api/v1/security-events");
+}
+
+#[tokio::test]
+async fn test_sparse_fields_and_includes() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool));
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+    for uri in ["/api/v1/users", "/api/v1/login"] {
+        let req = request("POST", uri)
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": "ana", "password": "password123" }).to_string(),
+            ))
+            .unwrap();
+        assert!(call(&app, req).await.0.is_success());
+    }
+    create_admin(&users, "root").await;
+
+    // 1. Solo los campos pedidos, más la expansión del último login (admins)
+    let req = request("GET", "/api/v1/users?fields=id,username&include=last_login")
+        .header("cookie", cookie("root", Role::Admin))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let user = body[0].as_object().unwrap();
+    let mut keys: Vec<_> = user.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(keys, ["id", "last_login", "username"]);
+    assert!(user["last_login"].is_string());
+
+    // 2. Lo mismo en modo cursor; sin `include`, sin expansión
+    let req = request("GET", "/api/v1/users?after=&fields=username")
+        .body(Body::empty())
+        .unwrap();
+    let (_, body) = call(&app, req).await;
+    assert_eq!(body["items"][0], json!({ "username": "ana" }));
+
+    // 3. Campos desconocidos: 400 con los permitidos
+    let req = request(
+        "GET",
+        "/api/v1/users?fields=id,password_hash&include=memberships",
+    )
+    .body(Body::empty())
+    .unwrap();
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["errors"]["fields"][0]["params"]["field"],
+        "password_hash"
+    );
+    assert!(body["errors"]["fields"][0]["params"]["allowed"]
+        .as_str()
+        .unwrap()
+        .contains("username"));
+    assert_eq!(
+        body["errors"]["include"][0]["params"]["allowed"],
+        "last_login"
+    );
+
+    // 4. Sin sesión de admin, pedir el email o el último login es 403
+    for uri in [
+        "/api/v1/users?include=last_login",
+        "/api/v1/users?fields=username,email",
+        "/api/v1/users?after=&include=last_login",
+    ] {
+        let req = request("GET", uri)
+            .header("cookie", cookie("ana", Role::User))
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = call(&app, req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}: {}", uri, body);
+        assert_eq!(body["code"], "forbidden");
+    }
+}
+
+#[tokio::test]
+async fn test_email_is_only_visible_and_searchable_for_admins() {
+    let pool = memory_pool().await;
+    let users: DynUserRepository = Arc::new(SqliteRepository::new(pool));
+    let app = create_app(AppState::builder_shared(users.clone()).build());
+    let req = request("POST", "/api/v1/users")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "username": "ana", "password": "password123" }).to_string(),
+        ))
+        .unwrap();
+    assert_eq!(call(&app, req).await.0, StatusCode::CREATED);
+    let status = set_profile(&app, "ana", json!({ "email": "ana@corp.io" })).await;
+    assert_eq!(status, StatusCode::OK);
+    create_admin(&users, "root").await;
+
+    let list = |uri: &str, cookie: Option<String>| {
+        let mut req = request("GET", uri);
+        if let Some(cookie) = cookie {
+            req = req.header("cookie", cookie);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        let app = app.clone();
+        async move {
+            let (status, body) = call(&app, req).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            body
+        }
+    };
+
+    // 1. Anónimo o usuario normal: sin email en la respuesta ni en la búsqueda
+    //    (FTS con "corp" y LIKE con "@")
+    for cookie in [None, Some(cookie("ana", Role::User))] {
+        let body = list("/api/v1/users", cookie.clone()).await;
+        assert!(body[0].get("email").is_none(), "{}", body);
+        assert_eq!(body[0]["username"], "ana");
+        for uri in ["/api/v1/users?q=corp", "/api/v1/users?q=%40"] {
+            let body = list(uri, cookie.clone()).await;
+            assert!(body.as_array().unwrap().is_empty(), "{}: {}", uri, body);
+        }
+        let body = list("/api/v1/users?after=&q=%40", cookie).await;
+        assert!(body["items"].as_array().unwrap().is_empty(), "{}", body);
+    }
+
+    // 2. Admin: lo ve y lo encuentra
+    let admin = || Some(cookie("root", Role::Admin));
+    let body = list("/api/v1/users", admin()).await;
+    assert_eq!(body[0]["email"], "ana@corp.io");
+    let body = list("/api/v1/users?q=corp", admin()).await;
+    assert_eq!(body[0]["highlight"], "ana@<mark>corp</mark>.io");
+    let body = list("/api/v1/users?q=%40", admin()).await;
+    assert_eq!(body[0]["username"], "ana");
+}